@@ -4,6 +4,9 @@ extern crate rand;
 
 use itertools::Itertools;
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
 use std::str;
 
 use crate::bellman_ce::{
@@ -11,6 +14,8 @@ use crate::bellman_ce::{
     SynthesisError, Variable,
 };
 
+use crate::errors::Result as EigenResult;
+use crate::reader::{detect_r1cs_format, load_r1cs, load_r1cs_streaming, R1CSFormat};
 use crate::utils::repr_to_big;
 
 #[derive(Serialize, Deserialize)]
@@ -97,66 +102,173 @@ impl<E: ScalarEngine> CircomCircuit<E> {
 impl<E: Engine> Circuit<E> for CircomCircuit<E> {
     //noinspection RsBorrowChecker
     fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-        let witness = &self.witness;
-        let wire_mapping = &self.wire_mapping;
-        for i in 1..self.r1cs.num_inputs {
-            cs.alloc_input(
-                || format!("variable {}", i),
-                || {
-                    Ok(match witness {
-                        None => E::Fr::from_str(&format!("alloc input {} error", i)).unwrap(),
-                        Some(w) => match wire_mapping {
-                            None => w[i],
-                            Some(m) => w[m[i]],
-                        },
-                    })
-                },
-            )?;
-        }
-        for i in 0..self.r1cs.num_aux {
-            cs.alloc(
-                || format!("aux {}", i + self.aux_offset),
-                || {
-                    Ok(match witness {
-                        None => {
-                            E::Fr::from_str(&format!("alloc aux {} error", i + self.aux_offset))
-                                .unwrap()
-                        }
-                        Some(w) => match wire_mapping {
-                            None => w[i + self.r1cs.num_inputs],
-                            Some(m) => w[m[i + self.r1cs.num_inputs]],
-                        },
-                    })
-                },
-            )?;
+        alloc_wires(
+            cs,
+            &self.r1cs,
+            &self.witness,
+            &self.wire_mapping,
+            self.aux_offset,
+        )?;
+        for (i, constraint) in self.r1cs.constraints.iter().enumerate() {
+            enforce_constraint(cs, &self.r1cs, self.aux_offset, i, constraint);
         }
+        Ok(())
+    }
+}
+
+/// A circom circuit whose constraints are read from the binary `r1cs_file` on every synthesis,
+/// instead of being held in memory, as the plonk transpiler synthesizes it several times. The
+/// constraints of a json r1cs are held in `r1cs`, with no `r1cs_file`.
+#[derive(Clone, Debug)]
+pub struct StreamingCircomCircuit<E: ScalarEngine> {
+    pub r1cs: R1CS<E>,
+    pub r1cs_file: Option<PathBuf>,
+    pub num_constraints: usize,
+    pub witness: Option<Vec<E::Fr>>,
+    pub wire_mapping: Option<Vec<usize>>,
+    pub aux_offset: usize,
+}
 
-        let make_index = |index| {
-            if index < self.r1cs.num_inputs {
-                Index::Input(index)
-            } else {
-                Index::Aux(index - self.r1cs.num_inputs + self.aux_offset)
+impl<E: ScalarEngine> StreamingCircomCircuit<E> {
+    /// read the header of the binary `r1cs_file`, the constraints are left in it
+    pub fn load(
+        r1cs_file: &str,
+        witness: Option<Vec<E::Fr>>,
+        aux_offset: usize,
+    ) -> EigenResult<Self> {
+        let mut reader = BufReader::new(File::open(r1cs_file)?);
+        let (r1cs, r1cs_file, num_constraints) = match detect_r1cs_format(&mut reader)? {
+            R1CSFormat::Bin => {
+                let (r1cs, _wire_mapping, constraints) = load_r1cs_streaming::<E>(r1cs_file)?;
+                let num_constraints = constraints.len();
+                (r1cs, Some(PathBuf::from(r1cs_file)), num_constraints)
+            }
+            R1CSFormat::Json => {
+                let r1cs = load_r1cs::<E>(r1cs_file)?;
+                let num_constraints = r1cs.constraints.len();
+                (r1cs, None, num_constraints)
             }
         };
-        let make_lc = |lc_data: Vec<(usize, E::Fr)>| {
-            lc_data.iter().fold(
-                LinearCombination::<E>::zero(),
-                |lc: LinearCombination<E>, (index, coeff)| {
-                    lc + (*coeff, Variable::new_unchecked(make_index(*index)))
-                },
-            )
-        };
-        for (i, constraint) in self.r1cs.constraints.iter().enumerate() {
-            // 0 * LC = 0 must be ignored
-            if !((constraint.0.is_empty() || constraint.1.is_empty()) && constraint.2.is_empty()) {
-                cs.enforce(
-                    || format!("{}", i),
-                    |_| make_lc(constraint.0.clone()),
-                    |_| make_lc(constraint.1.clone()),
-                    |_| make_lc(constraint.2.clone()),
-                );
+        Ok(Self {
+            r1cs,
+            r1cs_file,
+            num_constraints,
+            witness,
+            wire_mapping: None,
+            aux_offset,
+        })
+    }
+}
+
+impl<E: ScalarEngine> From<CircomCircuit<E>> for StreamingCircomCircuit<E> {
+    fn from(circuit: CircomCircuit<E>) -> Self {
+        Self {
+            num_constraints: circuit.r1cs.constraints.len(),
+            r1cs: circuit.r1cs,
+            r1cs_file: None,
+            witness: circuit.witness,
+            wire_mapping: circuit.wire_mapping,
+            aux_offset: circuit.aux_offset,
+        }
+    }
+}
+
+impl<E: Engine> Circuit<E> for StreamingCircomCircuit<E> {
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        alloc_wires(
+            cs,
+            &self.r1cs,
+            &self.witness,
+            &self.wire_mapping,
+            self.aux_offset,
+        )?;
+        let r1cs_file = match &self.r1cs_file {
+            Some(r1cs_file) => r1cs_file,
+            None => {
+                for (i, constraint) in self.r1cs.constraints.iter().enumerate() {
+                    enforce_constraint(cs, &self.r1cs, self.aux_offset, i, constraint);
+                }
+                return Ok(());
             }
+        };
+        let (_, _, constraints) = load_r1cs_streaming::<E>(&r1cs_file.to_string_lossy())
+            .map_err(|e| SynthesisError::from(io::Error::new(io::ErrorKind::Other, e)))?;
+        for (i, constraint) in constraints.enumerate() {
+            enforce_constraint(cs, &self.r1cs, self.aux_offset, i, &constraint?);
         }
         Ok(())
     }
 }
+
+fn alloc_wires<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    r1cs: &R1CS<E>,
+    witness: &Option<Vec<E::Fr>>,
+    wire_mapping: &Option<Vec<usize>>,
+    aux_offset: usize,
+) -> Result<(), SynthesisError> {
+    for i in 1..r1cs.num_inputs {
+        cs.alloc_input(
+            || format!("variable {}", i),
+            || {
+                Ok(match witness {
+                    None => E::Fr::from_str(&format!("alloc input {} error", i)).unwrap(),
+                    Some(w) => match wire_mapping {
+                        None => w[i],
+                        Some(m) => w[m[i]],
+                    },
+                })
+            },
+        )?;
+    }
+    for i in 0..r1cs.num_aux {
+        cs.alloc(
+            || format!("aux {}", i + aux_offset),
+            || {
+                Ok(match witness {
+                    None => {
+                        E::Fr::from_str(&format!("alloc aux {} error", i + aux_offset)).unwrap()
+                    }
+                    Some(w) => match wire_mapping {
+                        None => w[i + r1cs.num_inputs],
+                        Some(m) => w[m[i + r1cs.num_inputs]],
+                    },
+                })
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn enforce_constraint<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    r1cs: &R1CS<E>,
+    aux_offset: usize,
+    i: usize,
+    constraint: &Constraint<E>,
+) {
+    let make_index = |index| {
+        if index < r1cs.num_inputs {
+            Index::Input(index)
+        } else {
+            Index::Aux(index - r1cs.num_inputs + aux_offset)
+        }
+    };
+    let make_lc = |lc_data: &[(usize, E::Fr)]| {
+        lc_data.iter().fold(
+            LinearCombination::<E>::zero(),
+            |lc: LinearCombination<E>, (index, coeff)| {
+                lc + (*coeff, Variable::new_unchecked(make_index(*index)))
+            },
+        )
+    };
+    // 0 * LC = 0 must be ignored
+    if !((constraint.0.is_empty() || constraint.1.is_empty()) && constraint.2.is_empty()) {
+        cs.enforce(
+            || format!("{}", i),
+            |_| make_lc(&constraint.0),
+            |_| make_lc(&constraint.1),
+            |_| make_lc(&constraint.2),
+        );
+    }
+}
@@ -23,12 +23,21 @@ pub enum EigenError {
     #[error("merkle tree error`{0}`")]
     MerkleTreeError(String),
 
+    #[error("invalid input at `{path}`: {msg}")]
+    InvalidInput { path: String, msg: String },
+
+    #[error("field mismatch (expected prime {expected:?}, found {found:?})")]
+    FieldMismatch { expected: String, found: String },
+
     #[error("degree should be equal, but `{0}` != `{1}`")]
     MustEqualDegreeError(usize, usize),
 
     #[error("expression error, msg `{0}`")]
     ExpressionError(String),
 
+    #[error("pil compile error at `{location}`: {msg}")]
+    PilCompileError { location: String, msg: String },
+
     #[error("invalid op, msg `{0}`")]
     InvalidOperator(String),
 
@@ -41,12 +50,24 @@ pub enum EigenError {
     #[error("Fr::from_expr error")]
     FrankinCryptoPFDecodeError(#[from] bellman_ce::PrimeFieldDecodingError),
 
+    #[error("witness program error, `{0}`")]
+    WitnessProgramError(String),
+
     #[error("WasmRuntime error, exit `{0}`")]
     WasmerRuntimeError(#[from] wasmer::RuntimeError),
 
     #[error("WasmRuntime init error")]
     InstantiationError(#[from] wasmer::InstantiationError),
 
+    #[error("WasmRuntime compile error")]
+    WasmerCompileError(#[from] wasmer::CompileError),
+
+    #[error("Wasm module serialize error")]
+    WasmerSerializeError(#[from] wasmer::SerializeError),
+
+    #[error("Wasm module deserialize error")]
+    WasmerDeserializeError(#[from] wasmer::DeserializeError),
+
     #[error("parse bigint error")]
     ParseBigIntError(#[from] num_bigint::ParseBigIntError),
 
@@ -17,8 +17,11 @@ pub mod arch;
 pub mod circom_circuit;
 pub mod errors;
 pub mod field_gl;
+pub mod r1cs_check;
 pub mod r1cs_file;
+pub mod r1cs_optimize;
 pub mod reader;
+pub mod sym;
 pub mod witness;
 
 pub mod utils;
@@ -0,0 +1,257 @@
+//! Check a witness satisfies every constraint `<A, w> * <B, w> = <C, w>` of a R1CS, and report
+//! the unsatisfied ones with the circom signal names of their wires.
+use crate::bellman_ce::{
+    pairing::{bls12_381::Bls12, bn256::Bn256},
+    Field, PrimeField, ScalarEngine,
+};
+use crate::circom_circuit::R1CS;
+use crate::errors::{EigenError, Result};
+use crate::field_gl::GL;
+use crate::reader::{load_r1cs_from_bin, load_witness_from_file};
+use crate::sym::SymbolTable;
+use crate::utils::repr_to_big;
+use anyhow::bail;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::BufReader;
+
+/// A wire involved in an unsatisfied constraint
+#[derive(Clone, Debug, Serialize)]
+pub struct WireInfo {
+    pub wire: usize,
+    pub name: Option<String>,
+    pub value: String,
+}
+
+/// An unsatisfied constraint, with the evaluations of its linear combinations
+#[derive(Clone, Debug, Serialize)]
+pub struct UnsatisfiedConstraint {
+    pub index: usize,
+    pub a: String,
+    pub b: String,
+    pub c: String,
+    pub wires: Vec<WireInfo>,
+}
+
+impl std::fmt::Display for UnsatisfiedConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "constraint {}: A * B != C, A = {}, B = {}, C = {}",
+            self.index, self.a, self.b, self.c
+        )?;
+        for w in &self.wires {
+            writeln!(
+                f,
+                "    wire {} ({}) = {}",
+                w.wire,
+                w.name.as_deref().unwrap_or("unknown"),
+                w.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate every constraint of `r1cs` by `witness`, and return the unsatisfied ones.
+///
+/// `wire_mapping` maps a wire of the r1cs to its index in the witness, like
+/// `CircomCircuit::wire_mapping`, and `names` maps a wire to its signal name.
+pub fn check_r1cs<E: ScalarEngine>(
+    r1cs: &R1CS<E>,
+    witness: &[E::Fr],
+    wire_mapping: Option<&[usize]>,
+    names: &dyn Fn(usize) -> Option<String>,
+) -> Result<Vec<UnsatisfiedConstraint>> {
+    let value_of = |wire: usize| -> Result<E::Fr> {
+        let index = wire_mapping.map_or(Some(wire), |m| m.get(wire).copied());
+        match index.and_then(|i| witness.get(i)) {
+            Some(v) => Ok(*v),
+            None => bail!(EigenError::OutOfRangeError {
+                expected: format!("wire of a witness with length {}", witness.len()),
+                found: wire.to_string(),
+            }),
+        }
+    };
+    let eval = |lc: &[(usize, E::Fr)]| -> Result<E::Fr> {
+        let mut acc = E::Fr::zero();
+        for (wire, coeff) in lc {
+            let mut term = value_of(*wire)?;
+            term.mul_assign(coeff);
+            acc.add_assign(&term);
+        }
+        Ok(acc)
+    };
+
+    let mut unsatisfied = vec![];
+    for (index, (a, b, c)) in r1cs.constraints.iter().enumerate() {
+        let (va, vb, vc) = (eval(a)?, eval(b)?, eval(c)?);
+        let mut ab = va;
+        ab.mul_assign(&vb);
+        if ab == vc {
+            continue;
+        }
+        let wires = a
+            .iter()
+            .chain(b.iter())
+            .chain(c.iter())
+            .map(|(wire, _)| *wire)
+            .collect::<BTreeSet<_>>();
+        unsatisfied.push(UnsatisfiedConstraint {
+            index,
+            a: repr_to_big(va.into_repr()),
+            b: repr_to_big(vb.into_repr()),
+            c: repr_to_big(vc.into_repr()),
+            wires: wires
+                .into_iter()
+                .map(|wire| {
+                    Ok(WireInfo {
+                        wire,
+                        name: names(wire),
+                        value: repr_to_big(value_of(wire)?.into_repr()),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        });
+    }
+    Ok(unsatisfied)
+}
+
+/// Check the witness file against the r1cs file, naming the wires by the symbol file if given.
+///
+/// The r1cs optimized by `r1cs_optimize` is checked against the witness of the original circuit
+/// by the json `wire_mapping_file` written with it.
+pub fn check_witness(
+    curve_type: &str,
+    r1cs_file: &str,
+    wtns_file: &str,
+    sym_file: Option<&str>,
+    wire_mapping_file: Option<&str>,
+) -> Result<()> {
+    let wire_mapping = match wire_mapping_file {
+        Some(file) => Some(serde_json::from_reader::<_, Vec<usize>>(BufReader::new(
+            File::open(file)?,
+        ))?),
+        None => None,
+    };
+    let wire_mapping = wire_mapping.as_deref();
+    let unsatisfied = match curve_type {
+        "BN128" => check_witness_file::<Bn256>(r1cs_file, wtns_file, sym_file, wire_mapping)?,
+        "BLS12381" => check_witness_file::<Bls12>(r1cs_file, wtns_file, sym_file, wire_mapping)?,
+        "GL" => check_witness_file::<GL>(r1cs_file, wtns_file, sym_file, wire_mapping)?,
+        _ => bail!(EigenError::Unknown(format!(
+            "Unknown curve type: {}",
+            curve_type
+        ))),
+    };
+    if !unsatisfied.is_empty() {
+        for c in &unsatisfied {
+            log::error!("{}", c);
+        }
+        bail!(EigenError::InvalidValue(format!(
+            "{} constraints are not satisfied, the first one is {}",
+            unsatisfied.len(),
+            unsatisfied[0].index
+        )));
+    }
+    Ok(())
+}
+
+fn check_witness_file<E: ScalarEngine>(
+    r1cs_file: &str,
+    wtns_file: &str,
+    sym_file: Option<&str>,
+    wire_mapping: Option<&[usize]>,
+) -> Result<Vec<UnsatisfiedConstraint>> {
+    let (r1cs, wire_to_label) = load_r1cs_from_bin::<_, E>(BufReader::new(File::open(r1cs_file)?))?;
+    if let Some(wire_mapping) = wire_mapping {
+        if wire_mapping.len() != r1cs.num_variables {
+            bail!(EigenError::InvalidInput {
+                path: r1cs_file.to_string(),
+                msg: format!(
+                    "{} wires, but the wire mapping has {}",
+                    r1cs.num_variables,
+                    wire_mapping.len()
+                ),
+            });
+        }
+    }
+    let witness = load_witness_from_file::<E>(wtns_file)?;
+    let sym = match sym_file {
+        Some(sym_file) => SymbolTable::from_file(sym_file)?,
+        None => SymbolTable::default(),
+    };
+    let names_by_label = sym.names_by_label();
+    let names_by_wire = sym.names_by_wire();
+    let names = |wire: usize| {
+        wire_to_label
+            .get(wire)
+            .and_then(|label| names_by_label.get(&(*label as u64)))
+            .map(|name| name.to_string())
+            .or_else(|| {
+                // the symbol file names the wires of the original circuit
+                let wire = wire_mapping.map_or(Some(wire), |m| m.get(wire).copied())?;
+                names_by_wire.get(&wire).map(|names| names.join("|"))
+            })
+    };
+    // the witness calculated by circom is indexed by the wire of the original circuit
+    check_r1cs(&r1cs, &witness, wire_mapping, &names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::load_r1cs;
+
+    const CIRCUIT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.r1cs");
+
+    #[test]
+    fn check_multiplier() {
+        let r1cs = load_r1cs::<Bn256>(CIRCUIT_FILE).unwrap();
+        let fr = |x: &str| <Bn256 as ScalarEngine>::Fr::from_str(x).unwrap();
+        let names = |wire: usize| Some(format!("w{}", wire));
+
+        // 1, c, a, b
+        let witness = [fr("1"), fr("33"), fr("3"), fr("11")];
+        assert!(check_r1cs(&r1cs, &witness, None, &names)
+            .unwrap()
+            .is_empty());
+
+        let witness = [fr("1"), fr("34"), fr("3"), fr("11")];
+        let unsatisfied = check_r1cs(&r1cs, &witness, None, &names).unwrap();
+        assert_eq!(unsatisfied.len(), 1);
+        assert_eq!(unsatisfied[0].index, 0);
+        assert_eq!(unsatisfied[0].wires.len(), 3);
+        assert_eq!(unsatisfied[0].wires[0].name.as_deref(), Some("w1"));
+        assert_eq!(unsatisfied[0].wires[0].value, "34");
+
+        assert!(check_r1cs(&r1cs, &witness[..2], None, &names).is_err());
+    }
+
+    #[test]
+    fn check_optimized_witness_file() {
+        let (r1cs, wtns, wire_mapping) = (
+            "/tmp/check_multiplier.r1cs",
+            "/tmp/check_multiplier.wtns.json",
+            "/tmp/check_multiplier.wire_mapping.json",
+        );
+        crate::r1cs_optimize::optimize_r1cs_file("BN128", CIRCUIT_FILE, r1cs, wire_mapping)
+            .unwrap();
+        std::fs::write(wtns, r#"["1", "33", "3", "11"]"#).unwrap();
+        check_witness("BN128", r1cs, wtns, None, Some(wire_mapping)).unwrap();
+
+        // the mapping of another circuit
+        std::fs::write("/tmp/check_multiplier.bad_mapping.json", "[0, 1]").unwrap();
+        assert!(check_witness(
+            "BN128",
+            r1cs,
+            wtns,
+            None,
+            Some("/tmp/check_multiplier.bad_mapping.json")
+        )
+        .is_err());
+
+        std::fs::write(wtns, r#"["1", "34", "3", "11"]"#).unwrap();
+        assert!(check_witness("BN128", r1cs, wtns, None, Some(wire_mapping)).is_err());
+    }
+}
@@ -3,10 +3,10 @@
 #![allow(unused_variables, dead_code, non_snake_case)]
 use crate::bellman_ce::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
 use crate::circom_circuit::{Constraint, CustomGates, CustomGatesUses};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::{
     collections::BTreeMap,
-    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom},
+    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
 };
 
 // R1CSFile's header
@@ -71,10 +71,7 @@ fn read_header<R: Read>(mut reader: R, size: u64) -> Result<Header> {
     })
 }
 
-fn read_constraint_vec<R: Read, E: ScalarEngine>(
-    mut reader: R,
-    header: &Header,
-) -> Result<Vec<(usize, E::Fr)>> {
+fn read_constraint_vec<R: Read, E: ScalarEngine>(mut reader: R) -> Result<Vec<(usize, E::Fr)>> {
     let n_vec = reader.read_u32::<LittleEndian>()? as usize;
     let mut vec = Vec::with_capacity(n_vec);
     for _ in 0..n_vec {
@@ -82,26 +79,9 @@ fn read_constraint_vec<R: Read, E: ScalarEngine>(
             reader.read_u32::<LittleEndian>()? as usize,
             read_field::<&mut R, E>(&mut reader)?,
         ));
-        // sort by key
-        vec.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    }
-    Ok(vec)
-}
-
-fn read_constraints<R: Read, E: ScalarEngine>(
-    mut reader: R,
-    size: u64,
-    header: &Header,
-) -> Result<Vec<Constraint<E>>> {
-    // todo check section size
-    let mut vec = Vec::with_capacity(header.n_constraints as usize);
-    for _ in 0..header.n_constraints {
-        vec.push((
-            read_constraint_vec::<&mut R, E>(&mut reader, header)?,
-            read_constraint_vec::<&mut R, E>(&mut reader, header)?,
-            read_constraint_vec::<&mut R, E>(&mut reader, header)?,
-        ));
     }
+    // sort by key
+    vec.sort_by_key(|a| a.0);
     Ok(vec)
 }
 
@@ -198,11 +178,61 @@ fn read_custom_gates_uses_list<R: Read>(
     Ok(custom_gates_uses)
 }
 
-pub fn from_reader<R: Read + Seek, E: ScalarEngine>(mut reader: R) -> Result<R1CSFile<E>> {
+/// The magic number of the binary r1cs file, "r1cs"
+pub const MAGIC: [u8; 4] = [0x72, 0x31, 0x63, 0x73];
+
+/// A streaming reader of the constraints section, which yields the constraints one by one
+/// instead of loading them all into memory.
+pub struct ConstraintIter<R: Read + Seek, E: ScalarEngine> {
+    reader: R,
+    remaining: u32,
+    _marker: std::marker::PhantomData<E>,
+}
+
+impl<R: Read + Seek, E: ScalarEngine> Iterator for ConstraintIter<R, E> {
+    type Item = Result<Constraint<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut read_constraint = || -> Result<Constraint<E>> {
+            Ok((
+                read_constraint_vec::<&mut R, E>(&mut self.reader)?,
+                read_constraint_vec::<&mut R, E>(&mut self.reader)?,
+                read_constraint_vec::<&mut R, E>(&mut self.reader)?,
+            ))
+        };
+        let res = read_constraint();
+        if res.is_err() {
+            // stop at the first error, the following data can not be trusted
+            self.remaining = 0;
+        }
+        Some(res)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl<R: Read + Seek, E: ScalarEngine> ExactSizeIterator for ConstraintIter<R, E> {}
+
+pub fn from_reader<R: Read + Seek, E: ScalarEngine>(reader: R) -> Result<R1CSFile<E>> {
+    let (mut file, constraints) = from_reader_streaming::<R, E>(reader)?;
+    file.constraints = constraints.collect::<Result<Vec<_>>>()?;
+    Ok(file)
+}
+
+/// Parse all the sections but the constraints, which are left to the returned iterator, so the
+/// `constraints` of the returned file is empty.
+pub fn from_reader_streaming<R: Read + Seek, E: ScalarEngine>(
+    mut reader: R,
+) -> Result<(R1CSFile<E>, ConstraintIter<R, E>)> {
     let mut magic = [0u8; 4];
     reader.read_exact(&mut magic)?;
-    if magic != [0x72, 0x31, 0x63, 0x73] {
-        // magic = "r1cs"
+    if magic != MAGIC {
         return Err(Error::new(ErrorKind::InvalidData, "Invalid magic number"));
     }
 
@@ -217,8 +247,7 @@ pub fn from_reader<R: Read + Seek, E: ScalarEngine>(mut reader: R) -> Result<R1C
     let mut section_offsets = BTreeMap::<u32, u64>::new();
     let mut section_sizes = BTreeMap::<u32, u64>::new();
 
-    // get file offset of each section, we donot support custom gate yet, so ignore the
-    // last two sections.
+    // get file offset of each section
     for i in 0..(num_sections) {
         let section_type = reader.read_u32::<LittleEndian>()?;
         let section_size = reader.read_u64::<LittleEndian>()?;
@@ -227,9 +256,22 @@ pub fn from_reader<R: Read + Seek, E: ScalarEngine>(mut reader: R) -> Result<R1C
         section_sizes.insert(section_type, section_size);
         reader.seek(SeekFrom::Current(section_size as i64))?;
     }
+    let section = |section_type: u32| -> Result<(u64, u64)> {
+        match (
+            section_offsets.get(&section_type),
+            section_sizes.get(&section_type),
+        ) {
+            (Some(offset), Some(size)) => Ok((*offset, *size)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Missing section {}", section_type),
+            )),
+        }
+    };
 
-    reader.seek(SeekFrom::Start(*section_offsets.get(&HEADER_TYPE).unwrap()))?;
-    let mut header = read_header(&mut reader, *section_sizes.get(&HEADER_TYPE).unwrap())?;
+    let (offset, size) = section(HEADER_TYPE)?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = read_header(&mut reader, size)?;
     if section_offsets.get(&CUSTOM_GATES_USE).is_some()
         && section_offsets.get(&CUSTOM_GATES_LIST).is_some()
     {
@@ -255,55 +297,195 @@ pub fn from_reader<R: Read + Seek, E: ScalarEngine>(mut reader: R) -> Result<R1C
             "This parser only supports bn256 or GL",
         ));
     }
-    reader.seek(SeekFrom::Start(
-        *section_offsets.get(&CONSTRAINT_TYPE).unwrap(),
-    ))?;
-    let constraints = read_constraints::<&mut R, E>(
-        &mut reader,
-        *section_sizes.get(&CONSTRAINT_TYPE).unwrap(),
-        &header,
-    )?;
-
-    reader.seek(SeekFrom::Start(
-        *section_offsets.get(&WIRE2LABEL_TYPE).unwrap(),
-    ))?;
-    let wire_mapping = read_map(
-        &mut reader,
-        *section_sizes.get(&WIRE2LABEL_TYPE).unwrap(),
-        &header,
-    )?;
+
+    let (offset, size) = section(WIRE2LABEL_TYPE)?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let wire_mapping = read_map(&mut reader, size, &header)?;
+
     let mut custom_gates: Vec<CustomGates<E>> = vec![];
-    if section_offsets.get(&CUSTOM_GATES_LIST).is_some() {
-        reader.seek(SeekFrom::Start(
-            *section_offsets.get(&CUSTOM_GATES_LIST).unwrap(),
-        ))?;
-        custom_gates = read_custom_gates_list(
-            &mut reader,
-            *section_sizes.get(&CUSTOM_GATES_LIST).unwrap(),
-            &header,
-        )?;
+    if let Ok((offset, size)) = section(CUSTOM_GATES_LIST) {
+        reader.seek(SeekFrom::Start(offset))?;
+        custom_gates = read_custom_gates_list(&mut reader, size, &header)?;
     }
 
     let mut custom_gates_uses: Vec<CustomGatesUses> = vec![];
-    if section_offsets.get(&CUSTOM_GATES_USE).is_some() {
-        reader.seek(SeekFrom::Start(
-            *section_offsets.get(&CUSTOM_GATES_USE).unwrap(),
-        ))?;
-        custom_gates_uses = read_custom_gates_uses_list(
-            &mut reader,
-            *section_sizes.get(&CUSTOM_GATES_USE).unwrap(),
-            &header,
-        )?;
-    }
-
-    Ok(R1CSFile {
-        version,
-        header,
+    if let Ok((offset, size)) = section(CUSTOM_GATES_USE) {
+        reader.seek(SeekFrom::Start(offset))?;
+        custom_gates_uses = read_custom_gates_uses_list(&mut reader, size, &header)?;
+    }
+
+    // leave the reader at the constraints section
+    let (offset, size) = section(CONSTRAINT_TYPE)?;
+    reader.seek(SeekFrom::Start(offset))?;
+    let constraints = ConstraintIter {
+        reader,
+        remaining: header.n_constraints,
+        _marker: std::marker::PhantomData,
+    };
+
+    Ok((
+        R1CSFile {
+            version,
+            header,
+            constraints: vec![],
+            wire_mapping,
+            custom_gates,
+            custom_gates_uses,
+        },
         constraints,
-        wire_mapping,
-        custom_gates,
-        custom_gates_uses,
-    })
+    ))
+}
+
+fn write_field<W: Write, E: ScalarEngine>(mut writer: W, fr: &E::Fr) -> Result<()> {
+    fr.into_repr().write_le(&mut writer)
+}
+
+fn write_section_header<W: Write>(mut writer: W, section_type: u32, size: u64) -> Result<()> {
+    writer.write_u32::<LittleEndian>(section_type)?;
+    writer.write_u64::<LittleEndian>(size)
+}
+
+fn write_header<W: Write>(mut writer: W, header: &Header) -> Result<()> {
+    write_section_header(&mut writer, HEADER_TYPE, 32 + header.field_size as u64)?;
+    writer.write_u32::<LittleEndian>(header.field_size)?;
+    writer.write_all(&header.prime_size)?;
+    writer.write_u32::<LittleEndian>(header.n_wires)?;
+    writer.write_u32::<LittleEndian>(header.n_pub_out)?;
+    writer.write_u32::<LittleEndian>(header.n_pub_in)?;
+    writer.write_u32::<LittleEndian>(header.n_prv_in)?;
+    writer.write_u64::<LittleEndian>(header.n_labels)?;
+    writer.write_u32::<LittleEndian>(header.n_constraints)
+}
+
+fn write_constraint_vec<W: Write, E: ScalarEngine>(
+    mut writer: W,
+    vec: &[(usize, E::Fr)],
+) -> Result<()> {
+    let to_u32 = |n: usize, what: &str| {
+        u32::try_from(n).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} {} doesn't fit the 32 bits of the r1cs format", what, n),
+            )
+        })
+    };
+    writer.write_u32::<LittleEndian>(to_u32(vec.len(), "linear combination length")?)?;
+    for (wire, coeff) in vec {
+        writer.write_u32::<LittleEndian>(to_u32(*wire, "wire")?)?;
+        write_field::<&mut W, E>(&mut writer, coeff)?;
+    }
+    Ok(())
+}
+
+fn write_constraints<W: Write, E: ScalarEngine>(
+    mut writer: W,
+    constraints: &[Constraint<E>],
+    header: &Header,
+) -> Result<()> {
+    let lc_size = |lc: &[(usize, E::Fr)]| 4 + lc.len() as u64 * (4 + header.field_size as u64);
+    let size = constraints
+        .iter()
+        .map(|(a, b, c)| lc_size(a) + lc_size(b) + lc_size(c))
+        .sum();
+    write_section_header(&mut writer, CONSTRAINT_TYPE, size)?;
+    for (a, b, c) in constraints {
+        write_constraint_vec::<&mut W, E>(&mut writer, a)?;
+        write_constraint_vec::<&mut W, E>(&mut writer, b)?;
+        write_constraint_vec::<&mut W, E>(&mut writer, c)?;
+    }
+    Ok(())
+}
+
+fn write_map<W: Write>(mut writer: W, wire_mapping: &[u64]) -> Result<()> {
+    write_section_header(&mut writer, WIRE2LABEL_TYPE, wire_mapping.len() as u64 * 8)?;
+    for label in wire_mapping {
+        writer.write_u64::<LittleEndian>(*label)?;
+    }
+    Ok(())
+}
+
+fn write_custom_gates_list<W: Write, E: ScalarEngine>(
+    mut writer: W,
+    custom_gates: &[CustomGates<E>],
+    header: &Header,
+) -> Result<()> {
+    let size = 4 + custom_gates
+        .iter()
+        .map(|g| {
+            g.template_name.len() as u64
+                + 1
+                + 4
+                + g.parameters.len() as u64 * header.field_size as u64
+        })
+        .sum::<u64>();
+    write_section_header(&mut writer, CUSTOM_GATES_LIST, size)?;
+    writer.write_u32::<LittleEndian>(custom_gates.len() as u32)?;
+    for g in custom_gates {
+        writer.write_all(g.template_name.as_bytes())?;
+        writer.write_u8(0)?;
+        writer.write_u32::<LittleEndian>(g.parameters.len() as u32)?;
+        for p in &g.parameters {
+            write_field::<&mut W, E>(&mut writer, p)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_custom_gates_uses_list<W: Write>(
+    mut writer: W,
+    custom_gates_uses: &[CustomGatesUses],
+) -> Result<()> {
+    let size = 4 + custom_gates_uses
+        .iter()
+        .map(|u| 8 + u.signals.len() as u64 * 8)
+        .sum::<u64>();
+    write_section_header(&mut writer, CUSTOM_GATES_USE, size)?;
+    writer.write_u32::<LittleEndian>(custom_gates_uses.len() as u32)?;
+    for u in custom_gates_uses {
+        writer.write_u32::<LittleEndian>(u.id as u32)?;
+        writer.write_u32::<LittleEndian>(u.signals.len() as u32)?;
+        for signal in &u.signals {
+            writer.write_u64::<LittleEndian>(*signal)?;
+        }
+    }
+    Ok(())
+}
+
+/// Serialize the R1CS in the iden3 binary format, which can be parsed by `from_reader` and snarkjs.
+/// The custom gates sections are written only if the circuit uses custom gates.
+pub fn to_writer<W: Write, E: ScalarEngine>(mut writer: W, file: &R1CSFile<E>) -> Result<()> {
+    let header = &file.header;
+    let repr_size = E::Fr::zero().into_repr().as_ref().len() as u32 * 8;
+    if header.field_size != repr_size || header.prime_size.len() != repr_size as usize {
+        return Err(Error::new(ErrorKind::InvalidData, "Different prime"));
+    }
+    if header.n_constraints as usize != file.constraints.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid number of constraints",
+        ));
+    }
+    if header.n_wires as usize != file.wire_mapping.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid map section size",
+        ));
+    }
+    let use_custom_gates = header.use_custom_gates
+        || !file.custom_gates.is_empty()
+        || !file.custom_gates_uses.is_empty();
+
+    writer.write_all(&MAGIC)?;
+    writer.write_u32::<LittleEndian>(file.version)?;
+    writer.write_u32::<LittleEndian>(if use_custom_gates { 5 } else { 3 })?;
+    write_header(&mut writer, header)?;
+    write_constraints::<&mut W, E>(&mut writer, &file.constraints, header)?;
+    write_map(&mut writer, &file.wire_mapping)?;
+    if use_custom_gates {
+        write_custom_gates_list::<&mut W, E>(&mut writer, &file.custom_gates, header)?;
+        write_custom_gates_uses_list(&mut writer, &file.custom_gates_uses)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -313,6 +495,18 @@ mod tests {
     use crate::bellman_ce::pairing::ff;
     use std::io::{BufReader, Cursor};
 
+    const CIRCUIT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.r1cs");
+
+    #[test]
+    fn write_wire_out_of_range() {
+        let one = <Bn256 as ScalarEngine>::Fr::one();
+        let mut buf = vec![];
+        assert!(write_constraint_vec::<_, Bn256>(&mut buf, &[(u32::MAX as usize, one)]).is_ok());
+        let err = write_constraint_vec::<_, Bn256>(&mut buf, &[(u32::MAX as usize + 1, one)])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
     #[test]
     fn sample() {
         let data = hex!(
@@ -403,4 +597,96 @@ mod tests {
         let err = read_header(&mut buf.as_slice(), 32).err().unwrap();
         assert_eq!(err.kind(), ErrorKind::InvalidData)
     }
+
+    #[test]
+    fn round_trip_multiplier() {
+        let data = std::fs::read(CIRCUIT_FILE).unwrap();
+        let file = from_reader::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        assert_eq!(file.header.n_constraints, 1);
+
+        let mut buf = vec![];
+        to_writer(&mut buf, &file).unwrap();
+        // the sections are reordered as header, constraints and wire2label
+        assert_eq!(buf.len(), data.len());
+
+        let file2 = from_reader::<_, Bn256>(Cursor::new(&buf[..])).unwrap();
+        assert_eq!(file2.header.n_wires, file.header.n_wires);
+        assert_eq!(file2.header.n_pub_out, file.header.n_pub_out);
+        assert_eq!(file2.header.n_pub_in, file.header.n_pub_in);
+        assert_eq!(file2.header.n_prv_in, file.header.n_prv_in);
+        assert_eq!(file2.header.n_labels, file.header.n_labels);
+        assert_eq!(file2.header.prime_size, file.header.prime_size);
+        assert_eq!(file2.constraints, file.constraints);
+        assert_eq!(file2.wire_mapping, file.wire_mapping);
+
+        let mut buf2 = vec![];
+        to_writer(&mut buf2, &file2).unwrap();
+        assert_eq!(buf2, buf);
+    }
+
+    #[test]
+    fn round_trip_custom_gates() {
+        let data = std::fs::read(CIRCUIT_FILE).unwrap();
+        let mut file = from_reader::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        file.custom_gates = vec![CustomGates {
+            template_name: "CMul".to_string(),
+            parameters: vec![ff::from_hex("0x03").unwrap(), ff::from_hex("0x05").unwrap()],
+        }];
+        file.custom_gates_uses = vec![CustomGatesUses {
+            id: 0,
+            signals: vec![1, 2, 0x100000003],
+        }];
+
+        let mut buf = vec![];
+        to_writer(&mut buf, &file).unwrap();
+        let file2 = from_reader::<_, Bn256>(Cursor::new(&buf[..])).unwrap();
+        assert!(file2.header.use_custom_gates);
+        assert_eq!(file2.constraints, file.constraints);
+        assert_eq!(file2.custom_gates.len(), 1);
+        assert_eq!(file2.custom_gates[0].template_name, "CMul");
+        assert_eq!(
+            file2.custom_gates[0].parameters,
+            file.custom_gates[0].parameters
+        );
+        assert_eq!(file2.custom_gates_uses.len(), 1);
+        assert_eq!(file2.custom_gates_uses[0].signals, vec![1, 2, 0x100000003]);
+
+        let mut buf2 = vec![];
+        to_writer(&mut buf2, &file2).unwrap();
+        assert_eq!(buf2, buf);
+    }
+
+    #[test]
+    fn write_invalid_header() {
+        let data = std::fs::read(CIRCUIT_FILE).unwrap();
+        let mut file = from_reader::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        file.header.n_wires += 1;
+        let err = to_writer(&mut vec![], &file).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData)
+    }
+
+    #[test]
+    fn streaming_constraints() {
+        let data = std::fs::read(CIRCUIT_FILE).unwrap();
+        let file = from_reader::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        let (header_only, constraints) =
+            from_reader_streaming::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        assert!(header_only.constraints.is_empty());
+        assert_eq!(header_only.wire_mapping, file.wire_mapping);
+        assert_eq!(constraints.len(), 1);
+        let constraints = constraints.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(constraints, file.constraints);
+
+        // claim one more constraint than the section holds, the iterator runs into the
+        // wire2label section, fails at the end of file, and stops
+        let mut data = vec![];
+        to_writer(&mut data, &file).unwrap();
+        let n_constraints_offset = 12 + 12 + 4 + 32 + 4 * 4 + 8;
+        data[n_constraints_offset..n_constraints_offset + 4].copy_from_slice(&2u32.to_le_bytes());
+        let (_, mut constraints) =
+            from_reader_streaming::<_, Bn256>(Cursor::new(&data[..])).unwrap();
+        assert!(constraints.next().unwrap().is_ok());
+        assert!(constraints.next().unwrap().is_err());
+        assert!(constraints.next().is_none());
+    }
 }
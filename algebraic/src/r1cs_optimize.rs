@@ -0,0 +1,514 @@
+//! Optimize a R1CS by substituting away the linear constraints, merging the duplicate
+//! constraints and removing the unused wires, like the `--O2` simplification of circom, but for
+//! the circuits compiled elsewhere.
+//!
+//! The preserved wires, i.e. the constant one, the public signals and optionally the private
+//! inputs, are never eliminated nor renumbered, and the returned `wire_mapping` maps each wire of
+//! the optimized R1CS to the wire of the original one, so that the witness of the original
+//! circuit can be used by `CircomCircuit { wire_mapping: Some(wire_mapping), .. }`.
+use crate::bellman_ce::{
+    pairing::{bls12_381::Bls12, bn256::Bn256},
+    Field, PrimeField, ScalarEngine,
+};
+use crate::circom_circuit::{Constraint, CustomGatesUses, R1CS};
+use crate::errors::{EigenError, Result};
+use crate::field_gl::GL;
+use crate::r1cs_file::{from_reader, to_writer, Header, R1CSFile};
+use anyhow::bail;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+type LinearCombination<E> = BTreeMap<usize, <E as ScalarEngine>::Fr>;
+
+/// The optimized R1CS and how it is got
+#[derive(Debug)]
+pub struct OptimizedR1CS<E: ScalarEngine> {
+    pub r1cs: R1CS<E>,
+    /// wire of the optimized R1CS => wire of the original R1CS
+    pub wire_mapping: Vec<usize>,
+    pub stats: OptimizeStats,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OptimizeStats {
+    pub constraints_before: usize,
+    pub constraints_after: usize,
+    pub wires_before: usize,
+    pub wires_after: usize,
+    pub linear_eliminated: usize,
+    pub duplicates_removed: usize,
+}
+
+/// The linear substitutions `wire => lc` found so far
+struct Substitutions<E: ScalarEngine> {
+    map: HashMap<usize, LinearCombination<E>>,
+    order: Vec<usize>,
+}
+
+impl<E: ScalarEngine> Substitutions<E> {
+    fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            order: vec![],
+        }
+    }
+
+    fn insert(&mut self, wire: usize, lc: LinearCombination<E>) {
+        self.map.insert(wire, lc);
+        self.order.push(wire);
+    }
+
+    /// Substitute the eliminated wires of `lc` recursively.
+    fn resolve(&self, lc: &LinearCombination<E>) -> LinearCombination<E> {
+        let mut res = LinearCombination::<E>::new();
+        let mut stack = lc.iter().map(|(w, c)| (*w, *c)).collect::<Vec<_>>();
+        while let Some((wire, coeff)) = stack.pop() {
+            match self.map.get(&wire) {
+                Some(sub) => stack.extend(sub.iter().map(|(w, c)| {
+                    let mut c = *c;
+                    c.mul_assign(&coeff);
+                    (*w, c)
+                })),
+                None => res
+                    .entry(wire)
+                    .or_insert_with(E::Fr::zero)
+                    .add_assign(&coeff),
+            }
+        }
+        res.retain(|_, c| !c.is_zero());
+        res
+    }
+
+    /// Resolve every substitution, so that the later resolutions are one level deep. A
+    /// substitution only refers to the wires eliminated after it, so do it from the last one.
+    fn flatten(&mut self) {
+        for wire in self.order.iter().rev() {
+            let lc = self.map.remove(wire).unwrap();
+            let lc = self.resolve(&lc);
+            self.map.insert(*wire, lc);
+        }
+    }
+}
+
+fn to_lc<E: ScalarEngine>(terms: &[(usize, E::Fr)]) -> LinearCombination<E> {
+    let mut lc = LinearCombination::<E>::new();
+    for (wire, coeff) in terms {
+        lc.entry(*wire)
+            .or_insert_with(E::Fr::zero)
+            .add_assign(coeff);
+    }
+    lc.retain(|_, c| !c.is_zero());
+    lc
+}
+
+fn is_constant<E: ScalarEngine>(lc: &LinearCombination<E>) -> bool {
+    lc.keys().all(|w| *w == 0)
+}
+
+/// If `a * b = c` is linear, returns the `lc` of the equivalent `lc = 0`.
+fn linearize<E: ScalarEngine>(
+    a: &LinearCombination<E>,
+    b: &LinearCombination<E>,
+    c: &LinearCombination<E>,
+) -> Option<LinearCombination<E>> {
+    let (k, lc) = if a.is_empty() || b.is_empty() {
+        (E::Fr::zero(), b)
+    } else if is_constant(a) {
+        (a[&0], b)
+    } else if is_constant(b) {
+        (b[&0], a)
+    } else {
+        return None;
+    };
+    let mut res = LinearCombination::<E>::new();
+    for (wire, coeff) in lc {
+        let mut coeff = *coeff;
+        coeff.mul_assign(&k);
+        res.insert(*wire, coeff);
+    }
+    for (wire, coeff) in c {
+        res.entry(*wire)
+            .or_insert_with(E::Fr::zero)
+            .sub_assign(coeff);
+    }
+    res.retain(|_, c| !c.is_zero());
+    Some(res)
+}
+
+fn scale<E: ScalarEngine>(lc: &mut LinearCombination<E>, k: &E::Fr) {
+    for coeff in lc.values_mut() {
+        coeff.mul_assign(k);
+    }
+}
+
+type Key = Vec<(usize, Vec<u64>)>;
+
+fn lc_key<E: ScalarEngine>(lc: &LinearCombination<E>) -> Key {
+    lc.iter()
+        .map(|(w, c)| (*w, c.into_repr().as_ref().to_vec()))
+        .collect()
+}
+
+/// Optimize `r1cs`, and never eliminate the wires `0..num_preserved`, which must cover the
+/// constant one and the public signals, i.e. `num_preserved >= r1cs.num_inputs`.
+pub fn optimize_r1cs<E: ScalarEngine>(
+    r1cs: &R1CS<E>,
+    num_preserved: usize,
+) -> Result<OptimizedR1CS<E>> {
+    if num_preserved < r1cs.num_inputs || num_preserved > r1cs.num_variables {
+        bail!(EigenError::OutOfRangeError {
+            expected: format!(
+                "preserved wires in [{}, {}]",
+                r1cs.num_inputs, r1cs.num_variables
+            ),
+            found: num_preserved.to_string(),
+        });
+    }
+    // the wires referred by the custom gates can not be substituted
+    let custom_gate_wires = r1cs
+        .custom_gates_uses
+        .iter()
+        .flat_map(|u| u.signals.iter().map(|s| *s as usize))
+        .collect::<BTreeSet<_>>();
+    let eliminable = |wire: usize| wire >= num_preserved && !custom_gate_wires.contains(&wire);
+
+    let mut stats = OptimizeStats {
+        constraints_before: r1cs.constraints.len(),
+        wires_before: r1cs.num_variables,
+        ..Default::default()
+    };
+
+    // 1. substitute away the linear constraints, until no more linear constraint shows up
+    let mut constraints = r1cs
+        .constraints
+        .iter()
+        .map(|(a, b, c)| (to_lc::<E>(a), to_lc::<E>(b), to_lc::<E>(c)))
+        .collect::<Vec<_>>();
+    let mut subs = Substitutions::<E>::new();
+    loop {
+        let mut eliminated = 0;
+        let mut kept = Vec::with_capacity(constraints.len());
+        for (a, b, c) in constraints {
+            let (a, b, c) = (subs.resolve(&a), subs.resolve(&b), subs.resolve(&c));
+            let lc = match linearize::<E>(&a, &b, &c) {
+                Some(lc) => lc,
+                None => {
+                    kept.push((a, b, c));
+                    continue;
+                }
+            };
+            if lc.is_empty() {
+                // always satisfied
+                continue;
+            }
+            // eliminate the latest wire, which is usually an intermediate signal
+            match lc.keys().rev().find(|w| eliminable(**w)).copied() {
+                Some(wire) => {
+                    let mut sub = lc;
+                    let mut k = sub.remove(&wire).unwrap().inverse().unwrap();
+                    k.negate();
+                    scale::<E>(&mut sub, &k);
+                    subs.insert(wire, sub);
+                    eliminated += 1;
+                }
+                None => kept.push((
+                    LinearCombination::<E>::new(),
+                    LinearCombination::<E>::new(),
+                    lc,
+                )),
+            }
+        }
+        constraints = kept;
+        stats.linear_eliminated += eliminated;
+        if eliminated == 0 {
+            break;
+        }
+        subs.flatten();
+    }
+
+    // 2. merge the duplicate constraints, up to the order of a and b, and the scale of the
+    // linear ones
+    let mut seen = HashSet::new();
+    let mut unique = Vec::with_capacity(constraints.len());
+    for (mut a, mut b, mut c) in constraints {
+        if a.is_empty() || b.is_empty() {
+            a.clear();
+            b.clear();
+            let first = c.values().next().copied();
+            if let Some(first) = first {
+                scale::<E>(&mut c, &first.inverse().unwrap());
+            }
+        }
+        let (ka, kb) = (lc_key::<E>(&a), lc_key::<E>(&b));
+        let key = if ka <= kb {
+            (ka, kb, lc_key::<E>(&c))
+        } else {
+            (kb, ka, lc_key::<E>(&c))
+        };
+        if seen.insert(key) {
+            unique.push((a, b, c));
+        } else {
+            stats.duplicates_removed += 1;
+        }
+    }
+
+    // 3. remove the unused wires, and renumber the rest in order
+    let mut used = (0..num_preserved).collect::<BTreeSet<_>>();
+    used.extend(custom_gate_wires.iter().copied());
+    for (a, b, c) in &unique {
+        used.extend(a.keys().chain(b.keys()).chain(c.keys()).copied());
+    }
+    let wire_mapping = used.into_iter().collect::<Vec<_>>();
+    let new_wire = wire_mapping
+        .iter()
+        .enumerate()
+        .map(|(new, old)| (*old, new))
+        .collect::<HashMap<_, _>>();
+    let remap = |lc: &LinearCombination<E>| -> Vec<(usize, E::Fr)> {
+        lc.iter().map(|(w, c)| (new_wire[w], *c)).collect()
+    };
+    let constraints: Vec<Constraint<E>> = unique
+        .iter()
+        .map(|(a, b, c)| (remap(a), remap(b), remap(c)))
+        .collect();
+    let custom_gates_uses = r1cs
+        .custom_gates_uses
+        .iter()
+        .map(|u| CustomGatesUses {
+            id: u.id,
+            signals: u
+                .signals
+                .iter()
+                .map(|s| new_wire[&(*s as usize)] as u64)
+                .collect(),
+        })
+        .collect();
+
+    stats.constraints_after = constraints.len();
+    stats.wires_after = wire_mapping.len();
+    Ok(OptimizedR1CS {
+        r1cs: R1CS {
+            num_inputs: r1cs.num_inputs,
+            num_aux: wire_mapping.len() - r1cs.num_inputs,
+            num_variables: wire_mapping.len(),
+            num_outputs: r1cs.num_outputs,
+            constraints,
+            custom_gates: r1cs.custom_gates.clone(),
+            custom_gates_uses,
+        },
+        wire_mapping,
+        stats,
+    })
+}
+
+/// Optimize the binary r1cs file, preserving the public signals and the private inputs, write
+/// the optimized one to `output` and its wire mapping to `wire_mapping_file` in json.
+pub fn optimize_r1cs_file(
+    curve_type: &str,
+    input: &str,
+    output: &str,
+    wire_mapping_file: &str,
+) -> Result<()> {
+    let stats = match curve_type {
+        "BN128" => optimize_file::<Bn256>(input, output, wire_mapping_file)?,
+        "BLS12381" => optimize_file::<Bls12>(input, output, wire_mapping_file)?,
+        "GL" => optimize_file::<GL>(input, output, wire_mapping_file)?,
+        _ => bail!(EigenError::Unknown(format!(
+            "Unknown curve type: {}",
+            curve_type
+        ))),
+    };
+    log::info!(
+        "r1cs optimized: {}",
+        serde_json::to_string(&stats).unwrap_or_else(|_| "<failed>".to_owned())
+    );
+    Ok(())
+}
+
+fn optimize_file<E: ScalarEngine>(
+    input: &str,
+    output: &str,
+    wire_mapping_file: &str,
+) -> Result<OptimizeStats> {
+    let file = from_reader::<_, E>(BufReader::new(File::open(input)?))?;
+    let header = &file.header;
+    let num_inputs = 1 + header.n_pub_out as usize + header.n_pub_in as usize;
+    let num_aux = match (header.n_wires as usize).checked_sub(num_inputs) {
+        Some(num_aux) => num_aux,
+        None => bail!(EigenError::InvalidInput {
+            path: input.to_string(),
+            msg: format!(
+                "{} wires, less than the {} public ones",
+                header.n_wires, num_inputs
+            ),
+        }),
+    };
+    let r1cs = R1CS::<E> {
+        num_inputs,
+        num_aux,
+        num_variables: header.n_wires as usize,
+        num_outputs: header.n_pub_out as usize,
+        constraints: file.constraints,
+        custom_gates: file.custom_gates,
+        custom_gates_uses: file.custom_gates_uses,
+    };
+    let optimized = optimize_r1cs(&r1cs, num_inputs + header.n_prv_in as usize)?;
+
+    let optimized_file = R1CSFile::<E> {
+        version: file.version,
+        header: Header {
+            field_size: header.field_size,
+            prime_size: header.prime_size.clone(),
+            n_wires: optimized.r1cs.num_variables as u32,
+            n_pub_out: header.n_pub_out,
+            n_pub_in: header.n_pub_in,
+            n_prv_in: header.n_prv_in,
+            n_labels: header.n_labels,
+            n_constraints: optimized.r1cs.constraints.len() as u32,
+            use_custom_gates: header.use_custom_gates,
+        },
+        // keep the labels, so that the symbol file still works
+        wire_mapping: optimized
+            .wire_mapping
+            .iter()
+            .map(|w| file.wire_mapping[*w])
+            .collect(),
+        constraints: optimized.r1cs.constraints,
+        custom_gates: optimized.r1cs.custom_gates,
+        custom_gates_uses: optimized.r1cs.custom_gates_uses,
+    };
+    to_writer(BufWriter::new(File::create(output)?), &optimized_file)?;
+    serde_json::to_writer(
+        BufWriter::new(File::create(wire_mapping_file)?),
+        &optimized.wire_mapping,
+    )?;
+    Ok(optimized.stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r1cs_check::check_r1cs;
+
+    type Fr = <Bn256 as ScalarEngine>::Fr;
+
+    fn fr(x: &str) -> Fr {
+        Fr::from_str(x).unwrap()
+    }
+
+    fn neg(x: &str) -> Fr {
+        let mut x = fr(x);
+        x.negate();
+        x
+    }
+
+    fn r1cs(num_variables: usize, constraints: Vec<Constraint<Bn256>>) -> R1CS<Bn256> {
+        R1CS {
+            num_inputs: 2,
+            num_aux: num_variables - 2,
+            num_variables,
+            num_outputs: 1,
+            constraints,
+            custom_gates: vec![],
+            custom_gates_uses: vec![],
+        }
+    }
+
+    #[test]
+    fn eliminate_and_dedup() {
+        // 0: one, 1: out, 2: a, 3: b, 4: t, 5: u, 6: unused
+        let r1cs = r1cs(
+            7,
+            vec![
+                // a * b = t
+                (vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(4, fr("1"))]),
+                // 1 * t = u
+                (vec![(0, fr("1"))], vec![(4, fr("1"))], vec![(5, fr("1"))]),
+                // a * b = u, a duplicate after u := t
+                (vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(5, fr("1"))]),
+                // 0 = out - t
+                (vec![], vec![], vec![(1, fr("1")), (4, neg("1"))]),
+            ],
+        );
+        let witness = ["1", "33", "3", "11", "33", "33", "7"].map(fr);
+        let names = |_: usize| None;
+        assert!(check_r1cs(&r1cs, &witness, None, &names)
+            .unwrap()
+            .is_empty());
+
+        // keep a and b as private inputs
+        let optimized = optimize_r1cs(&r1cs, 4).unwrap();
+        assert_eq!(optimized.stats.linear_eliminated, 2);
+        assert_eq!(optimized.stats.duplicates_removed, 1);
+        assert_eq!(optimized.wire_mapping, vec![0, 1, 2, 3]);
+        assert_eq!(optimized.r1cs.num_variables, 4);
+        assert_eq!(optimized.r1cs.num_inputs, 2);
+        assert_eq!(
+            optimized.r1cs.constraints,
+            vec![(vec![(2, fr("1"))], vec![(3, fr("1"))], vec![(1, fr("1"))])]
+        );
+        let wire_mapping = Some(&optimized.wire_mapping[..]);
+        assert!(check_r1cs(&optimized.r1cs, &witness, wire_mapping, &names)
+            .unwrap()
+            .is_empty());
+
+        assert!(optimize_r1cs(&r1cs, 1).is_err());
+    }
+
+    #[test]
+    fn renumber_wires() {
+        // 0: one, 1: out, 2: s, 3: x, 4: y, 5: z
+        let r1cs = r1cs(
+            6,
+            vec![
+                // x * y = z
+                (vec![(3, fr("1"))], vec![(4, fr("1"))], vec![(5, fr("1"))]),
+                // 0 = out - 3s
+                (vec![], vec![], vec![(1, fr("1")), (2, neg("3"))]),
+                // s * s = z
+                (vec![(2, fr("1"))], vec![(2, fr("1"))], vec![(5, fr("1"))]),
+            ],
+        );
+        let optimized = optimize_r1cs(&r1cs, 2).unwrap();
+        assert_eq!(optimized.stats.linear_eliminated, 1);
+        assert_eq!(optimized.wire_mapping, vec![0, 1, 3, 4, 5]);
+        assert_eq!(optimized.r1cs.constraints.len(), 2);
+        assert!(optimized
+            .r1cs
+            .constraints
+            .iter()
+            .flat_map(|(a, b, c)| a.iter().chain(b.iter()).chain(c.iter()))
+            .all(|(w, _)| *w < 5));
+
+        let names = |_: usize| None;
+        let wire_mapping = Some(&optimized.wire_mapping[..]);
+        let witness = ["1", "9", "3", "3", "3", "9"].map(fr);
+        assert!(check_r1cs(&optimized.r1cs, &witness, wire_mapping, &names)
+            .unwrap()
+            .is_empty());
+        let witness = ["1", "9", "3", "3", "3", "8"].map(fr);
+        assert!(!check_r1cs(&optimized.r1cs, &witness, wire_mapping, &names)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn malformed_header() {
+        let circuit = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.r1cs");
+        let mut file =
+            from_reader::<_, Bn256>(BufReader::new(File::open(circuit).unwrap())).unwrap();
+        // fewer wires than the public signals
+        file.header.n_wires = 1;
+        file.wire_mapping.truncate(1);
+        let malformed = "/tmp/malformed_multiplier.r1cs";
+        to_writer(BufWriter::new(File::create(malformed).unwrap()), &file).unwrap();
+        assert!(optimize_r1cs_file(
+            "BN128",
+            malformed,
+            "/tmp/malformed_optimized.r1cs",
+            "/tmp/malformed_wire_mapping.json"
+        )
+        .is_err());
+    }
+}
@@ -14,6 +14,7 @@ use crate::bellman_ce::{
 };
 
 use crate::circom_circuit::{CircuitJson, R1CS};
+use crate::r1cs_file::ConstraintIter;
 
 /// get universal setup file by filename
 fn get_universal_setup_file_buff_reader(setup_file_name: &str) -> Result<BufReader<File>> {
@@ -44,8 +45,17 @@ pub fn maybe_load_key_lagrange_form<E: Engine>(
     }
 }
 
+/// little-endian bytes of the scalar field modulus, as stored in the `.wtns` and `.r1cs` headers
+pub fn field_modulus_le_bytes<E: ScalarEngine>() -> Vec<u8> {
+    let mut buf = vec![];
+    E::Fr::char()
+        .write_le(&mut buf)
+        .expect("write to vec never fails");
+    buf
+}
+
 /// load witness file by filename with autodetect encoding (bin or json).
-pub fn load_witness_from_file<E: ScalarEngine>(filename: &str) -> Vec<E::Fr> {
+pub fn load_witness_from_file<E: ScalarEngine>(filename: &str) -> Result<Vec<E::Fr>> {
     if filename.ends_with("json") {
         load_witness_from_json_file::<E>(filename)
     } else {
@@ -54,31 +64,27 @@ pub fn load_witness_from_file<E: ScalarEngine>(filename: &str) -> Vec<E::Fr> {
 }
 
 /// load witness from json file by filename
-pub fn load_witness_from_json_file<E: ScalarEngine>(filename: &str) -> Vec<E::Fr> {
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(filename)
-        .expect("unable to open.");
+pub fn load_witness_from_json_file<E: ScalarEngine>(filename: &str) -> Result<Vec<E::Fr>> {
+    let reader = OpenOptions::new().read(true).open(filename)?;
     load_witness_from_json::<E, BufReader<File>>(BufReader::new(reader))
 }
 
 /// load witness from json by a reader
-fn load_witness_from_json<E: ScalarEngine, R: Read>(reader: R) -> Vec<E::Fr> {
-    let witness: Vec<String> = serde_json::from_reader(reader).expect("unable to read.");
+fn load_witness_from_json<E: ScalarEngine, R: Read>(reader: R) -> Result<Vec<E::Fr>> {
+    let witness: Vec<String> = serde_json::from_reader(reader)?;
     witness
         .into_iter()
-        .map(|x| E::Fr::from_str(&x).unwrap())
-        .collect::<Vec<E::Fr>>()
+        .map(|x| match E::Fr::from_str(&x) {
+            Some(fr) => Ok(fr),
+            None => bail!(EigenError::from(format!("invalid witness value {}", x))),
+        })
+        .collect()
 }
 
 /// load witness from bin file by filename
-pub fn load_witness_from_bin_file<E: ScalarEngine>(filename: &str) -> Vec<E::Fr> {
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(filename)
-        .expect("unable to open.");
+pub fn load_witness_from_bin_file<E: ScalarEngine>(filename: &str) -> Result<Vec<E::Fr>> {
+    let reader = OpenOptions::new().read(true).open(filename)?;
     load_witness_from_bin_reader::<E, BufReader<File>>(BufReader::new(reader))
-        .expect("read witness failed")
 }
 
 /// load witness from u8 array
@@ -108,17 +114,18 @@ pub fn load_witness_from_bin_reader<E: ScalarEngine, R: Read>(mut reader: R) ->
     if sec_type != 1 {
         bail!(EigenError::from("invalid section type".to_string()));
     }
+    let modulus = field_modulus_le_bytes::<E>();
     let sec_size = reader.read_u64::<LittleEndian>()?;
-    if sec_size != 4 + 32 + 4 {
+    if sec_size != 4 + modulus.len() as u64 + 4 {
         bail!(EigenError::from("invalid section len".to_string()));
     }
     let field_size = reader.read_u32::<LittleEndian>()?;
-    if field_size != 32 {
+    if field_size != modulus.len() as u32 {
         bail!(EigenError::from("invalid field byte size".to_string()));
     }
     let mut prime = vec![0u8; field_size as usize];
     reader.read_exact(&mut prime)?;
-    if prime != hex!("010000f093f5e1439170b97948e833285d588181b64550b829a031e1724e6430") {
+    if prime != modulus {
         bail!(EigenError::from("invalid curve prime".to_string()));
     }
     let witness_len = reader.read_u32::<LittleEndian>()?;
@@ -143,51 +150,79 @@ pub fn load_witness_from_bin_reader<E: ScalarEngine, R: Read>(mut reader: R) ->
     Ok(result)
 }
 
-/// load r1cs file by filename with autodetect encoding (bin or json)
-pub fn load_r1cs<E: ScalarEngine>(filename: &str) -> R1CS<E> {
-    if filename.ends_with("json") {
-        load_r1cs_from_json_file(filename)
-    } else {
-        let (r1cs, _wire_mapping) = load_r1cs_from_bin_file(filename);
-        r1cs
+/// Encoding of a r1cs file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum R1CSFormat {
+    Bin,
+    Json,
+}
+
+/// Detect the encoding of a r1cs by its leading bytes, and rewind the reader.
+pub fn detect_r1cs_format<R: Read + Seek>(reader: &mut R) -> Result<R1CSFormat> {
+    let mut buf = [0u8; 64];
+    let n = reader.read(&mut buf)?;
+    reader.rewind()?;
+    if n >= 4 && buf[..4] == crate::r1cs_file::MAGIC {
+        return Ok(R1CSFormat::Bin);
+    }
+    match buf[..n].iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') => Ok(R1CSFormat::Json),
+        _ => bail!(EigenError::InvalidValue(
+            "unknown r1cs format, neither binary nor json".to_string()
+        )),
     }
 }
 
-/// load r1cs from json file by filename
-fn load_r1cs_from_json_file<E: ScalarEngine>(filename: &str) -> R1CS<E> {
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(filename)
-        .expect("unable to open.");
-    load_r1cs_from_json(BufReader::new(reader))
+/// load r1cs file by filename with autodetect encoding (bin or json)
+pub fn load_r1cs<E: ScalarEngine>(filename: &str) -> Result<R1CS<E>> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    match detect_r1cs_format(&mut reader)? {
+        R1CSFormat::Json => load_r1cs_from_json(reader),
+        R1CSFormat::Bin => Ok(load_r1cs_from_bin(reader)?.0),
+    }
 }
 
 /// load r1cs from json by a reader
-fn load_r1cs_from_json<E: ScalarEngine, R: Read>(reader: R) -> R1CS<E> {
-    let circuit_json: CircuitJson = serde_json::from_reader(reader).expect("unable to read.");
+fn load_r1cs_from_json<E: ScalarEngine, R: Read>(reader: R) -> Result<R1CS<E>> {
+    let circuit_json: CircuitJson = serde_json::from_reader(reader)?;
 
     let num_inputs = circuit_json.num_inputs + circuit_json.num_outputs + 1;
+    if circuit_json.num_variables < num_inputs {
+        bail!(EigenError::OutOfRangeError {
+            expected: format!("at least {} variables", num_inputs),
+            found: circuit_json.num_variables.to_string(),
+        });
+    }
     let num_aux = circuit_json.num_variables - num_inputs;
 
-    let convert_constraint = |lc: &BTreeMap<String, String>| {
+    let convert_constraint = |lc: &BTreeMap<String, String>| -> Result<Vec<(usize, E::Fr)>> {
         lc.iter()
-            .map(|(index, coeff)| (index.parse().unwrap(), E::Fr::from_str(coeff).unwrap()))
-            .collect_vec()
+            .map(|(index, coeff)| match E::Fr::from_str(coeff) {
+                Some(coeff) => Ok((index.parse()?, coeff)),
+                None => bail!(EigenError::InvalidValue(format!(
+                    "invalid coefficient {} of wire {}",
+                    coeff, index
+                ))),
+            })
+            .collect()
     };
 
-    let constraints = circuit_json
-        .constraints
-        .iter()
-        .map(|c| {
-            (
-                convert_constraint(&c[0]),
-                convert_constraint(&c[1]),
-                convert_constraint(&c[2]),
-            )
-        })
-        .collect_vec();
+    let mut constraints = Vec::with_capacity(circuit_json.constraints.len());
+    for (i, c) in circuit_json.constraints.iter().enumerate() {
+        if c.len() != 3 {
+            bail!(EigenError::InvalidValue(format!(
+                "constraint {} should have 3 linear combinations",
+                i
+            )));
+        }
+        constraints.push((
+            convert_constraint(&c[0])?,
+            convert_constraint(&c[1])?,
+            convert_constraint(&c[2])?,
+        ));
+    }
 
-    R1CS {
+    Ok(R1CS {
         num_inputs,
         num_aux,
         num_variables: circuit_json.num_variables,
@@ -195,34 +230,103 @@ fn load_r1cs_from_json<E: ScalarEngine, R: Read>(reader: R) -> R1CS<E> {
         constraints,
         custom_gates: vec![],
         custom_gates_uses: vec![],
-    }
+    })
 }
 
-/// load r1cs from bin file by filename
-fn load_r1cs_from_bin_file<E: ScalarEngine>(filename: &str) -> (R1CS<E>, Vec<usize>) {
-    let reader = OpenOptions::new()
-        .read(true)
-        .open(filename)
-        .unwrap_or_else(|_| panic!("unable to open {}.", filename));
-    load_r1cs_from_bin(BufReader::new(reader))
+/// load r1cs from bin by a reader, returns the r1cs and its wire to label mapping
+pub fn load_r1cs_from_bin<R: Read + Seek, E: ScalarEngine>(
+    reader: R,
+) -> Result<(R1CS<E>, Vec<usize>)> {
+    let (r1cs, wire_mapping, constraints) = load_r1cs_streaming_from_bin::<R, E>(reader)?;
+    let constraints = constraints.collect::<std::io::Result<Vec<_>>>()?;
+    Ok((
+        R1CS {
+            constraints,
+            ..r1cs
+        },
+        wire_mapping,
+    ))
+}
+
+/// load r1cs from bin file by filename, but leave the constraints to the returned iterator,
+/// which reads them one by one, so that the huge circuits can be processed in a streaming way.
+/// The `constraints` of the returned r1cs is empty.
+pub fn load_r1cs_streaming<E: ScalarEngine>(
+    filename: &str,
+) -> Result<(R1CS<E>, Vec<usize>, ConstraintIter<BufReader<File>, E>)> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    if detect_r1cs_format(&mut reader)? != R1CSFormat::Bin {
+        bail!(EigenError::InvalidValue(format!(
+            "only the binary r1cs can be streamed, {}",
+            filename
+        )));
+    }
+    load_r1cs_streaming_from_bin(reader)
 }
 
-/// load r1cs from bin by a reader
-pub fn load_r1cs_from_bin<R: Read + Seek, E: ScalarEngine>(reader: R) -> (R1CS<E>, Vec<usize>) {
-    let file = crate::r1cs_file::from_reader::<R, E>(reader).expect("unable to read.");
+fn load_r1cs_streaming_from_bin<R: Read + Seek, E: ScalarEngine>(
+    reader: R,
+) -> Result<(R1CS<E>, Vec<usize>, ConstraintIter<R, E>)> {
+    let (file, constraints) = crate::r1cs_file::from_reader_streaming::<R, E>(reader)?;
     let num_inputs = (1 + file.header.n_pub_in + file.header.n_pub_out) as usize;
     let num_variables = file.header.n_wires as usize;
+    if num_variables < num_inputs {
+        bail!(EigenError::OutOfRangeError {
+            expected: format!("at least {} wires", num_inputs),
+            found: num_variables.to_string(),
+        });
+    }
     let num_aux = num_variables - num_inputs;
-    (
+    Ok((
         R1CS {
             num_aux,
             num_inputs,
             num_variables,
             num_outputs: file.header.n_pub_out as usize,
-            constraints: file.constraints,
+            constraints: vec![],
             custom_gates: file.custom_gates,
             custom_gates_uses: file.custom_gates_uses,
         },
         file.wire_mapping.iter().map(|e| *e as usize).collect_vec(),
-    )
+        constraints,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bellman_ce::pairing::bn256::Bn256;
+    use std::io::Cursor;
+
+    const CIRCUIT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.r1cs");
+
+    #[test]
+    fn detect_and_load_r1cs() {
+        let r1cs = load_r1cs::<Bn256>(CIRCUIT_FILE).unwrap();
+        assert_eq!(r1cs.num_inputs, 2);
+        assert_eq!(r1cs.num_variables, 4);
+        assert_eq!(r1cs.constraints.len(), 1);
+
+        let (header_only, wire_mapping, constraints) =
+            load_r1cs_streaming::<Bn256>(CIRCUIT_FILE).unwrap();
+        assert!(header_only.constraints.is_empty());
+        assert_eq!(wire_mapping, vec![0, 1, 2, 3]);
+        let constraints = constraints.collect::<std::io::Result<Vec<_>>>().unwrap();
+        assert_eq!(constraints, r1cs.constraints);
+
+        let json = r#"
+            {"nPubInputs": 0, "nOutputs": 1, "nVars": 4,
+             "constraints": [[{"2": "21888242871839275222246405745257275088548364400416034343698204186575808495616"},
+                              {"3": "1"},
+                              {"1": "21888242871839275222246405745257275088548364400416034343698204186575808495616"}]]}"#;
+        let mut reader = Cursor::new(json.as_bytes());
+        assert_eq!(detect_r1cs_format(&mut reader).unwrap(), R1CSFormat::Json);
+        let r1cs_json = load_r1cs_from_json::<Bn256, _>(reader).unwrap();
+        assert_eq!(r1cs_json.num_inputs, r1cs.num_inputs);
+        assert_eq!(r1cs_json.constraints, r1cs.constraints);
+
+        let mut reader = Cursor::new(b"not a r1cs".to_vec());
+        assert!(detect_r1cs_format(&mut reader).is_err());
+        assert!(load_r1cs::<Bn256>("/tmp/not_exist.r1cs").is_err());
+    }
 }
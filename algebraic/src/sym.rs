@@ -0,0 +1,88 @@
+//! Reader of the circom symbol file (`.sym`), each line is `label,wire,component,name`, and the
+//! wire is -1 if the signal is removed by the simplification.
+use crate::errors::{EigenError, Result};
+use anyhow::bail;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// A signal of the circuit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub label: u64,
+    pub wire: Option<usize>,
+    pub component: u64,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn from_file(filename: &str) -> Result<Self> {
+        Self::from_reader(BufReader::new(File::open(filename)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut symbols = vec![];
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = line.splitn(4, ',').collect::<Vec<_>>();
+            if fields.len() != 4 {
+                bail!(EigenError::InvalidValue(format!(
+                    "invalid symbol at line {}: {}",
+                    i + 1,
+                    line
+                )));
+            }
+            let wire = fields[1].parse::<i64>()?;
+            symbols.push(Symbol {
+                label: fields[0].parse()?,
+                wire: if wire < 0 { None } else { Some(wire as usize) },
+                component: fields[2].parse()?,
+                name: fields[3].to_string(),
+            });
+        }
+        Ok(SymbolTable { symbols })
+    }
+
+    /// The signal names by label
+    pub fn names_by_label(&self) -> HashMap<u64, &str> {
+        self.symbols
+            .iter()
+            .map(|s| (s.label, s.name.as_str()))
+            .collect()
+    }
+
+    /// The signal names by wire, a wire might be shared by several signals
+    pub fn names_by_wire(&self) -> HashMap<usize, Vec<&str>> {
+        let mut names: HashMap<usize, Vec<&str>> = HashMap::new();
+        for s in &self.symbols {
+            if let Some(wire) = s.wire {
+                names.entry(wire).or_default().push(&s.name);
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sym() {
+        let sym = "1,1,0,main.c\n2,2,0,main.a\n3,-1,0,main.b\n4,3,1,main.sub.x\n";
+        let table = SymbolTable::from_reader(sym.as_bytes()).unwrap();
+        assert_eq!(table.symbols.len(), 4);
+        assert_eq!(table.symbols[2].wire, None);
+        assert_eq!(table.names_by_label()[&4], "main.sub.x");
+        assert_eq!(table.names_by_wire()[&2], vec!["main.a"]);
+        assert!(SymbolTable::from_reader("1,1,main.c".as_bytes()).is_err());
+    }
+}
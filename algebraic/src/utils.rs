@@ -11,3 +11,13 @@ pub fn repr_to_big<T: Display>(r: T) -> String {
         .unwrap()
         .to_str_radix(10)
 }
+
+/// Create a deterministic rng from `seed`, so that setup and proving are reproducible.
+///
+/// INSECURE: anyone knowing the seed can recover the toxic waste of the setup and the blinding
+/// factors of the proofs. Only use it for testing.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn seeded_rng(seed: u64) -> rand::ChaChaRng {
+    use rand::SeedableRng;
+    rand::ChaChaRng::from_seed(&[seed as u32, (seed >> 32) as u32])
+}
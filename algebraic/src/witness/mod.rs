@@ -1,16 +1,21 @@
 mod circom;
 pub(crate) mod memory;
+#[cfg(not(feature = "wasm"))]
+mod native;
 pub(super) mod witness_calculator;
 
+use crate::errors::{EigenError, Result};
+use anyhow::bail;
 use fnv::FnvHasher;
+#[cfg(not(feature = "wasm"))]
+pub use native::NativeWitnessCalculator;
 use num_bigint::BigInt;
 use num_traits::{One, Zero};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::hash::Hasher;
-use std::str::FromStr;
 pub use witness_calculator::flat_array;
-pub use witness_calculator::WitnessCalculator;
+pub use witness_calculator::{CircomField, TypedWitness, WitnessCalculator};
 
 pub(crate) fn fnv(inp: &str) -> (u32, u32) {
     let mut hasher = FnvHasher::default();
@@ -20,35 +25,200 @@ pub(crate) fn fnv(inp: &str) -> (u32, u32) {
     ((h >> 32) as u32, h as u32)
 }
 
-pub fn load_input_for_witness(input_file: &str) -> HashMap<String, Vec<BigInt>> {
-    let inputs_str = std::fs::read_to_string(input_file).unwrap();
-    let inputs: std::collections::HashMap<String, serde_json::Value> =
-        serde_json::from_str(&inputs_str).unwrap();
-
-    inputs
-        .iter()
-        .map(|(key, value)| {
-            let res = match value {
-                Value::String(inner) => {
-                    vec![BigInt::from_str(inner).unwrap()]
-                }
-                Value::Bool(inner) => {
-                    if *inner {
-                        vec![BigInt::one()]
-                    } else {
-                        vec![BigInt::zero()]
-                    }
-                }
-                Value::Number(inner) => {
-                    vec![BigInt::from_str(&inner.to_string()).unwrap()]
-                    //vec![BigInt::from(inner.as_u64().expect("not a u32"))]
-                }
-                //Value::Array(inner) => inner.iter().cloned().map(value_to_bigint).collect(),
-                Value::Array(inner) => flat_array(inner),
-                _ => panic!("{:?}", value),
-            };
-
-            (key.clone(), res)
-        })
-        .collect::<std::collections::HashMap<_, _>>()
+/// Load the input signals of a circuit from a json file, see `parse_input_for_witness`.
+pub fn load_input_for_witness(input_file: &str) -> Result<HashMap<String, Vec<BigInt>>> {
+    let inputs_str = std::fs::read_to_string(input_file)?;
+    parse_input_for_witness(&inputs_str)
+}
+
+/// Parse the input signals from json as circom's witness calculator does. Nested objects are
+/// flattened to dotted signal names, arrays of objects are indexed like `a[0].b`, and arrays of
+/// values are flattened. A value is a decimal or hex string, a number or a boolean, and might be
+/// negative, it is reduced modulo the prime when calculating the witness.
+pub fn parse_input_for_witness(inputs_str: &str) -> Result<HashMap<String, Vec<BigInt>>> {
+    let inputs: Value = serde_json::from_str(inputs_str)?;
+    if !inputs.is_object() {
+        bail!(invalid_input("$", "the input must be an object"));
+    }
+    let mut signals = HashMap::new();
+    qualify_input("", "$", &inputs, &mut signals)?;
+    Ok(signals)
+}
+
+fn invalid_input(path: &str, msg: &str) -> EigenError {
+    EigenError::InvalidInput {
+        path: path.to_string(),
+        msg: msg.to_string(),
+    }
+}
+
+fn qualify_input(
+    prefix: &str,
+    path: &str,
+    value: &Value,
+    signals: &mut HashMap<String, Vec<BigInt>>,
+) -> Result<()> {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                qualify_input(&name, &format!("{}.{}", path, key), field, signals)?;
+            }
+        }
+        Value::Array(_) if contains_object(value) => {
+            qualify_input_list(prefix, path, value, signals)?;
+        }
+        _ => {
+            let mut values = vec![];
+            flatten_values(path, value, &mut values)?;
+            if signals.insert(prefix.to_string(), values).is_some() {
+                bail!(invalid_input(path, "duplicate signal"));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn qualify_input_list(
+    prefix: &str,
+    path: &str,
+    value: &Value,
+    signals: &mut HashMap<String, Vec<BigInt>>,
+) -> Result<()> {
+    match value {
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let name = format!("{}[{}]", prefix, i);
+                qualify_input_list(&name, &format!("{}[{}]", path, i), item, signals)?;
+            }
+            Ok(())
+        }
+        Value::Object(_) => qualify_input(prefix, path, value, signals),
+        _ => bail!(invalid_input(
+            path,
+            "expect an object, as the other elements of the array"
+        )),
+    }
+}
+
+fn contains_object(value: &Value) -> bool {
+    match value {
+        Value::Object(_) => true,
+        Value::Array(items) => items.iter().any(contains_object),
+        _ => false,
+    }
+}
+
+fn flatten_values(path: &str, value: &Value, values: &mut Vec<BigInt>) -> Result<()> {
+    match value {
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_values(&format!("{}[{}]", path, i), item, values)?;
+            }
+        }
+        Value::Bool(inner) => {
+            if *inner {
+                values.push(BigInt::one());
+            } else {
+                values.push(BigInt::zero());
+            }
+        }
+        Value::Number(inner) => match parse_bigint(&inner.to_string()) {
+            Some(v) => values.push(v),
+            None => bail!(invalid_input(path, "expect an integer")),
+        },
+        Value::String(inner) => match parse_bigint(inner) {
+            Some(v) => values.push(v),
+            None => bail!(invalid_input(path, "expect a decimal or hex integer")),
+        },
+        _ => bail!(invalid_input(path, "unsupported value")),
+    }
+    Ok(())
+}
+
+// parse a decimal or "0x" prefixed hex integer, optionally negative
+fn parse_bigint(s: &str) -> Option<BigInt> {
+    let s = s.trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let (radix, digits) = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(digits) => (16, digits),
+        None => (10, digits),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let v = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+    Some(if negative { -v } else { v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(inputs_str: &str) -> Result<Vec<(String, Vec<i64>)>> {
+        let mut signals = parse_input_for_witness(inputs_str)?
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    k,
+                    v.iter().map(|x| x.to_string().parse().unwrap()).collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        signals.sort();
+        Ok(signals)
+    }
+
+    #[test]
+    fn parse_values() {
+        let signals = parse(r#"{"a": "0x1F", "b": [-3, "-0x2", true, [false, "7"]], "c": 5}"#);
+        assert_eq!(
+            signals.unwrap(),
+            vec![
+                ("a".to_string(), vec![31]),
+                ("b".to_string(), vec![-3, -2, 1, 0, 7]),
+                ("c".to_string(), vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_nested_objects() {
+        let signals = parse(r#"{"p": {"x": 1, "y": [2, 3]}, "q": [{"x": 4}, {"x": 5}]}"#);
+        assert_eq!(
+            signals.unwrap(),
+            vec![
+                ("p.x".to_string(), vec![1]),
+                ("p.y".to_string(), vec![2, 3]),
+                ("q[0].x".to_string(), vec![4]),
+                ("q[1].x".to_string(), vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let path_of = |inputs_str: &str| match parse_input_for_witness(inputs_str)
+            .unwrap_err()
+            .downcast::<EigenError>()
+        {
+            Ok(EigenError::InvalidInput { path, .. }) => path,
+            e => panic!("unexpected error {:?}", e),
+        };
+        assert_eq!(path_of(r#"{"a": {"b": [1, "x"]}}"#), "$.a.b[1]");
+        assert_eq!(path_of(r#"{"a": [1.5]}"#), "$.a[0]");
+        assert_eq!(path_of(r#"{"a": [{"b": 1}, 2]}"#), "$.a[1]");
+        assert_eq!(path_of(r#"{"a": null}"#), "$.a");
+        assert_eq!(path_of(r#"[1]"#), "$");
+    }
 }
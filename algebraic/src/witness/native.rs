@@ -0,0 +1,148 @@
+use crate::bellman_ce::ScalarEngine;
+use crate::errors::{EigenError, Result};
+use crate::reader::{field_modulus_le_bytes, load_witness_from_bin_file};
+use anyhow::bail;
+use num_bigint::{BigInt, Sign};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The C++ witness generator of circom, the `<circuit>_cpp` folder of `--c`, run as a
+/// subprocess. It's much faster than the wasm one on large circuits, and writes the same `.wtns`.
+///
+/// Building it requires `make`, a C++ compiler, `nasm`, GMP and nlohmann-json.
+pub struct NativeWitnessCalculator {
+    binary: PathBuf,
+}
+
+fn program_error(msg: String) -> EigenError {
+    EigenError::WitnessProgramError(msg)
+}
+
+impl NativeWitnessCalculator {
+    /// Build the witness generator in `c_folder` by its Makefile, which is skipped if it's up to
+    /// date.
+    pub fn build<P: AsRef<Path>>(c_folder: P) -> Result<Self> {
+        let c_folder = c_folder.as_ref();
+        let name = match c_folder
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|f| f.strip_suffix("_cpp"))
+        {
+            Some(name) => name.to_string(),
+            None => bail!(program_error(format!(
+                "{} is not a <circuit>_cpp folder",
+                c_folder.display()
+            ))),
+        };
+        let output = Command::new("make").arg("-C").arg(c_folder).output()?;
+        if !output.status.success() {
+            bail!(program_error(format!(
+                "make {} failed: {}",
+                c_folder.display(),
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Self::from_binary(c_folder.join(name))
+    }
+
+    /// Use a built witness generator, its `.dat` file must be next to it
+    pub fn from_binary<P: AsRef<Path>>(binary: P) -> Result<Self> {
+        // the program finds its .dat file by its own path
+        let binary = std::fs::canonicalize(binary.as_ref())?;
+        let dat = PathBuf::from(format!("{}.dat", binary.to_string_lossy()));
+        if !dat.is_file() {
+            bail!(program_error(format!("missing {}", dat.display())));
+        }
+        Ok(Self { binary })
+    }
+
+    /// Calculate the witness of the json `input_file`, and write it into the `.wtns` file
+    /// `wtns_file`
+    pub fn calculate_witness_file(&self, input_file: &str, wtns_file: &str) -> Result<()> {
+        let output = Command::new(&self.binary)
+            .arg(input_file)
+            .arg(wtns_file)
+            .output()?;
+        if !output.status.success() {
+            bail!(program_error(format!(
+                "{} failed with {}: {}{}",
+                self.binary.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Calculate the witness as elements of the field of `E`, which must be the field of the
+    /// circuit. The inputs and the witness go through a temporary directory.
+    pub fn calculate_witness_fr<E: ScalarEngine, I: IntoIterator<Item = (String, Vec<BigInt>)>>(
+        &self,
+        inputs: I,
+    ) -> Result<Vec<E::Fr>> {
+        let dir = tempfile::Builder::new()
+            .prefix("native_witness")
+            .tempdir()?;
+        let result = (|| -> Result<Vec<E::Fr>> {
+            let input_file = dir.path().join("input.json");
+            let wtns_file = dir.path().join("witness.wtns");
+            std::fs::write(&input_file, inputs_to_json::<E, _>(inputs).to_string())?;
+            self.calculate_witness_file(
+                &input_file.to_string_lossy(),
+                &wtns_file.to_string_lossy(),
+            )?;
+            load_witness_from_bin_file::<E>(&wtns_file.to_string_lossy())
+        })();
+        let path = dir.path().to_path_buf();
+        if let Err(e) = dir.close() {
+            log::warn!("failed to remove {}: {}", path.display(), e);
+        }
+        result
+    }
+}
+
+/// The input json of the C++ witness generator, the values are reduced to the field of `E` as
+/// the wasm witness calculator does.
+fn inputs_to_json<E: ScalarEngine, I: IntoIterator<Item = (String, Vec<BigInt>)>>(
+    inputs: I,
+) -> Value {
+    let prime = BigInt::from_bytes_le(Sign::Plus, &field_modulus_le_bytes::<E>());
+    let mut json = Map::new();
+    for (name, values) in inputs {
+        let mut values = values
+            .iter()
+            .map(|v| Value::String((((v % &prime) + &prime) % &prime).to_string()))
+            .collect::<Vec<_>>();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            Value::Array(values)
+        };
+        json.insert(name, value);
+    }
+    Value::Object(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bellman_ce::pairing::bn256::Bn256;
+
+    #[test]
+    fn native_inputs() {
+        let inputs = vec![
+            ("a".to_string(), vec![BigInt::from(-1)]),
+            ("b".to_string(), vec![BigInt::from(2), BigInt::from(3)]),
+        ];
+        let json = inputs_to_json::<Bn256, _>(inputs);
+        assert_eq!(
+            json["a"],
+            "21888242871839275222246405745257275088548364400416034343698204186575808495616"
+        );
+        assert_eq!(json["b"], serde_json::json!(["2", "3"]));
+
+        assert!(NativeWitnessCalculator::build("/tmp/not_a_circuit").is_err());
+    }
+}
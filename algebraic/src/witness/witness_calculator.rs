@@ -1,13 +1,19 @@
 // copied and modified by https://github.com/arkworks-rs/circom-compat/blob/master/src/witness/witness_calculator.rs
-use crate::bellman_ce::ScalarEngine;
+use crate::bellman_ce::pairing::{bls12_381::Bls12, bn256::Bn256};
+use crate::bellman_ce::{Field, PrimeField, PrimeFieldRepr, ScalarEngine};
 use crate::errors::{EigenError, Result};
+use crate::field_gl::GL;
+use crate::reader::field_modulus_le_bytes;
 use crate::witness::{circom::Wasm, fnv, memory::SafeMemory};
 use anyhow::bail;
-use num::ToPrimitive;
+use num::{Integer, ToPrimitive};
 use num_bigint::BigInt;
 use num_bigint::Sign;
 use num_traits::{One, Zero};
 use serde_json::Value;
+#[cfg(not(feature = "wasm"))]
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use wasmer::{imports, Function, Instance, Memory, MemoryType, Module, Store};
 
@@ -18,9 +24,30 @@ use std::io::{BufWriter, Write};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
+/// The fields of circom circuits that the witness can be natively calculated in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircomField {
+    Bn254,
+    Bls12381,
+    Goldilocks,
+}
+
+/// The witness in the field of the circuit
+#[derive(Clone, Debug)]
+pub enum TypedWitness {
+    Bn254(Vec<<Bn256 as ScalarEngine>::Fr>),
+    Bls12381(Vec<<Bls12 as ScalarEngine>::Fr>),
+    Goldilocks(Vec<<GL as ScalarEngine>::Fr>),
+}
+
+fn prime_of<E: ScalarEngine>() -> BigInt {
+    BigInt::from_bytes_le(Sign::Plus, &field_modulus_le_bytes::<E>())
+}
+
 pub struct WitnessCalculator {
     pub instance: Wasm,
     store: Store,
+    module: Module,
     pub memory: SafeMemory,
     pub n64: u32,
     pub circom_version: u32,
@@ -49,11 +76,120 @@ fn to_array32(s: &BigInt, size: usize) -> Vec<u32> {
     res
 }
 
+// the compiled module is only valid for the same wasm and the same wasmer
+#[cfg(not(feature = "wasm"))]
+fn module_cache_file(cache_dir: &Path, wasm: &[u8]) -> PathBuf {
+    cache_dir.join(format!(
+        "{}-wasmer-{}.module",
+        hex::encode(Sha256::digest(wasm)),
+        wasmer::VERSION
+    ))
+}
+
+/// the sha256 of the serialized module, written next to it
+#[cfg(not(feature = "wasm"))]
+fn module_digest_file(cache_file: &Path) -> PathBuf {
+    cache_file.with_extension("module.sha256")
+}
+
+/// The serialized module in `cache_file` if it's the one written by `from_file_with_cache`: its
+/// sha256 matches the digest written with it, and neither is writable by others.
+#[cfg(not(feature = "wasm"))]
+fn read_module_cache(cache_file: &Path) -> Result<Vec<u8>> {
+    let digest_file = module_digest_file(cache_file);
+    #[cfg(unix)]
+    for file in [cache_file, &digest_file] {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(file)?.permissions().mode() & 0o022 != 0 {
+            bail!(EigenError::InvalidInput {
+                path: file.display().to_string(),
+                msg: "writable by others".to_string()
+            });
+        }
+    }
+    let module = std::fs::read(cache_file)?;
+    let expected = std::fs::read_to_string(&digest_file)?;
+    if hex::encode(Sha256::digest(&module)) != expected.trim() {
+        bail!(EigenError::InvalidInput {
+            path: cache_file.display().to_string(),
+            msg: "the module doesn't match its sha256".to_string()
+        });
+    }
+    Ok(module)
+}
+
+/// Write the serialized module and its digest into `cache_file`, each one is written to a
+/// temporary file and renamed into place, so a reader never sees a torn file.
+#[cfg(not(feature = "wasm"))]
+fn write_module_cache(cache_file: &Path, module: &[u8]) -> Result<()> {
+    let cache_dir = cache_file.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(cache_dir)?;
+    let digest = hex::encode(Sha256::digest(module));
+    for (file, bytes) in [
+        (cache_file.to_path_buf(), module),
+        (module_digest_file(cache_file), digest.as_bytes()),
+    ] {
+        let mut tmp = tempfile::NamedTempFile::new_in(cache_dir)?;
+        tmp.write_all(bytes)?;
+        tmp.persist(&file)?;
+    }
+    Ok(())
+}
+
 impl WitnessCalculator {
-    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
-        let mut store = Store::default();
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let store = Store::default();
         let module = Module::from_file(&store, path).expect("correct wtns file");
-        let mut wtns = Self::from_module(&mut store, module).unwrap();
+        Self::from_store_and_module(store, module)
+    }
+
+    /// Same as `from_file`, but reuses the compiled module serialized in `cache_dir`, and
+    /// caches it there on a miss.
+    #[cfg(not(feature = "wasm"))]
+    pub fn from_file_with_cache(
+        path: impl AsRef<Path>,
+        cache_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let store = Store::default();
+        let wasm = std::fs::read(path)?;
+        let cache_file = module_cache_file(cache_dir.as_ref(), &wasm);
+
+        let mut cached = None;
+        if cache_file.exists() {
+            match read_module_cache(&cache_file) {
+                // SAFETY: the bytes are the ones serialized below for the same wasm and wasmer
+                // version, which is guaranteed by the cache key and their sha256.
+                Ok(bytes) => match unsafe { Module::deserialize(&store, bytes) } {
+                    Ok(module) => cached = Some(module),
+                    Err(e) => log::warn!("invalid module cache {:?}: {}", cache_file, e),
+                },
+                Err(e) => log::warn!("invalid module cache {:?}: {}", cache_file, e),
+            }
+        }
+        let module = match cached {
+            Some(module) => {
+                log::debug!("load compiled module from {:?}", cache_file);
+                module
+            }
+            None => {
+                let module = Module::new(&store, &wasm)?;
+                // the cache is an optimization, the witness is computed without it
+                match module
+                    .serialize()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|bytes| write_module_cache(&cache_file, &bytes))
+                {
+                    Ok(()) => log::debug!("save compiled module to {:?}", cache_file),
+                    Err(e) => log::warn!("failed to save the module cache {:?}: {}", cache_file, e),
+                }
+                module
+            }
+        };
+        Self::from_store_and_module(store, module)
+    }
+
+    fn from_store_and_module(mut store: Store, module: Module) -> Result<Self> {
+        let mut wtns = Self::from_module(&mut store, module)?;
         wtns.store = store;
         Ok(wtns)
     }
@@ -84,6 +220,7 @@ impl WitnessCalculator {
         // Circom 2 feature flag with version 2
         fn new_circom(
             store: &mut Store,
+            module: Module,
             instance: Wasm,
             memory: Memory,
         ) -> Result<WitnessCalculator> {
@@ -105,22 +242,98 @@ impl WitnessCalculator {
             Ok(WitnessCalculator {
                 instance,
                 store: Store::default(),
+                module,
                 memory: safe_memory,
                 n64,
                 circom_version: version,
             })
         }
 
-        new_circom(store, instance, memory)
+        new_circom(store, module, instance, memory)
+    }
+
+    /// The field of the circuit, detected by the prime reported by the wasm
+    pub fn field(&self) -> Result<CircomField> {
+        let prime = &self.memory.prime;
+        if *prime == prime_of::<Bn256>() {
+            Ok(CircomField::Bn254)
+        } else if *prime == prime_of::<Bls12>() {
+            Ok(CircomField::Bls12381)
+        } else if *prime == prime_of::<GL>() {
+            Ok(CircomField::Goldilocks)
+        } else {
+            bail!(EigenError::FieldMismatch {
+                expected: "one of bn254, bls12381 or goldilocks".to_string(),
+                found: prime.to_string(),
+            })
+        }
+    }
+
+    /// Check the circuit is defined over the field of `E`
+    pub fn check_field<E: ScalarEngine>(&self) -> Result<()> {
+        let expected = prime_of::<E>();
+        if self.memory.prime != expected {
+            bail!(EigenError::FieldMismatch {
+                expected: expected.to_string(),
+                found: self.memory.prime.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Calculate the witness as elements of the field of `E`, which must be the field of the
+    /// circuit.
+    pub fn calculate_witness_fr<E: ScalarEngine, I: IntoIterator<Item = (String, Vec<BigInt>)>>(
+        &mut self,
+        inputs: I,
+        sanity_check: bool,
+    ) -> Result<Vec<E::Fr>> {
+        self.check_field::<E>()?;
+        let wtns_u32 = self.calculate_witness_circom(inputs, sanity_check)?;
+        let n32 = self.instance.get_field_num_len32(&mut self.store)? as usize;
+
+        // each element is n32 little-endian u32 limbs, in normal form
+        let mut bytes = Vec::with_capacity(n32 * 4);
+        wtns_u32
+            .chunks(n32)
+            .map(|limbs| -> Result<E::Fr> {
+                bytes.clear();
+                for limb in limbs {
+                    bytes.write_u32::<LittleEndian>(*limb)?;
+                }
+                let mut repr = E::Fr::zero().into_repr();
+                repr.read_le(&bytes[..])?;
+                Ok(E::Fr::from_repr(repr)?)
+            })
+            .collect()
     }
 
+    /// Calculate the witness in the field of the circuit
+    pub fn calculate_typed_witness<I: IntoIterator<Item = (String, Vec<BigInt>)>>(
+        &mut self,
+        inputs: I,
+        sanity_check: bool,
+    ) -> Result<TypedWitness> {
+        Ok(match self.field()? {
+            CircomField::Bn254 => {
+                TypedWitness::Bn254(self.calculate_witness_fr::<Bn256, _>(inputs, sanity_check)?)
+            }
+            CircomField::Bls12381 => {
+                TypedWitness::Bls12381(self.calculate_witness_fr::<Bls12, _>(inputs, sanity_check)?)
+            }
+            CircomField::Goldilocks => {
+                TypedWitness::Goldilocks(self.calculate_witness_fr::<GL, _>(inputs, sanity_check)?)
+            }
+        })
+    }
+
+    /// Calculate the witness of an input set. The instance is reinitialized on each call, so one
+    /// calculator can be reused for many input sets.
     pub fn calculate_witness<I: IntoIterator<Item = (String, Vec<BigInt>)>>(
         &mut self,
-        // store: &mut Store,
         inputs: I,
         sanity_check: bool,
     ) -> Result<Vec<BigInt>> {
-        self.instance.init(&mut self.store, sanity_check)?;
         let wtns_u32 = self.calculate_witness_circom(inputs, sanity_check)?;
         let n32 = self.instance.get_field_num_len32(&mut self.store)?;
 
@@ -141,10 +354,54 @@ impl WitnessCalculator {
         inputs: I,
         sanity_check: bool,
     ) -> Result<Vec<u32>> {
-        self.instance.init(&mut self.store, sanity_check)?;
         self.calculate_witness_circom(inputs, sanity_check)
     }
 
+    /// Calculate the witnesses of many input sets by `num_instances` wasm instances in parallel,
+    /// the results are in the same order as `inputs`.
+    #[cfg(not(feature = "wasm"))]
+    pub fn calculate_witness_batch<I>(
+        &self,
+        inputs: Vec<I>,
+        sanity_check: bool,
+        num_instances: usize,
+    ) -> Result<Vec<Vec<BigInt>>>
+    where
+        I: IntoIterator<Item = (String, Vec<BigInt>)> + Send,
+    {
+        if inputs.is_empty() {
+            return Ok(vec![]);
+        }
+        let chunk_size = (inputs.len() + num_instances.max(1) - 1) / num_instances.max(1);
+        let mut chunks: Vec<Vec<I>> = vec![];
+        let mut inputs = inputs.into_iter().peekable();
+        while inputs.peek().is_some() {
+            chunks.push(inputs.by_ref().take(chunk_size).collect());
+        }
+
+        let witnesses = std::thread::scope(|scope| {
+            let handles = chunks
+                .into_iter()
+                .map(|chunk| {
+                    let engine = self.store.engine().clone();
+                    let module = self.module.clone();
+                    scope.spawn(move || -> Result<Vec<Vec<BigInt>>> {
+                        let mut wtns = Self::from_store_and_module(Store::new(engine), module)?;
+                        chunk
+                            .into_iter()
+                            .map(|input| wtns.calculate_witness(input, sanity_check))
+                            .collect()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("witness calculation thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok(witnesses.into_iter().flatten().collect())
+    }
+
     // Circom 2 feature flag with version 2
     fn calculate_witness_circom<I: IntoIterator<Item = (String, Vec<BigInt>)>>(
         &mut self,
@@ -160,6 +417,8 @@ impl WitnessCalculator {
             let (msb, lsb) = fnv(&name);
 
             for (i, value) in values.into_iter().enumerate() {
+                // negative or overflowed inputs are reduced modulo the prime, as circom does
+                let value = value.mod_floor(&self.memory.prime);
                 let f_arr = to_array32(&value, n32 as usize);
                 for j in 0..n32 {
                     self.instance.write_shared_rw_memory(
@@ -368,6 +627,7 @@ mod runtime {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::witness::load_input_for_witness;
     use std::{collections::HashMap, path::PathBuf};
 
     struct TestCase<'a> {
@@ -423,6 +683,117 @@ mod tests {
         });
     }
 
+    #[test]
+    fn reuse_and_batch() {
+        let inputs = [
+            "mycircuit-input1.json",
+            "mycircuit-input2.json",
+            "mycircuit-input3.json",
+        ]
+        .iter()
+        .map(|f| load_input_for_witness(&root_path(&format!("test-vectors/{}", f))).unwrap())
+        .collect::<Vec<_>>();
+
+        let mut wtns =
+            WitnessCalculator::from_file(root_path("test-vectors/mycircuit.wasm")).unwrap();
+        let expected = inputs
+            .iter()
+            .map(|input| wtns.calculate_witness(input.clone(), true).unwrap())
+            .collect::<Vec<_>>();
+        assert_ne!(expected[0], expected[1]);
+
+        let res = wtns.calculate_witness_batch(inputs, true, 2).unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn module_cache() {
+        let cache_dir = std::env::temp_dir().join("eigen_witness_module_cache");
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let input =
+            load_input_for_witness(&root_path("test-vectors/mycircuit-input1.json")).unwrap();
+
+        let mut wtns = WitnessCalculator::from_file_with_cache(
+            root_path("test-vectors/mycircuit.wasm"),
+            &cache_dir,
+        )
+        .unwrap();
+        let expected = wtns.calculate_witness(input.clone(), true).unwrap();
+        // the module and its sha256
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
+        let wasm = std::fs::read(root_path("test-vectors/mycircuit.wasm")).unwrap();
+        let cache_file = module_cache_file(&cache_dir, &wasm);
+        assert!(read_module_cache(&cache_file).is_ok());
+
+        // hit the cache
+        let mut wtns = WitnessCalculator::from_file_with_cache(
+            root_path("test-vectors/mycircuit.wasm"),
+            &cache_dir,
+        )
+        .unwrap();
+        assert_eq!(
+            wtns.calculate_witness(input.clone(), true).unwrap(),
+            expected
+        );
+
+        // a planted module is not loaded, but compiled again
+        std::fs::write(&cache_file, b"not a module").unwrap();
+        assert!(read_module_cache(&cache_file).is_err());
+        let mut wtns = WitnessCalculator::from_file_with_cache(
+            root_path("test-vectors/mycircuit.wasm"),
+            &cache_dir,
+        )
+        .unwrap();
+        assert_eq!(
+            wtns.calculate_witness(input.clone(), true).unwrap(),
+            expected
+        );
+        assert!(read_module_cache(&cache_file).is_ok());
+
+        // a cache which can't be written is skipped
+        let not_a_dir = cache_dir.join("not_a_dir");
+        std::fs::write(&not_a_dir, b"").unwrap();
+        let mut wtns = WitnessCalculator::from_file_with_cache(
+            root_path("test-vectors/mycircuit.wasm"),
+            &not_a_dir,
+        )
+        .unwrap();
+        assert_eq!(wtns.calculate_witness(input, true).unwrap(), expected);
+    }
+
+    #[test]
+    fn negative_input() {
+        let input =
+            crate::witness::parse_input_for_witness(r#"{"a": "-12345678", "b": "0xb"}"#).unwrap();
+        let mut wtns =
+            WitnessCalculator::from_file(root_path("test-vectors/mycircuit.wasm")).unwrap();
+        let res = wtns.calculate_witness(input, true).unwrap();
+        let expected =
+            load_input_for_witness(&root_path("test-vectors/mycircuit-input2.json")).unwrap();
+        assert_eq!(res, wtns.calculate_witness(expected, true).unwrap());
+    }
+
+    #[test]
+    fn typed_witness() {
+        let input =
+            load_input_for_witness(&root_path("test-vectors/mycircuit-input2.json")).unwrap();
+        let mut wtns =
+            WitnessCalculator::from_file(root_path("test-vectors/mycircuit.wasm")).unwrap();
+        assert_eq!(wtns.field().unwrap(), CircomField::Bn254);
+
+        let expected = wtns.calculate_witness(input.clone(), true).unwrap();
+        let res = match wtns.calculate_typed_witness(input.clone(), true).unwrap() {
+            TypedWitness::Bn254(w) => w,
+            _ => panic!("the circuit is over bn254"),
+        };
+        for (r, w) in res.iter().zip(expected) {
+            assert_eq!(crate::utils::repr_to_big(r.into_repr()), w.to_string());
+        }
+
+        assert!(wtns.check_field::<GL>().is_err());
+        assert!(wtns.calculate_witness_fr::<Bls12, _>(input, true).is_err());
+    }
+
     // TODO: test complex samples
 
     fn run_test(case: TestCase) {
@@ -0,0 +1,308 @@
+use crate::diagnostics::Diagnostic;
+use crate::errors::{bail, DslError, Result};
+use crate::input_user::Input;
+use crate::{CompileOptions, CIRCOM_VERSION};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The record of the last compilation into an output directory, `<circuit>.cache.json`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    key: String,
+    /// output file, relative to the output directory => sha256
+    outputs: BTreeMap<String, String>,
+    /// the warnings of the compilation, reported again when the outputs are reused
+    #[serde(default)]
+    warnings: Vec<Diagnostic>,
+}
+
+/// A content addressed cache of the compilation outputs.
+///
+/// The key is the sha256 of the compiler version, the options, and the contents of the main
+/// file with its transitive includes. The outputs are reused if the stamp of the output
+/// directory has the same key, and none of the outputs was changed or removed since.
+pub(crate) struct Cache {
+    key: String,
+    output: PathBuf,
+    stamp: PathBuf,
+    files: Vec<PathBuf>,
+    folders: Vec<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(opts: &CompileOptions, input: &Input, output: &Path) -> Result<Self> {
+        let mut files = vec![];
+        let mut folders = vec![];
+        if opts.r1cs {
+            files.push(input.out_r1cs.clone());
+        }
+        if opts.sym {
+            files.push(input.out_sym.clone());
+        }
+        if opts.json_constraints {
+            files.push(input.out_json_constraints.clone());
+        }
+        if opts.wasm || opts.wat {
+            folders.push(input.out_js_folder.clone());
+        }
+        if opts.c {
+            folders.push(input.out_c_folder.clone());
+        }
+        Ok(Self {
+            key: cache_key(opts)?,
+            output: output.to_path_buf(),
+            stamp: output.join(format!("{}.cache.json", input.out_wasm_name)),
+            files,
+            folders,
+        })
+    }
+
+    /// The warnings of the outputs if they were compiled with the same key, and are unchanged
+    pub fn fresh_warnings(&self) -> Option<Vec<Diagnostic>> {
+        let stamp = std::fs::read(&self.stamp).ok()?;
+        match (serde_json::from_slice::<Stamp>(&stamp), self.hash_outputs()) {
+            (Ok(stamp), Ok(outputs)) if stamp.key == self.key && stamp.outputs == outputs => {
+                Some(stamp.warnings)
+            }
+            _ => None,
+        }
+    }
+
+    /// remove the stamp before compiling, so that a failed compilation is never reused
+    pub fn invalidate(&self) -> Result<()> {
+        match std::fs::remove_file(&self.stamp) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn store(&self, warnings: &[Diagnostic]) -> Result<()> {
+        let stamp = Stamp {
+            key: self.key.clone(),
+            outputs: self.hash_outputs()?,
+            warnings: warnings.to_vec(),
+        };
+        std::fs::write(&self.stamp, serde_json::to_string_pretty(&stamp)?)?;
+        Ok(())
+    }
+
+    fn hash_outputs(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = self.files.clone();
+        for folder in &self.folders {
+            for entry in std::fs::read_dir(folder)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    files.push(entry.path());
+                }
+            }
+        }
+        let mut outputs = BTreeMap::new();
+        for file in files {
+            let name = file.strip_prefix(&self.output).unwrap_or(&file);
+            outputs.insert(
+                name.to_string_lossy().to_string(),
+                hex::encode(Sha256::digest(std::fs::read(&file)?)),
+            );
+        }
+        Ok(outputs)
+    }
+}
+
+/// The cache key of the compilation by `opts`
+pub(crate) fn cache_key(opts: &CompileOptions) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut update = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    update(CIRCOM_VERSION.as_bytes());
+    update(env!("CARGO_PKG_VERSION").as_bytes());
+    update(opts.prime.as_bytes());
+    update(format!("{:?}", opts.simplification).as_bytes());
+    update(&[
+        opts.r1cs as u8,
+        opts.sym as u8,
+        opts.wasm as u8,
+        opts.wat as u8,
+        opts.c as u8,
+        opts.json_constraints as u8,
+        opts.main_inputs as u8,
+    ]);
+    for source in sources(&opts.input, &opts.link_directories)? {
+        update(&std::fs::read(source)?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// The main file and its transitive includes, in the order the parser visits them.
+///
+/// Like circom, an include is resolved against the directory of the including file first, then
+/// against the link directories.
+fn sources(main: &Path, link_directories: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let main = std::fs::canonicalize(main)?;
+    let mut visited = HashSet::new();
+    let mut stack = vec![main];
+    let mut sources = vec![];
+    while let Some(file) = stack.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+        let source = std::fs::read_to_string(&file)?;
+        let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut includes = vec![];
+        for name in includes_of(&source) {
+            match std::iter::once(&dir)
+                .chain(link_directories)
+                .find_map(|lib| std::fs::canonicalize(lib.join(&name)).ok())
+            {
+                Some(include) => includes.push(include),
+                None => bail!(DslError::CircomCompileError(format!(
+                    "include {} of {} not found",
+                    name,
+                    file.display()
+                ))),
+            }
+        }
+        // pop in source order
+        stack.extend(includes.into_iter().rev());
+        sources.push(file);
+    }
+    Ok(sources)
+}
+
+/// The file names of the `include "<name>";` statements, ignoring the comments
+fn includes_of(source: &str) -> Vec<String> {
+    let mut code = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        code.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                code.push(' ');
+            }
+            ('"', _) => {
+                code.push(c);
+                for c in chars.by_ref() {
+                    code.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            _ => code.push(c),
+        }
+    }
+
+    let mut includes = vec![];
+    let mut rest = code.as_str();
+    while let Some(pos) = rest.find("include") {
+        let before = rest[..pos].chars().next_back();
+        let after = rest[pos + "include".len()..].trim_start();
+        rest = &rest[pos + "include".len()..];
+        if matches!(before, Some(c) if c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        if let Some(name) = after.strip_prefix('"').and_then(|s| s.split('"').next()) {
+            includes.push(name.to_string());
+        }
+    }
+    includes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    const CIRCUIT_FILE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../algebraic/test-vectors/mycircuit.circom"
+    );
+
+    #[test]
+    fn parse_includes() {
+        let source = r#"
+            pragma circom 2.0.0;
+            include "a.circom";
+            // include "b.circom";
+            /* include "c.circom"; */
+            include"d/e.circom";
+            template no_include() {}
+        "#;
+        assert_eq!(includes_of(source), vec!["a.circom", "d/e.circom"]);
+    }
+
+    #[test]
+    fn key_of_transitive_includes() {
+        let dir = Path::new("/tmp/dsl_compile_cache_key");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("main.circom"),
+            "include \"mul.circom\";\ncomponent main = Mul();\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lib/mul.circom"),
+            "include \"../common.circom\";\ntemplate Mul() {}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("common.circom"), "template Common() {}\n").unwrap();
+
+        let opts = CompileOptions::new(dir.join("main.circom")).link_directory(dir.join("lib"));
+        assert_eq!(
+            sources(&opts.input, &opts.link_directories).unwrap().len(),
+            3
+        );
+        let key = cache_key(&opts).unwrap();
+        assert_eq!(key, cache_key(&opts).unwrap());
+        assert_ne!(key, cache_key(&opts.clone().prime("goldilocks")).unwrap());
+
+        std::fs::write(dir.join("common.circom"), "template Common2() {}\n").unwrap();
+        assert_ne!(key, cache_key(&opts).unwrap());
+
+        // unresolved include
+        let opts = CompileOptions::new(dir.join("main.circom"));
+        assert!(cache_key(&opts).is_err());
+    }
+
+    #[test]
+    fn reuse_outputs() {
+        let output = Path::new("/tmp/dsl_compile_cache_outputs");
+        std::fs::create_dir_all(output).unwrap();
+        let opts = CompileOptions::new(CIRCUIT_FILE).cache(true);
+        compile(&opts, output).unwrap();
+
+        let cache = Cache::new(&opts, &Input::new(&opts, output).unwrap(), output).unwrap();
+        assert!(cache.fresh_warnings().is_some());
+        let r1cs = output.join("mycircuit.r1cs");
+        let modified = std::fs::metadata(&r1cs).unwrap().modified().unwrap();
+        compile(&opts, output).unwrap();
+        assert_eq!(
+            modified,
+            std::fs::metadata(&r1cs).unwrap().modified().unwrap()
+        );
+
+        // a changed output is compiled again
+        std::fs::write(&r1cs, b"").unwrap();
+        assert!(cache.fresh_warnings().is_none());
+        compile(&opts, output).unwrap();
+        assert!(cache.fresh_warnings().is_some());
+    }
+}
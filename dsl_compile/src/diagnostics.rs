@@ -0,0 +1,467 @@
+use program_structure::error_definition::{Report, ReportLabel};
+use program_structure::file_definition::FileLibrary;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A location in a source file, `start..end` is the byte span
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticLabel {
+    pub primary: bool,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+/// A machine readable circom report
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// the circom report code, like `P1001`
+    pub code: String,
+    pub message: String,
+    /// the location of the first primary label
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub span: Option<(usize, usize)>,
+    pub labels: Vec<DiagnosticLabel>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub(crate) fn from_report(report: &Report, files: &FileLibrary) -> Self {
+        let label = |l: &ReportLabel, primary: bool| DiagnosticLabel {
+            primary,
+            file: files
+                .to_storage()
+                .get(l.file_id)
+                .map(|f| f.name().to_string()),
+            line: files.get_line(l.range.start, l.file_id),
+            start: l.range.start,
+            end: l.range.end,
+            message: l.message.clone(),
+        };
+        let labels = report
+            .get_primary()
+            .iter()
+            .map(|l| label(l, true))
+            .chain(report.get_secondary().iter().map(|l| label(l, false)))
+            .collect::<Vec<_>>();
+        let first = labels.iter().find(|l| l.primary);
+        Diagnostic {
+            severity: if report.is_warning() {
+                Severity::Warning
+            } else {
+                Severity::Error
+            },
+            code: report.get_code().to_string(),
+            message: report.get_message().clone(),
+            file: first.and_then(|l| l.file.clone()),
+            line: first.and_then(|l| l.line),
+            span: first.map(|l| (l.start, l.end)),
+            notes: report.get_notes().clone(),
+            labels,
+        }
+    }
+
+    /// An error without location, for the phases which don't return their reports
+    pub(crate) fn error(code: &str, message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.to_string(),
+            message: message.to_string(),
+            file: None,
+            line: None,
+            span: None,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+}
+
+/// Parse the reports printed by circom, `Report::print_reports` renders them by codespan, like
+///
+/// ```text
+/// error[T3001]: Exception caused by invalid access
+///   ┌─ /tmp/main.circom:5:5
+///   │
+/// 5 │     b <== a[2];
+///   │     ^^^^^^^^^^ found here
+///   │
+///   = call trace:
+/// ```
+///
+/// The lines which aren't part of a report are returned as they are.
+pub(crate) fn parse_printed_reports(printed: &str) -> (Vec<Diagnostic>, String) {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let mut rest = String::new();
+    // the file and line of the last source line of the current report
+    let mut location: Option<(String, usize)> = None;
+    let mut in_report = false;
+    for raw in printed.lines() {
+        let line = strip_ansi(raw);
+        if let Some(diagnostic) = parse_header(&line) {
+            diagnostics.push(diagnostic);
+            location = None;
+            in_report = true;
+            continue;
+        }
+        let current = match diagnostics.last_mut() {
+            Some(current) if in_report => current,
+            _ => {
+                rest.push_str(raw);
+                rest.push('\n');
+                continue;
+            }
+        };
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        } else if let Some(at) = trimmed.strip_prefix("┌─ ") {
+            let mut parts = at.rsplitn(3, ':');
+            let (_column, line, file) = (parts.next(), parts.next(), parts.next());
+            if let (Some(file), Some(line)) = (file, line.and_then(|l| l.parse().ok())) {
+                location = Some((file.to_string(), line));
+            }
+        } else if let Some(note) = trimmed.strip_prefix("= ") {
+            current.notes.push(note.to_string());
+        } else if let Some(note) = current
+            .notes
+            .last_mut()
+            .filter(|_| line.starts_with("    "))
+        {
+            // the next lines of a note are indented under its first one
+            note.push('\n');
+            note.push_str(trimmed);
+        } else if let Some((gutter, text)) = line.split_once('│') {
+            let text = text.strip_prefix(' ').unwrap_or(text);
+            match gutter.trim().parse::<usize>() {
+                Ok(number) => {
+                    if let Some((_, line)) = &mut location {
+                        *line = number;
+                    }
+                }
+                Err(_) => {
+                    if let Some(label) = parse_label(text, location.as_ref()) {
+                        if label.primary && current.file.is_none() {
+                            current.file = label.file.clone();
+                            current.line = label.line;
+                            current.span = Some((label.start, label.end));
+                        }
+                        current.labels.push(label);
+                    }
+                }
+            }
+        } else if trimmed != "·" {
+            in_report = false;
+            rest.push_str(raw);
+            rest.push('\n');
+        }
+    }
+    for diagnostic in diagnostics.iter_mut() {
+        if diagnostic.file.is_none() {
+            if let Some(label) = diagnostic.labels.first() {
+                diagnostic.file = label.file.clone();
+                diagnostic.line = label.line;
+            }
+        }
+    }
+    (diagnostics, rest)
+}
+
+/// `error[P1012]: message`, the code is optional
+fn parse_header(line: &str) -> Option<Diagnostic> {
+    let (head, message) = line.split_once(": ")?;
+    let (severity, code) = match head.split_once('[') {
+        Some((severity, code)) => (severity, code.strip_suffix(']')?),
+        None => (head, ""),
+    };
+    let severity = match severity {
+        "error" | "bug" => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => return None,
+    };
+    let mut diagnostic = Diagnostic::error(code, message);
+    diagnostic.severity = severity;
+    Some(diagnostic)
+}
+
+/// The marks under a source line, `^^^ message` for a primary label and `--- message` for a
+/// secondary one. The span is found by the column of the marks in the source file.
+fn parse_label(text: &str, location: Option<&(String, usize)>) -> Option<DiagnosticLabel> {
+    let column = text.find(|c: char| c == '^' || c == '-')?;
+    // the end of a multi-line label, `╰────^ message`
+    let multi_line = text[..column]
+        .chars()
+        .any(|c| !c.is_whitespace() && c != '│');
+    if text[..column]
+        .chars()
+        .any(|c| !c.is_whitespace() && c != '│' && c != '╰' && c != '─')
+    {
+        return None;
+    }
+    let mark = text[column..].chars().next()?;
+    let width = text[column..].chars().take_while(|c| *c == mark).count();
+    let message = text[column + width..].trim().to_string();
+    let (start, end) = match location {
+        Some((file, line)) if !multi_line => {
+            let start = line_offset(file, *line, text[..column].chars().count()).unwrap_or(0);
+            (start, start + width)
+        }
+        _ => (0, 0),
+    };
+    Some(DiagnosticLabel {
+        primary: mark == '^',
+        file: location.map(|(file, _)| file.clone()),
+        line: location.map(|(_, line)| *line),
+        start,
+        end,
+        message,
+    })
+}
+
+/// The byte offset of the `column`th character of the 1-based `line` of `file`
+fn line_offset(file: &str, line: usize, column: usize) -> Option<usize> {
+    let source = std::fs::read_to_string(file.trim_matches('"')).ok()?;
+    let start = source
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum::<usize>();
+    let in_line = source[start..].char_indices().nth(column).map(|(i, _)| i)?;
+    Some(start + in_line)
+}
+
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI, `ESC [ parameters final`, the final byte is in `@..=~`
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}[{}]: {}", severity, self.code, self.message)?;
+        if let Some(file) = &self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the circom reports go, colored in the terminal, or collected as `Diagnostic`s. The
+/// warnings are also kept, to be stored with the cached outputs.
+pub(crate) struct Reporter<'a> {
+    collect: Option<&'a mut Vec<Diagnostic>>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn terminal() -> Self {
+        Self {
+            collect: None,
+            warnings: vec![],
+        }
+    }
+
+    pub fn collect(diagnostics: &'a mut Vec<Diagnostic>) -> Self {
+        Self {
+            collect: Some(diagnostics),
+            warnings: vec![],
+        }
+    }
+
+    pub fn report(&mut self, reports: &[Report], files: &FileLibrary) {
+        let diagnostics = reports
+            .iter()
+            .map(|r| Diagnostic::from_report(r, files))
+            .collect::<Vec<_>>();
+        self.warnings.extend(
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Warning)
+                .cloned(),
+        );
+        match &mut self.collect {
+            None => Report::print_reports(reports, files),
+            Some(collected) => collected.extend(diagnostics),
+        }
+    }
+
+    /// report again the warnings of the cached outputs
+    pub fn replay(&mut self, warnings: Vec<Diagnostic>) {
+        match &mut self.collect {
+            None => warnings.iter().for_each(|w| log::warn!("{}", w)),
+            Some(collected) => collected.extend(warnings.iter().cloned()),
+        }
+        self.warnings.extend(warnings);
+    }
+
+    /// Run `f`, which prints the circom reports itself, like `build_circuit`. When collecting,
+    /// its stderr is captured and parsed into diagnostics, the other output is printed back.
+    pub fn capture<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let collected = match &mut self.collect {
+            Some(collected) => collected,
+            None => return f(),
+        };
+        let mut redirect = match gag::BufferRedirect::stderr() {
+            Ok(redirect) => redirect,
+            Err(e) => {
+                log::warn!("can't capture the circom reports: {}", e);
+                return f();
+            }
+        };
+        let output = f();
+        let mut printed = String::new();
+        if let Err(e) = redirect.read_to_string(&mut printed) {
+            log::warn!("can't read the circom reports: {}", e);
+        }
+        drop(redirect);
+        let (diagnostics, rest) = parse_printed_reports(&printed);
+        let _ = std::io::stderr().write_all(rest.as_bytes());
+        self.warnings.extend(
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Warning)
+                .cloned(),
+        );
+        collected.extend(diagnostics);
+        output
+    }
+
+    pub fn error(&mut self, code: &str, message: &str) {
+        if let Some(collected) = &mut self.collect {
+            collected.push(Diagnostic::error(code, message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile_with_diagnostics, CompileOptions, Severity};
+
+    #[test]
+    fn collect_parse_errors() {
+        let dir = "/tmp/dsl_compile_diagnostics";
+        std::fs::create_dir_all(dir).unwrap();
+        let input = format!("{}/broken.circom", dir);
+        std::fs::write(
+            &input,
+            "pragma circom 2.0.0;\ntemplate A() {\n    signal input a\n}\ncomponent main = A();\n",
+        )
+        .unwrap();
+
+        let mut diagnostics = vec![];
+        let opts = CompileOptions::new(&input);
+        assert!(compile_with_diagnostics(&opts, dir, &mut diagnostics).is_err());
+        let error = diagnostics
+            .iter()
+            .find(|d| d.severity == Severity::Error)
+            .unwrap();
+        assert!(error.code.starts_with('P'));
+        assert!(error.file.as_ref().unwrap().ends_with("broken.circom"));
+        assert!(error.span.is_some());
+
+        let json = serde_json::to_value(&diagnostics).unwrap();
+        assert_eq!(json[0]["severity"], "error");
+    }
+
+    #[test]
+    fn collect_constraint_generation_errors() {
+        let dir = "/tmp/dsl_compile_generation_diagnostics";
+        std::fs::create_dir_all(dir).unwrap();
+        let input = format!("{}/false_assert.circom", dir);
+        std::fs::write(
+            &input,
+            "pragma circom 2.0.0;\ntemplate A() {\n    assert(1 == 2);\n    signal input a;\n}\ncomponent main = A();\n",
+        )
+        .unwrap();
+
+        let mut diagnostics = vec![];
+        let opts = CompileOptions::new(&input);
+        assert!(compile_with_diagnostics(&opts, dir, &mut diagnostics).is_err());
+        let error = diagnostics
+            .iter()
+            .find(|d| d.severity == Severity::Error && !d.labels.is_empty())
+            .unwrap();
+        assert!(error.file.as_ref().unwrap().contains("false_assert.circom"));
+        assert_eq!(error.line, Some(3));
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.code == "constraint_generation"));
+    }
+
+    #[test]
+    fn parse_printed_report() {
+        let printed = "\x1b[1m\x1b[38;5;9merror[T3001]\x1b[0m\x1b[1m: False assert reached\x1b[0m\n  ┌─ /tmp/none.circom:3:5\n  │\n3 │     assert(1 == 2);\n  │     ^^^^^^^^^^^^^^ found here\n  │\n  = call trace:\n    ->A\n\nprogress\n";
+        let (diagnostics, rest) = super::parse_printed_reports(printed);
+        assert_eq!(diagnostics.len(), 1);
+        let error = &diagnostics[0];
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(error.code, "T3001");
+        assert_eq!(error.message, "False assert reached");
+        assert_eq!(error.file.as_deref(), Some("/tmp/none.circom"));
+        assert_eq!(error.line, Some(3));
+        assert_eq!(error.labels[0].message, "found here");
+        assert_eq!(error.notes, vec!["call trace:\n->A".to_string()]);
+        assert_eq!(rest, "progress\n");
+    }
+
+    #[test]
+    fn replay_cached_warnings() {
+        let dir = "/tmp/dsl_compile_cached_warnings";
+        std::fs::create_dir_all(dir).unwrap();
+        let input = format!("{}/no_pragma.circom", dir);
+        // no pragma version is a warning of the parser
+        std::fs::write(
+            &input,
+            "template A() {\n    signal input a;\n    signal output b;\n    b <== a * a;\n}\ncomponent main = A();\n",
+        )
+        .unwrap();
+        let opts = CompileOptions::new(&input).cache(true);
+        let warnings = |diagnostics: &[crate::Diagnostic]| {
+            diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Warning)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let mut compiled = vec![];
+        compile_with_diagnostics(&opts, dir, &mut compiled).unwrap();
+        assert!(!warnings(&compiled).is_empty());
+        // hit the cache
+        let mut cached = vec![];
+        compile_with_diagnostics(&opts, dir, &mut cached).unwrap();
+        assert_eq!(warnings(&compiled), warnings(&cached));
+    }
+}
@@ -13,6 +13,8 @@ pub struct ExecutionConfig {
     pub flag_s: bool,
     pub flag_f: bool,
     pub flag_p: bool,
+    /// the threads of the parallel simplification
+    pub workers: Option<usize>,
     pub flag_verbose: bool,
     pub inspect_constraints_flag: bool,
     pub sym_flag: bool,
@@ -23,6 +25,11 @@ pub struct ExecutionConfig {
 }
 
 pub fn execute_project(program_archive: ProgramArchive, config: ExecutionConfig) -> Result<VCP> {
+    let workers = if config.flag_p { config.workers } else { None };
+    with_workers(workers, move || build_project(program_archive, config))
+}
+
+fn build_project(program_archive: ProgramArchive, config: ExecutionConfig) -> Result<VCP> {
     use constraint_generation::{build_circuit, BuildConfig};
     let debug = DebugWriter::new(config.json_constraints).unwrap();
     let build_config = BuildConfig {
@@ -58,6 +65,68 @@ pub fn execute_project(program_archive: ProgramArchive, config: ExecutionConfig)
     }
 }
 
+/// The stack of the build thread, circom's execution recurses over the syntax tree.
+const BUILD_STACK_SIZE: usize = 64 << 20;
+
+/// Run `build` with at most `workers` threads of parallel simplification.
+///
+/// circom sizes its thread pool by the CPUs the calling thread can run on, and its threads
+/// inherit the CPU affinity of their creator. So `build` runs on a dedicated thread, whose
+/// affinity is narrowed to `workers` of the allowed CPUs before it starts. The affinity of the
+/// caller is never changed.
+#[cfg(target_os = "linux")]
+fn with_workers<T: Send>(workers: Option<usize>, build: impl FnOnce() -> T + Send) -> T {
+    let workers = match workers {
+        Some(workers) => workers.max(1),
+        None => return build(),
+    };
+    std::thread::scope(|scope| {
+        let handle = std::thread::Builder::new()
+            .name("circom-build".to_string())
+            .stack_size(BUILD_STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                if let Err(e) = limit_cpus(workers) {
+                    log::warn!("{}, use all the CPUs", e);
+                }
+                build()
+            })
+            .expect("failed to spawn the circom build thread");
+        match handle.join() {
+            Ok(output) => output,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+/// Narrow the CPU affinity of the current thread to the first `workers` allowed CPUs.
+#[cfg(target_os = "linux")]
+fn limit_cpus(workers: usize) -> std::result::Result<(), &'static str> {
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    unsafe {
+        let mut allowed: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut allowed) != 0 {
+            return Err("sched_getaffinity failed");
+        }
+        let mut limited: libc::cpu_set_t = std::mem::zeroed();
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| libc::CPU_ISSET(*cpu, &allowed))
+            .take(workers)
+            .for_each(|cpu| libc::CPU_SET(cpu, &mut limited));
+        if libc::sched_setaffinity(0, size, &limited) != 0 {
+            return Err("sched_setaffinity failed");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn with_workers<T: Send>(workers: Option<usize>, build: impl FnOnce() -> T + Send) -> T {
+    if workers.is_some() {
+        log::warn!("the number of workers is only supported on linux, use all the CPUs");
+    }
+    build()
+}
+
 fn generate_output_r1cs(file: &str, exporter: &dyn ConstraintExporter) -> Result<()> {
     if let Result::Ok(()) = exporter.r1cs(file, true) {
         log::trace!("{} {}", Colour::Green.paint("Written successfully:"), file);
@@ -106,3 +175,54 @@ fn generate_json_constraints(debug: &DebugWriter, exporter: &dyn ConstraintExpor
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, CompileOptions};
+    use algebraic::bellman_ce::pairing::bn256::Bn256;
+    use algebraic::r1cs_file::{from_reader, R1CSFile};
+    use std::path::Path;
+
+    const CIRCUITS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../stark-circuits/circuits");
+
+    fn compile_r1cs(opts: CompileOptions, output: &Path) -> R1CSFile<Bn256> {
+        std::fs::create_dir_all(output).unwrap();
+        compile(&opts.sym(false).wasm(false), output).unwrap();
+        let mut file = from_reader(std::fs::File::open(output.join("main.r1cs")).unwrap()).unwrap();
+        // the order of the constraints is not part of the r1cs semantics
+        file.constraints.sort_by_key(|c| format!("{:?}", c));
+        file
+    }
+
+    #[test]
+    fn parallel_simplification_is_serial() {
+        let dir = Path::new("/tmp/dsl_compile_parallel");
+        std::fs::create_dir_all(dir).unwrap();
+        let main = dir.join("main.circom");
+        std::fs::write(
+            &main,
+            "pragma circom 2.0.0;\ninclude \"poseidon.circom\";\ncomponent main = Poseidon(4);\n",
+        )
+        .unwrap();
+        let opts = CompileOptions::new(&main).link_directory(CIRCUITS);
+
+        let serial = compile_r1cs(opts.clone(), &dir.join("serial"));
+        for (name, opts) in [
+            ("parallel", opts.clone().parallel_simplification(true)),
+            (
+                "workers",
+                opts.clone().parallel_simplification(true).workers(2),
+            ),
+        ] {
+            let parallel = compile_r1cs(opts, &dir.join(name));
+            assert_eq!(serial.header.n_wires, parallel.header.n_wires);
+            assert_eq!(serial.header.n_pub_out, parallel.header.n_pub_out);
+            assert_eq!(serial.header.n_prv_in, parallel.header.n_prv_in);
+            assert_eq!(serial.wire_mapping, parallel.wire_mapping);
+            assert_eq!(
+                format!("{:?}", serial.constraints),
+                format!("{:?}", parallel.constraints)
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::errors::{bail, DslError, Result};
+use crate::{compile, CompileOptions};
+use algebraic::bellman_ce::ScalarEngine;
+use algebraic::circom_circuit::R1CS;
+use algebraic::reader::{field_modulus_le_bytes, load_r1cs_from_bin};
+use algebraic::sym::SymbolTable;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The outputs of a circom compilation, `None` if disabled by the `CompileOptions`
+#[derive(Debug)]
+pub struct CompiledCircuit<E: ScalarEngine> {
+    pub r1cs: Option<R1CS<E>>,
+    /// wire => label of the r1cs, the labels are the ones of the symbol table
+    pub wire_to_label: Option<Vec<usize>>,
+    pub sym: Option<SymbolTable>,
+    pub wasm: Option<Vec<u8>>,
+    pub wat: Option<String>,
+    pub json_constraints: Option<String>,
+    /// files of the C++ witness generator by name, like `main.cpp` and `<circuit>.dat`
+    pub c: Option<BTreeMap<String, Vec<u8>>>,
+}
+
+/// Compile the circuit by `opts`, and return the enabled outputs in memory, with the r1cs
+/// parsed in the field of `E`, which must be the prime of `opts`.
+///
+/// The circom backends can only write files, so the outputs go through a private temporary
+/// directory, which is removed before returning.
+pub fn compile_in_memory<E: ScalarEngine>(opts: &CompileOptions) -> Result<CompiledCircuit<E>> {
+    // created exclusively, so no one else can swap the outputs
+    let scratch = tempfile::Builder::new().prefix("dsl_compile_").tempdir()?;
+    compile(opts, scratch.path())?;
+
+    let name = match opts.input.file_stem().and_then(|s| s.to_str()) {
+        Some(name) => name,
+        None => bail!(DslError::CircomCompileError(format!(
+            "invalid input file {}",
+            opts.input.display()
+        ))),
+    };
+    let output = |file: String| scratch.path().join(file);
+
+    let (r1cs, wire_to_label) = if opts.r1cs {
+        let r1cs_file = output(format!("{}.r1cs", name));
+        let (file, _) = algebraic::r1cs_file::from_reader_streaming::<_, E>(BufReader::new(
+            File::open(&r1cs_file)?,
+        ))?;
+        if file.header.prime_size != field_modulus_le_bytes::<E>() {
+            bail!(DslError::CircomCompileError(format!(
+                "the r1cs is not in the field of prime {}",
+                opts.prime
+            )));
+        }
+        let (r1cs, wire_to_label) = load_r1cs_from_bin(BufReader::new(File::open(&r1cs_file)?))?;
+        (Some(r1cs), Some(wire_to_label))
+    } else {
+        (None, None)
+    };
+    let sym = if opts.sym {
+        Some(SymbolTable::from_file(
+            output(format!("{}.sym", name)).to_str().unwrap(),
+        )?)
+    } else {
+        None
+    };
+    let js_folder = output(format!("{}_js", name));
+    let wasm = if opts.wasm {
+        Some(std::fs::read(js_folder.join(format!("{}.wasm", name)))?)
+    } else {
+        None
+    };
+    let wat = if opts.wat {
+        Some(std::fs::read_to_string(
+            js_folder.join(format!("{}.wat", name)),
+        )?)
+    } else {
+        None
+    };
+    let json_constraints = if opts.json_constraints {
+        Some(std::fs::read_to_string(output(format!(
+            "{}_constraints.json",
+            name
+        )))?)
+    } else {
+        None
+    };
+    let c = if opts.c {
+        Some(read_dir_files(&output(format!("{}_cpp", name)))?)
+    } else {
+        None
+    };
+
+    Ok(CompiledCircuit {
+        r1cs,
+        wire_to_label,
+        sym,
+        wasm,
+        wat,
+        json_constraints,
+        c,
+    })
+}
+
+fn read_dir_files(dir: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.insert(
+                entry.file_name().to_string_lossy().to_string(),
+                std::fs::read(entry.path())?,
+            );
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebraic::bellman_ce::pairing::bn256::Bn256;
+    use algebraic::field_gl::GL;
+
+    const CIRCUIT_FILE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../algebraic/test-vectors/mycircuit.circom"
+    );
+
+    #[test]
+    fn compile_multiplier_in_memory() {
+        let opts = CompileOptions::new(CIRCUIT_FILE).wat(true).c(true);
+        let compiled = compile_in_memory::<Bn256>(&opts).unwrap();
+
+        let r1cs = compiled.r1cs.unwrap();
+        assert_eq!(r1cs.num_inputs, 2);
+        assert_eq!(r1cs.num_variables, 4);
+        assert_eq!(r1cs.constraints.len(), 1);
+        assert_eq!(compiled.wire_to_label.unwrap().len(), 4);
+        let sym = compiled.sym.unwrap();
+        assert!(sym.symbols.iter().any(|s| s.name == "main.c"));
+        assert!(compiled.wasm.unwrap().starts_with(b"\0asm"));
+        assert!(compiled.wat.unwrap().starts_with("(module"));
+        assert!(compiled.c.unwrap().contains_key("mycircuit.cpp"));
+        assert!(compiled.json_constraints.is_none());
+
+        // only the r1cs, and the field must match
+        let opts = CompileOptions::new(CIRCUIT_FILE).sym(false).wasm(false);
+        let compiled = compile_in_memory::<Bn256>(&opts).unwrap();
+        assert!(compiled.r1cs.is_some());
+        assert!(compiled.sym.is_none() && compiled.wasm.is_none());
+        assert!(compile_in_memory::<GL>(&opts).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs make, a C++ compiler, nasm, GMP and nlohmann-json"]
+    fn native_witness_matches_wasm() {
+        use algebraic::witness::{
+            load_input_for_witness, NativeWitnessCalculator, WitnessCalculator,
+        };
+
+        let output = Path::new("/tmp/dsl_compile_native_witness");
+        std::fs::create_dir_all(output).unwrap();
+        crate::compile(&CompileOptions::new(CIRCUIT_FILE).c(true), output).unwrap();
+        let input_file = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../algebraic/test-vectors/mycircuit-input1.json"
+        );
+
+        let native = NativeWitnessCalculator::build(output.join("mycircuit_cpp")).unwrap();
+        let native_wtns = output.join("native.wtns");
+        native
+            .calculate_witness_file(input_file, native_wtns.to_str().unwrap())
+            .unwrap();
+
+        let mut wasm =
+            WitnessCalculator::from_file(output.join("mycircuit_js/mycircuit.wasm")).unwrap();
+        let inputs = load_input_for_witness(input_file).unwrap();
+        let wasm_wtns = output.join("wasm.wtns");
+        let w = wasm.calculate_witness_bin(inputs.clone(), false).unwrap();
+        wasm.save_witness_to_bin_file::<Bn256>(wasm_wtns.to_str().unwrap(), &w)
+            .unwrap();
+        assert_eq!(
+            std::fs::read(native_wtns).unwrap(),
+            std::fs::read(wasm_wtns).unwrap()
+        );
+
+        assert_eq!(
+            native
+                .calculate_witness_fr::<Bn256, _>(inputs.clone())
+                .unwrap(),
+            wasm.calculate_witness_fr::<Bn256, _>(inputs, false)
+                .unwrap()
+        );
+    }
+}
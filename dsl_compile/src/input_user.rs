@@ -1,4 +1,5 @@
 use crate::errors::{bail, DslError, Result};
+use crate::CompileOptions;
 use ansi_term::Colour;
 use std::path::{Path, PathBuf};
 
@@ -28,6 +29,7 @@ pub struct Input {
     pub fast_flag: bool,
     pub reduced_simplification_flag: bool,
     pub parallel_simplification_flag: bool,
+    pub workers: Option<usize>,
     pub inspect_constraints_flag: bool,
     pub no_rounds: usize,
     pub flag_verbose: bool,
@@ -45,12 +47,19 @@ const DAT: &str = "dat";
 const SYM: &str = "sym";
 const JSON: &str = "json";
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum SimplificationStyle {
     O0,
     O1,
     O2(usize),
 }
+impl SimplificationStyle {
+    /// Parse the `--O0`, `--O1` and `--O2=<rounds|full>` flags of circom
+    pub fn from_flags(o_0: bool, o_1: bool, o_2_argument: &str) -> Result<Self> {
+        get_simplification_style(o_0, o_1, !o_2_argument.is_empty(), o_2_argument)
+    }
+}
+
 pub fn get_simplification_style(
     o_0: bool,
     o_1: bool,
@@ -77,20 +86,19 @@ pub fn get_simplification_style(
 }
 
 impl Input {
-    pub fn new(
-        input: &Path,
-        output_path: &Path,
-        o_style: SimplificationStyle,
-        prime: String,
-        paths: Vec<String>,
-    ) -> Result<Input> {
-        let file_name = input.file_stem().unwrap().to_str().unwrap().to_string();
+    pub fn new(opts: &CompileOptions, output_path: &Path) -> Result<Input> {
+        let input = opts.input.as_path();
+        let file_name = match input.file_stem().and_then(|s| s.to_str()) {
+            Some(file_name) => file_name.to_string(),
+            None => bail!(DslError::CircomCompileError(format!(
+                "invalid input file {}",
+                input.display()
+            ))),
+        };
         let output_c_path = Input::build_folder(output_path, &file_name, CPP);
         let output_js_path = Input::build_folder(output_path, &file_name, JS);
-        let mut link_libraries: Vec<PathBuf> = vec![];
-        for path in paths.into_iter() {
-            link_libraries.push(Path::new(&path).to_path_buf());
-        }
+        let link_libraries = opts.link_directories.clone();
+        let o_style = opts.simplification;
 
         let input = input.to_path_buf();
 
@@ -112,13 +120,13 @@ impl Input {
                 &format!("{}_constraints", file_name),
                 JSON,
             ),
-            wat_flag: false,
-            wasm_flag: true,
-            c_flag: false,
-            r1cs_flag: true,
-            sym_flag: true,
-            main_inputs_flag: false,
-            json_constraint_flag: false,
+            wat_flag: opts.wat,
+            wasm_flag: opts.wasm,
+            c_flag: opts.c,
+            r1cs_flag: opts.r1cs,
+            sym_flag: opts.sym,
+            main_inputs_flag: opts.main_inputs,
+            json_constraint_flag: opts.json_constraints,
             json_substitution_flag: false,
             print_ir_flag: false,
             no_rounds: if let SimplificationStyle::O2(r) = o_style {
@@ -128,11 +136,12 @@ impl Input {
             },
             fast_flag: o_style == SimplificationStyle::O0,
             reduced_simplification_flag: o_style == SimplificationStyle::O1,
-            parallel_simplification_flag: false, // TODO
+            parallel_simplification_flag: opts.parallel,
+            workers: opts.workers,
             inspect_constraints_flag: false,
             flag_verbose: false,
             //prime: "bn128".to_string(), //goldilocks
-            prime,
+            prime: opts.prime.clone(),
             link_libraries,
         })
     }
@@ -231,6 +240,9 @@ impl Input {
     pub fn parallel_simplification_flag(&self) -> bool {
         self.parallel_simplification_flag
     }
+    pub fn workers(&self) -> Option<usize> {
+        self.workers
+    }
     pub fn no_rounds(&self) -> usize {
         self.no_rounds
     }
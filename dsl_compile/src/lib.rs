@@ -1,15 +1,25 @@
 //! It's for: Domain Specific Language(DSL) compiler: now only include circom compile.
 
+use crate::diagnostics::Reporter;
 use crate::errors::Result;
 use std::path::Path;
 
+mod cache;
 mod compilation_user;
+mod diagnostics;
 mod errors;
 mod execution_user;
+mod in_memory;
 mod input_user;
+mod options;
 mod parser_user;
 mod type_analysis_user;
 
+pub use diagnostics::{Diagnostic, DiagnosticLabel, Severity};
+pub use in_memory::{compile_in_memory, CompiledCircuit};
+pub use input_user::SimplificationStyle;
+pub use options::CompileOptions;
+
 /// Align with https://github.com/iden3/circom/blob/master/circom/Cargo.toml#L3
 const CIRCOM_VERSION: &str = "2.1.2";
 
@@ -23,27 +33,91 @@ pub fn circom_compiler(
     no_simplification: bool,
     reduced_simplification: bool,
 ) -> Result<()> {
-    use compilation_user::CompilerConfig;
-    use execution_user::ExecutionConfig;
-    let fullopt = !full_simplification.is_empty();
-    let o2_arg = full_simplification.as_str();
-    let o_style = input_user::get_simplification_style(
+    let o_style = SimplificationStyle::from_flags(
         no_simplification,
         reduced_simplification,
-        fullopt,
-        o2_arg,
+        &full_simplification,
     )?;
-    let input = Path::new(&input);
-    let output = Path::new(&output);
+    let opts = CompileOptions::new(input)
+        .prime(&prime)
+        .simplification(o_style)
+        .link_directories(link_directories);
+    compile(&opts, output)
+}
+
+/// Compile the circuit by `opts`, and write the enabled outputs into the `output` directory.
+///
+/// With `opts.cache(true)`, the compilation is skipped if the outputs in `output` were compiled
+/// from the same sources and options, by the same compiler version.
+pub fn compile<P: AsRef<Path>>(opts: &CompileOptions, output: P) -> Result<()> {
+    compile_with_reporter(opts, output.as_ref(), &mut Reporter::terminal())
+}
+
+/// Like `compile`, but collect the circom reports into `diagnostics` instead of printing them.
+/// The warnings of reused outputs are collected again.
+///
+/// circom's `build_circuit` prints the reports of constraint generation itself, so they are
+/// captured from stderr and parsed back. A failure of constraint generation is also collected as
+/// a `constraint_generation` diagnostic without location.
+pub fn compile_with_diagnostics<P: AsRef<Path>>(
+    opts: &CompileOptions,
+    output: P,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    compile_with_reporter(opts, output.as_ref(), &mut Reporter::collect(diagnostics))
+}
+
+fn compile_with_reporter(
+    opts: &CompileOptions,
+    output: &Path,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    let user_input = input_user::Input::new(opts, output)?;
+    let cache = if opts.cache {
+        match cache::Cache::new(opts, &user_input, output) {
+            Ok(cache) => match cache.fresh_warnings() {
+                Some(warnings) => {
+                    log::info!("reuse the outputs of {}", opts.input.display());
+                    reporter.replay(warnings);
+                    return Ok(());
+                }
+                None => {
+                    cache.invalidate()?;
+                    Some(cache)
+                }
+            },
+            // leave the errors, like a missing include, to the parser
+            Err(e) => {
+                log::debug!("no cache key: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    run_compiler(opts, &user_input, reporter)?;
+    if let Some(cache) = cache {
+        cache.store(&reporter.warnings)?;
+    }
+    Ok(())
+}
+
+fn run_compiler(
+    opts: &CompileOptions,
+    user_input: &input_user::Input,
+    reporter: &mut Reporter,
+) -> Result<()> {
+    use compilation_user::CompilerConfig;
+    use execution_user::ExecutionConfig;
 
-    let user_input = input_user::Input::new(input, output, o_style, prime, link_directories)?;
-    let mut program_archive = parser_user::parse_project(&user_input)?;
+    let mut program_archive = parser_user::parse_project(user_input, reporter)?;
 
-    type_analysis_user::analyse_project(&mut program_archive)?;
+    type_analysis_user::analyse_project(&mut program_archive, reporter)?;
 
     let config = ExecutionConfig {
         no_rounds: user_input.no_rounds(),
         flag_p: user_input.parallel_simplification_flag(),
+        workers: user_input.workers(),
         flag_s: user_input.reduced_simplification_flag(),
         flag_f: user_input.unsimplified_flag(),
         flag_verbose: user_input.flag_verbose(),
@@ -57,7 +131,17 @@ pub fn circom_compiler(
         json_constraints: user_input.json_constraints_file().to_string(),
         prime: user_input.get_prime(),
     };
-    let circuit = execution_user::execute_project(program_archive, config)?;
+    let circuit =
+        match reporter.capture(|| execution_user::execute_project(program_archive, config)) {
+            Ok(circuit) => circuit,
+            Err(e) => {
+                reporter.error("constraint_generation", &e.to_string());
+                return Err(e);
+            }
+        };
+    if !opts.generates_code() {
+        return Ok(());
+    }
     let compilation_config = CompilerConfig {
         vcp: circuit,
         debug_output: user_input.print_ir_flag(),
@@ -74,6 +158,9 @@ pub fn circom_compiler(
         wasm_file: user_input.wasm_file().to_string(),
         produce_input_log: user_input.main_inputs_flag(),
     };
-    compilation_user::compile(compilation_config)?;
+    if let Err(e) = compilation_user::compile(compilation_config) {
+        reporter.error("code_generation", &e.to_string());
+        return Err(e);
+    }
     Result::Ok(())
 }
@@ -0,0 +1,134 @@
+use crate::input_user::SimplificationStyle;
+use std::path::{Path, PathBuf};
+
+/// Options of circom compilation, built like
+/// `CompileOptions::new("main.circom").prime("goldilocks").c(true)`.
+///
+/// The defaults follow `zkit compile`: bn128, full `--O2` simplification, and only the r1cs, sym
+/// and wasm outputs.
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    pub(crate) input: PathBuf,
+    pub(crate) prime: String,
+    pub(crate) simplification: SimplificationStyle,
+    pub(crate) link_directories: Vec<PathBuf>,
+    pub(crate) r1cs: bool,
+    pub(crate) sym: bool,
+    pub(crate) wasm: bool,
+    pub(crate) wat: bool,
+    pub(crate) c: bool,
+    pub(crate) json_constraints: bool,
+    pub(crate) main_inputs: bool,
+    pub(crate) cache: bool,
+    pub(crate) parallel: bool,
+    pub(crate) workers: Option<usize>,
+}
+
+impl CompileOptions {
+    pub fn new<P: AsRef<Path>>(input: P) -> Self {
+        Self {
+            input: input.as_ref().to_path_buf(),
+            prime: "bn128".to_string(),
+            simplification: SimplificationStyle::O2(usize::MAX),
+            link_directories: vec![],
+            r1cs: true,
+            sym: true,
+            wasm: true,
+            wat: false,
+            c: false,
+            json_constraints: false,
+            main_inputs: false,
+            cache: false,
+            parallel: false,
+            workers: None,
+        }
+    }
+
+    /// prime field, like bn128, bls12381 or goldilocks
+    pub fn prime(mut self, prime: &str) -> Self {
+        self.prime = prime.to_lowercase();
+        self
+    }
+
+    pub fn simplification(mut self, simplification: SimplificationStyle) -> Self {
+        self.simplification = simplification;
+        self
+    }
+
+    /// add a directory to search the included files
+    pub fn link_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.link_directories.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn link_directories<P: AsRef<Path>>(mut self, dirs: impl IntoIterator<Item = P>) -> Self {
+        self.link_directories
+            .extend(dirs.into_iter().map(|d| d.as_ref().to_path_buf()));
+        self
+    }
+
+    /// simplify the constraints in parallel, the r1cs is the same as the serial one
+    pub fn parallel_simplification(mut self, enable: bool) -> Self {
+        self.parallel = enable;
+        self
+    }
+
+    /// the number of threads of the parallel simplification, all the available CPUs by default
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    pub fn r1cs(mut self, enable: bool) -> Self {
+        self.r1cs = enable;
+        self
+    }
+
+    /// the symbol file, mapping the signal names to the wires
+    pub fn sym(mut self, enable: bool) -> Self {
+        self.sym = enable;
+        self
+    }
+
+    /// the wasm witness generator
+    pub fn wasm(mut self, enable: bool) -> Self {
+        self.wasm = enable;
+        self
+    }
+
+    /// the text format of the wasm witness generator
+    pub fn wat(mut self, enable: bool) -> Self {
+        self.wat = enable;
+        self
+    }
+
+    /// the C++ witness generator
+    pub fn c(mut self, enable: bool) -> Self {
+        self.c = enable;
+        self
+    }
+
+    /// the constraints in json, for debugging
+    pub fn json_constraints(mut self, enable: bool) -> Self {
+        self.json_constraints = enable;
+        self
+    }
+
+    /// make the witness generators log the inputs of the main component
+    pub fn main_inputs(mut self, enable: bool) -> Self {
+        self.main_inputs = enable;
+        self
+    }
+
+    /// reuse the outputs in the output directory if the sources and options are unchanged, see
+    /// `compile`
+    pub fn cache(mut self, enable: bool) -> Self {
+        self.cache = enable;
+        self
+    }
+
+    /// whether any witness generator is enabled, which is the expensive code generation phase
+    pub(crate) fn generates_code(&self) -> bool {
+        self.wasm || self.wat || self.c
+    }
+}
@@ -11,15 +11,15 @@ use crate::{
 };
 use algebraic::{
     bellman_ce::Engine,
-    circom_circuit::CircomCircuit,
+    circom_circuit::{CircomCircuit, R1CS},
     errors::{EigenError, Result},
-    reader::load_r1cs,
+    reader::{load_r1cs, load_witness_from_file},
+    utils::seeded_rng,
     witness::{load_input_for_witness, WitnessCalculator},
-    Field, PrimeField,
+    PrimeField,
 };
 use anyhow::bail;
-use num_traits::Zero;
-use rand;
+use rand::Rng;
 
 pub fn groth16_setup(
     curve_type: &str,
@@ -27,16 +27,17 @@ pub fn groth16_setup(
     pk_file: &str,
     vk_file: &str,
     to_hex: bool,
+    seed: Option<u64>,
 ) -> Result<()> {
-    let mut rng = rand::thread_rng();
+    let mut rng = new_rng(seed);
     match curve_type {
         "BN128" => {
-            let circuit = create_circuit_from_file::<Bn256>(circuit_file, None);
+            let circuit = create_circuit_from_file::<Bn256>(circuit_file, None)?;
             let (pk, vk) = Groth16::circuit_specific_setup(circuit, &mut rng)?;
             write_pk_vk_to_files(curve_type, pk, vk, pk_file, vk_file, to_hex)?
         }
         "BLS12381" => {
-            let circuit = create_circuit_from_file::<Bls12>(circuit_file, None);
+            let circuit = create_circuit_from_file::<Bls12>(circuit_file, None)?;
             let (pk, vk) = Groth16::circuit_specific_setup(circuit, &mut rng)?;
             write_pk_vk_to_files(curve_type, pk, vk, pk_file, vk_file, to_hex)?
         }
@@ -50,36 +51,27 @@ pub fn groth16_setup(
     Ok(())
 }
 
+/// Prove with groth16. `wtns_file` is either the circuit wasm, which computes the witness from
+/// `input_file`, or a precomputed witness in `.wtns` or `.json` format.
 #[allow(clippy::too_many_arguments)]
 pub fn groth16_prove(
     curve_type: &str,
     circuit_file: &str,
     wtns_file: &str,
     pk_file: &str,
-    input_file: &str,
+    input_file: Option<&str>,
     public_input_file: &str,
     proof_file: &str,
     to_hex: bool,
+    seed: Option<u64>,
 ) -> Result<()> {
-    let mut rng = rand::thread_rng();
-
-    let mut wtns = WitnessCalculator::from_file(wtns_file)?;
-    let inputs = load_input_for_witness(input_file);
-    let w = wtns.calculate_witness(inputs, false)?;
+    let mut rng = new_rng(seed);
     match curve_type {
         "BN128" => {
             let pk: Parameters<Bn256> = read_pk_from_file(pk_file, false)?;
-            let w = w
-                .iter()
-                .map(|wi| {
-                    if wi.is_zero() {
-                        Fr::zero()
-                    } else {
-                        Fr::from_str(&wi.to_string()).unwrap()
-                    }
-                })
-                .collect::<Vec<_>>();
-            let circuit = create_circuit_from_file::<Bn256>(circuit_file, Some(w));
+            let r1cs = load_r1cs::<Bn256>(circuit_file)?;
+            let w = load_witness::<Bn256>(wtns_file, input_file, &r1cs)?;
+            let circuit = create_circuit_from_r1cs(r1cs, Some(w));
             let proof = Groth16::prove(&pk, circuit.clone(), &mut rng)?;
             let proof_json = serialize_proof(&proof, curve_type, to_hex)?;
             std::fs::write(proof_file, proof_json)?;
@@ -88,17 +80,9 @@ pub fn groth16_prove(
         }
         "BLS12381" => {
             let pk: Parameters<Bls12> = read_pk_from_file(pk_file, false)?;
-            let w = w
-                .iter()
-                .map(|wi| {
-                    if wi.is_zero() {
-                        Fr_bls12381::zero()
-                    } else {
-                        Fr_bls12381::from_str(&wi.to_string()).unwrap()
-                    }
-                })
-                .collect::<Vec<_>>();
-            let circuit = create_circuit_from_file::<Bls12>(circuit_file, Some(w));
+            let r1cs = load_r1cs::<Bls12>(circuit_file)?;
+            let w = load_witness::<Bls12>(wtns_file, input_file, &r1cs)?;
+            let circuit = create_circuit_from_r1cs(r1cs, Some(w));
             let proof = Groth16::prove(&pk, circuit.clone(), &mut rng)?;
             let proof_json = serialize_proof(&proof, curve_type, to_hex)?;
             std::fs::write(proof_file, proof_json)?;
@@ -160,18 +144,66 @@ pub fn groth16_verify(
     Ok(())
 }
 
+// thread rng by default, or the insecure seeded one for reproducible tests
+fn new_rng(seed: Option<u64>) -> Box<dyn Rng> {
+    match seed {
+        Some(seed) => {
+            log::warn!("using the seeded rng, the setup and proof are INSECURE");
+            Box::new(seeded_rng(seed))
+        }
+        None => Box::new(rand::thread_rng()),
+    }
+}
+
 fn create_circuit_from_file<E: Engine>(
     circuit_file: &str,
     witness: Option<Vec<E::Fr>>,
+) -> Result<CircomCircuit<E>> {
+    Ok(create_circuit_from_r1cs(load_r1cs(circuit_file)?, witness))
+}
+
+fn create_circuit_from_r1cs<E: Engine>(
+    r1cs: R1CS<E>,
+    witness: Option<Vec<E::Fr>>,
 ) -> CircomCircuit<E> {
     CircomCircuit {
-        r1cs: load_r1cs(circuit_file),
+        r1cs,
         witness,
         wire_mapping: None,
         aux_offset: 0,
     }
 }
 
+// calculate the witness by the circuit wasm, or load a precomputed one, and check it against
+// the r1cs
+fn load_witness<E: Engine>(
+    wtns_file: &str,
+    input_file: Option<&str>,
+    r1cs: &R1CS<E>,
+) -> Result<Vec<E::Fr>> {
+    let w = if wtns_file.ends_with(".wasm") {
+        let input_file = match input_file {
+            Some(input_file) => input_file,
+            None => bail!(EigenError::Unknown(format!(
+                "input file is required to calculate the witness by {}",
+                wtns_file
+            ))),
+        };
+        let mut wtns = WitnessCalculator::from_file(wtns_file)?;
+        let inputs = load_input_for_witness(input_file)?;
+        wtns.calculate_witness_fr::<E, _>(inputs, false)?
+    } else {
+        load_witness_from_file::<E>(wtns_file)?
+    };
+    if w.len() != r1cs.num_variables {
+        bail!(EigenError::OutOfRangeError {
+            expected: format!("witness length of {}", r1cs.num_variables),
+            found: w.len().to_string(),
+        });
+    }
+    Ok(w)
+}
+
 fn read_pk_from_file<E: Engine>(file_path: &str, checked: bool) -> Result<Parameters<E>> {
     let file = std::fs::File::open(file_path)?;
     let mut reader = std::io::BufReader::new(file);
@@ -207,3 +239,72 @@ fn write_pk_vk_to_files<P: Parser>(
     std::fs::write(vk_file, vk_json)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const INPUT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.input.json");
+    const CIRCUIT_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.r1cs");
+    const WASM_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../test/multiplier.wasm");
+
+    #[test]
+    fn groth16_prove_from_wtns() -> Result<()> {
+        let pk_file = "/tmp/groth16_prove_from_wtns.zkey";
+        let vk_file = "/tmp/groth16_prove_from_wtns.vk.json";
+        let wtns_file = "/tmp/groth16_prove_from_wtns.wtns";
+        let public_input_file = "/tmp/groth16_prove_from_wtns.public.json";
+        let proof_file = "/tmp/groth16_prove_from_wtns.proof.json";
+        groth16_setup("BN128", CIRCUIT_FILE, pk_file, vk_file, false, None)?;
+
+        let mut wtns = WitnessCalculator::from_file(WASM_FILE)?;
+        let inputs = load_input_for_witness(INPUT_FILE)?;
+        let w = wtns.calculate_witness_bin(inputs, false)?;
+        wtns.save_witness_to_bin_file::<Bn256>(wtns_file, &w)?;
+
+        groth16_prove(
+            "BN128",
+            CIRCUIT_FILE,
+            wtns_file,
+            pk_file,
+            None,
+            public_input_file,
+            proof_file,
+            false,
+            None,
+        )?;
+        groth16_verify("BN128", vk_file, public_input_file, proof_file)?;
+
+        // the witness of bn128 can not be used to prove on bls12381
+        assert!(load_witness_from_file::<Bls12>(wtns_file).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn groth16_seeded_setup_and_prove() -> Result<()> {
+        let prove_with_seed = |i: usize| -> Result<(String, String)> {
+            let pk_file = format!("/tmp/groth16_seeded_{}.zkey", i);
+            let vk_file = format!("/tmp/groth16_seeded_{}.vk.json", i);
+            let public_input_file = format!("/tmp/groth16_seeded_{}.public.json", i);
+            let proof_file = format!("/tmp/groth16_seeded_{}.proof.json", i);
+            groth16_setup("BN128", CIRCUIT_FILE, &pk_file, &vk_file, false, Some(42))?;
+            groth16_prove(
+                "BN128",
+                CIRCUIT_FILE,
+                WASM_FILE,
+                &pk_file,
+                Some(INPUT_FILE),
+                &public_input_file,
+                &proof_file,
+                false,
+                Some(42),
+            )?;
+            groth16_verify("BN128", &vk_file, &public_input_file, &proof_file)?;
+            Ok((
+                std::fs::read_to_string(vk_file)?,
+                std::fs::read_to_string(proof_file)?,
+            ))
+        };
+        assert_eq!(prove_with_seed(0)?, prove_with_seed(1)?);
+        Ok(())
+    }
+}
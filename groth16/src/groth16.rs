@@ -71,7 +71,7 @@ mod tests {
         //1. SRS
         let t = std::time::Instant::now();
         let circuit: CircomCircuit<Bn256> = CircomCircuit {
-            r1cs: reader::load_r1cs(CIRCUIT_FILE),
+            r1cs: reader::load_r1cs(CIRCUIT_FILE)?,
             witness: None,
             wire_mapping: None,
             aux_offset: 0,
@@ -84,7 +84,7 @@ mod tests {
         //2. Prove
         let t1 = std::time::Instant::now();
         let mut wtns = WitnessCalculator::from_file(WASM_FILE)?;
-        let inputs = load_input_for_witness(INPUT_FILE);
+        let inputs = load_input_for_witness(INPUT_FILE)?;
         let w = wtns.calculate_witness(inputs, false).unwrap();
         let w = w
             .iter()
@@ -98,7 +98,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
         let circuit1: CircomCircuit<Bn256> = CircomCircuit {
-            r1cs: reader::load_r1cs(CIRCUIT_FILE),
+            r1cs: reader::load_r1cs(CIRCUIT_FILE)?,
             witness: Some(w),
             wire_mapping: None,
             aux_offset: 0,
@@ -126,7 +126,7 @@ mod tests {
         //1. SRS
         let t = std::time::Instant::now();
         let circuit: CircomCircuit<Bls12> = CircomCircuit {
-            r1cs: reader::load_r1cs(CIRCUIT_FILE_BLS12),
+            r1cs: reader::load_r1cs(CIRCUIT_FILE_BLS12)?,
             witness: None,
             wire_mapping: None,
             aux_offset: 0,
@@ -140,7 +140,7 @@ mod tests {
         let t1 = std::time::Instant::now();
         // let mut wtns = WitnessCalculator::new(WASM_FILE_BLS12).unwrap();
         let mut wtns = WitnessCalculator::from_file(WASM_FILE_BLS12)?;
-        let inputs = load_input_for_witness(INPUT_FILE);
+        let inputs = load_input_for_witness(INPUT_FILE)?;
         let w = wtns.calculate_witness(inputs, false).unwrap();
         let w = w
             .iter()
@@ -154,7 +154,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
         let circuit1: CircomCircuit<Bls12> = CircomCircuit {
-            r1cs: reader::load_r1cs(CIRCUIT_FILE_BLS12),
+            r1cs: reader::load_r1cs(CIRCUIT_FILE_BLS12)?,
             witness: Some(w),
             wire_mapping: None,
             aux_offset: 0,
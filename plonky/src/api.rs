@@ -1,8 +1,7 @@
 use crate::bellman_ce::pairing::bn256::Bn256;
 use crate::errors::{EigenError, Result};
 use crate::witness::{load_input_for_witness, WitnessCalculator};
-use crate::{circom_circuit::CircomCircuit, plonk, reader};
-use algebraic::reader::load_r1cs;
+use crate::{circom_circuit::StreamingCircomCircuit, plonk, reader};
 use anyhow::bail;
 use profiler_macro::time_profiler;
 
@@ -11,10 +10,11 @@ use crate::{aggregation, verifier};
 
 use std::path::Path;
 
-// generate a monomial_form SRS, and save it to a file
+// generate a monomial_form SRS, and save it to a file. `seed` makes the SRS reproducible, but
+// INSECURE, only for testing
 #[time_profiler("plonk_setup")]
-pub fn setup(power: u32, srs_monomial_form: &str) -> Result<()> {
-    let srs = plonk::gen_key_monomial_form(power)?;
+pub fn setup(power: u32, srs_monomial_form: &str, seed: Option<u64>) -> Result<()> {
+    let srs = plonk::gen_key_monomial_form(power, seed)?;
     let path = Path::new(srs_monomial_form);
     assert!(
         !path.exists(),
@@ -30,12 +30,7 @@ pub fn setup(power: u32, srs_monomial_form: &str) -> Result<()> {
 // circuit filename default resolver
 #[time_profiler()]
 pub fn analyse(circuit_file: &str, output: &str) -> Result<()> {
-    let circuit = CircomCircuit::<Bn256> {
-        r1cs: load_r1cs(circuit_file),
-        witness: None,
-        wire_mapping: None,
-        aux_offset: plonk::AUX_OFFSET,
-    };
+    let circuit = StreamingCircomCircuit::<Bn256>::load(circuit_file, None, plonk::AUX_OFFSET)?;
     let mut stats = plonk::analyse(circuit)?;
     let writer = std::fs::File::create(output)?;
     serde_json::to_writer_pretty(writer, &stats)?;
@@ -59,12 +54,12 @@ pub fn prove(
     proof_json: &str,
     public_json: &str,
 ) -> Result<()> {
-    let circuit = CircomCircuit {
-        r1cs: load_r1cs(circuit_file),
-        witness: Some(reader::load_witness_from_file::<Bn256>(witness)),
-        wire_mapping: None,
-        aux_offset: plonk::AUX_OFFSET,
-    };
+    // the constraints are streamed from the r1cs by every synthesis of the transpiler
+    let circuit = StreamingCircomCircuit::load(
+        circuit_file,
+        Some(reader::load_witness_from_file::<Bn256>(witness)),
+        plonk::AUX_OFFSET,
+    )?;
 
     let setup = plonk::SetupForProver::prepare_setup_for_prover(
         circuit.clone(),
@@ -88,13 +83,10 @@ pub fn prove(
 
 #[time_profiler("plonk_calculate_witness")]
 pub fn calculate_witness(wasm_file: &str, input_json: &str, output: &str) -> Result<()> {
-    let inputs = load_input_for_witness(input_json);
+    let inputs = load_input_for_witness(input_json)?;
 
     let mut wtns = WitnessCalculator::from_file(wasm_file)?;
-    assert_eq!(
-        wtns.memory.prime.to_str_radix(16),
-        "30644E72E131A029B85045B68181585D2833E84879B9709143E1F593F0000001".to_lowercase()
-    );
+    wtns.check_field::<Bn256>()?;
 
     let wtns_buf = wtns.calculate_witness_bin(inputs, false)?;
     wtns.save_witness_to_bin_file::<Bn256>(output, &wtns_buf)
@@ -105,12 +97,7 @@ pub fn export_verification_key(
     circuit_file: &str,
     output_vk: &str,
 ) -> Result<()> {
-    let circuit = CircomCircuit {
-        r1cs: load_r1cs(circuit_file),
-        witness: None,
-        wire_mapping: None,
-        aux_offset: plonk::AUX_OFFSET,
-    };
+    let circuit = StreamingCircomCircuit::<Bn256>::load(circuit_file, None, plonk::AUX_OFFSET)?;
 
     let setup = plonk::SetupForProver::prepare_setup_for_prover(
         circuit,
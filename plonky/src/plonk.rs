@@ -1,10 +1,10 @@
 #![allow(clippy::reversed_empty_ranges)]
 
 // Most of this file is forked from source codes of [Matter Labs's zkSync](https://github.com/matter-labs/zksync)
-use crate::bellman_ce::bn256::Bn256;
+use crate::bellman_ce::bn256::{Bn256, Fr, G1Affine, G2Affine};
 use crate::bellman_ce::{
     kate_commitment::{Crs, CrsForLagrangeForm, CrsForMonomialForm},
-    pairing::Engine,
+    pairing::{CurveAffine, CurveProjective, Engine},
     plonk::{
         better_cs::adaptor::TranspilationVariant,
         better_cs::cs::PlonkCsWidth4WithNextStepParams,
@@ -13,12 +13,14 @@ use crate::bellman_ce::{
         is_satisfied_using_one_shot_check, make_verification_key, prove, prove_by_steps, setup,
     },
     worker::Worker,
-    Circuit, ScalarEngine,
+    Circuit, Field, PrimeField, ScalarEngine,
 };
-use crate::circom_circuit::CircomCircuit;
+use crate::circom_circuit::StreamingCircomCircuit;
 use crate::errors::{EigenError, Result};
 use crate::transpile::{transpile_with_gates_count, ConstraintStat, TranspilerWrapper};
+use algebraic::utils::seeded_rng;
 use anyhow::bail;
+use rand::Rng;
 
 type E = Bn256;
 use franklin_crypto::plonk::circuit::bigint::field::RnsParameters;
@@ -30,8 +32,8 @@ pub const AUX_OFFSET: usize = 1;
 const SETUP_MIN_POW2: u32 = 10;
 const SETUP_MAX_POW2: u32 = 26;
 
-// generate a monomial_form SRS
-pub fn gen_key_monomial_form(power: u32) -> Result<Crs<E, CrsForMonomialForm>> {
+// generate a monomial_form SRS, with a seeded random tau if `seed` is given
+pub fn gen_key_monomial_form(power: u32, seed: Option<u64>) -> Result<Crs<E, CrsForMonomialForm>> {
     if (!SETUP_MIN_POW2..=SETUP_MAX_POW2).contains(&power) {
         bail!(EigenError::OutOfRangeError {
             expected: format!(
@@ -42,6 +44,11 @@ pub fn gen_key_monomial_form(power: u32) -> Result<Crs<E, CrsForMonomialForm>> {
         });
     }
 
+    if let Some(seed) = seed {
+        log::warn!("using the seeded rng, the SRS is INSECURE");
+        return Ok(gen_key_monomial_form_from_seed(1 << power, seed));
+    }
+
     // run a small setup to estimate time
     if power > 15 {
         use std::time::Instant;
@@ -59,6 +66,28 @@ pub fn gen_key_monomial_form(power: u32) -> Result<Crs<E, CrsForMonomialForm>> {
     ))
 }
 
+// powers of a tau drawn from the seeded rng, INSECURE, only for reproducible tests
+fn gen_key_monomial_form_from_seed(size: usize, seed: u64) -> Crs<E, CrsForMonomialForm> {
+    let mut rng = seeded_rng(seed);
+    let tau: Fr = rng.gen();
+    let g1 = G1Affine::one();
+    let mut g1_bases = vec![G1Affine::zero(); size];
+    Worker::new().scope(size, |scope, chunk| {
+        for (i, bases) in g1_bases.chunks_mut(chunk).enumerate() {
+            scope.spawn(move |_| {
+                let mut power = tau.pow([(i * chunk) as u64]);
+                for base in bases.iter_mut() {
+                    *base = g1.mul(power.into_repr()).into_affine();
+                    power.mul_assign(&tau);
+                }
+            });
+        }
+    });
+    let g2 = G2Affine::one();
+    let g2_bases = vec![g2, g2.mul(tau.into_repr()).into_affine()];
+    Crs::<E, CrsForMonomialForm>::new(g1_bases, g2_bases)
+}
+
 pub struct SetupForProver {
     setup_polynomials: SetupPolynomials<E, PlonkCsWidth4WithNextStepParams>,
     hints: Vec<(usize, TranspilationVariant)>,
@@ -81,13 +110,13 @@ pub struct AnalyseResult {
 }
 
 // analyse a circuit
-pub fn analyse<E: Engine>(circuit: CircomCircuit<E>) -> Result<AnalyseResult> {
+pub fn analyse<E: Engine>(circuit: StreamingCircomCircuit<E>) -> Result<AnalyseResult> {
     let mut transpiler = TranspilerWrapper::<E, PlonkCsWidth4WithNextStepParams>::new();
     let mut result = AnalyseResult {
         num_inputs: circuit.r1cs.num_inputs,
         num_aux: circuit.r1cs.num_aux,
         num_variables: circuit.r1cs.num_variables,
-        num_constraints: circuit.r1cs.constraints.len(),
+        num_constraints: circuit.num_constraints,
         num_nontrivial_constraints: 0,
         num_gates: 0,
         num_hints: 0,
@@ -270,6 +299,20 @@ mod tests {
 
     #[test]
     fn test_gen_key_monomial_form() {
-        gen_key_monomial_form(10).unwrap();
+        gen_key_monomial_form(10, None).unwrap();
+    }
+
+    #[test]
+    fn test_gen_key_monomial_form_with_seed() {
+        let serialize = |seed| {
+            let mut buf = vec![];
+            gen_key_monomial_form(10, Some(seed))
+                .unwrap()
+                .write(&mut buf)
+                .unwrap();
+            buf
+        };
+        assert_eq!(serialize(42), serialize(42));
+        assert_ne!(serialize(42), serialize(43));
     }
 }
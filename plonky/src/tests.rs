@@ -1,5 +1,5 @@
 use crate::bellman_ce::bn256::Bn256;
-use crate::circom_circuit::CircomCircuit;
+use crate::circom_circuit::{CircomCircuit, StreamingCircomCircuit};
 use crate::{plonk, reader};
 use algebraic::reader::load_r1cs;
 use std::fs;
@@ -16,13 +16,13 @@ const CIRCUIT_ANALYZE_RESULT: &str = r#"{"num_inputs":2,"num_aux":2,"num_variabl
 #[test]
 fn test_analyze() {
     let circuit = CircomCircuit::<Bn256> {
-        r1cs: load_r1cs(CIRCUIT_FILE),
+        r1cs: load_r1cs(CIRCUIT_FILE).unwrap(),
         witness: None,
         wire_mapping: None,
         aux_offset: plonk::AUX_OFFSET,
     };
 
-    let result = crate::plonk::analyse(circuit).unwrap();
+    let result = crate::plonk::analyse(circuit.into()).unwrap();
 
     assert_eq!(
         CIRCUIT_ANALYZE_RESULT,
@@ -33,7 +33,7 @@ fn test_analyze() {
 #[test]
 fn test_export_verification_key() {
     let circuit = CircomCircuit {
-        r1cs: load_r1cs(CIRCUIT_FILE),
+        r1cs: load_r1cs(CIRCUIT_FILE).unwrap(),
         witness: None,
         wire_mapping: None,
         aux_offset: plonk::AUX_OFFSET,
@@ -52,10 +52,29 @@ fn test_export_verification_key() {
     assert_eq!(check_vk, buf);
 }
 
+#[test]
+fn test_streaming_circuit() {
+    let circuit =
+        StreamingCircomCircuit::<Bn256>::load(CIRCUIT_FILE, None, plonk::AUX_OFFSET).unwrap();
+    assert!(circuit.r1cs.constraints.is_empty());
+    assert_eq!(circuit.num_constraints, 1);
+
+    let setup = plonk::SetupForProver::prepare_setup_for_prover(
+        circuit,
+        reader::load_key_monomial_form(MONOMIAL_KEY_FILE),
+        None,
+    )
+    .expect("prepare err");
+    let vk = setup.make_verification_key().unwrap();
+    let mut buf = vec![];
+    vk.write(&mut buf).unwrap();
+    assert_eq!(fs::read(VK_FILE).unwrap(), buf);
+}
+
 #[test]
 fn test_prove() {
     let circuit = CircomCircuit {
-        r1cs: load_r1cs(CIRCUIT_FILE),
+        r1cs: load_r1cs(CIRCUIT_FILE).unwrap(),
         witness: Some(reader::load_witness_from_file::<Bn256>(WITNESS_FILE)),
         wire_mapping: None,
         aux_offset: plonk::AUX_OFFSET,
@@ -4,9 +4,8 @@ use crate::io_utils::read_vec_from_file;
 use crate::pilcom::compile_pil_from_path;
 use crate::polsarray::{PolKind, PolsArray};
 use anyhow::Result;
-use num_traits::Zero;
 use plonky::ff::PrimeField;
-use plonky::field_gl::Fr as FGL;
+use plonky::field_gl::{Fr as FGL, GL};
 use plonky::witness::{load_input_for_witness, WitnessCalculator};
 use std::fs::File;
 use std::io::Write;
@@ -27,7 +26,7 @@ pub fn exec(
 
     // 1. Compiles a .pil file to its json form , and save it.
     // TODO: the pil_str has been compiled in plonk_setup#3
-    let pil_json = compile_pil_from_path(pil_file);
+    let pil_json = compile_pil_from_path(pil_file)?;
     let mut file = File::create(Path::new(&format!("{pil_file}.json")))?;
     let input = serde_json::to_string(&pil_json)?;
     write!(file, "{}", input)?;
@@ -37,19 +36,8 @@ pub fn exec(
 
     // 3. calculate witness. wasm+input->witness
     let mut wtns = WitnessCalculator::from_file(wasm_file)?;
-    let inputs = load_input_for_witness(input_file);
-    let w = wtns.calculate_witness(inputs, false)?;
-    let mut w = w
-        .iter()
-        .map(|wi| {
-            if wi.is_zero() {
-                FGL::ZERO
-            } else {
-                assert!(wi.to_u64_digits().1.len() < 2);
-                FGL::from(wi.to_u64_digits().1[0])
-            }
-        })
-        .collect::<Vec<_>>();
+    let inputs = load_input_for_witness(input_file)?;
+    let mut w = wtns.calculate_witness_fr::<GL, _>(inputs, false)?;
 
     for i in 0..adds_len {
         let w2 = FGL::from_raw_repr(<FGL as PrimeField>::Repr::from(adds[i * 4 + 2]))?;
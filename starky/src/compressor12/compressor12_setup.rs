@@ -1,8 +1,8 @@
 #![allow(non_snake_case)]
 use crate::compressor12::plonk_setup::PlonkSetup;
 use crate::io_utils::write_vec_to_file;
-use crate::r1cs2plonk::PlonkAdd;
-use algebraic::reader::load_r1cs;
+use crate::r1cs2plonk::{r1cs2plonk_streaming, PlonkAdd};
+use algebraic::reader::load_r1cs_streaming;
 use anyhow::Result;
 use plonky::field_gl::GL;
 use std::fs::File;
@@ -22,14 +22,16 @@ pub fn setup(
     exec_file: &str,
     force_n_bits: usize,
 ) -> Result<()> {
-    // 0. readR1cs
-    let r1cs = load_r1cs::<GL>(r1cs_file);
+    // 0. readR1cs, and transform the constraints to plonk gates one by one
+    let (r1cs, _wire_mapping, constraints) = load_r1cs_streaming::<GL>(r1cs_file)?;
+    let (plonk_constrains, plonk_additions) =
+        r1cs2plonk_streaming(r1cs.num_variables, constraints)?;
     let opts = Options {
         force_bits: force_n_bits,
     };
 
     // 1. plonk setup: generate plonk circuit, the pil file.
-    let res = PlonkSetup::new(&r1cs, &opts);
+    let res = PlonkSetup::from_plonk(&r1cs, plonk_constrains, plonk_additions, &opts)?;
 
     // 2. And write it into pil_file.
     let mut file = File::create(pil_file)?;
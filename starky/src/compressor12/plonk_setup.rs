@@ -3,6 +3,7 @@ use super::{
     compressor12_pil, compressor12_pil::CompressorNameSpace::*,
     compressor12_pil::CompressorPolName::*, compressor12_setup::Options, constants::CPOSEIDON,
 };
+use crate::errors::Result;
 use crate::pilcom::compile_pil_from_str;
 use crate::polsarray::{PolKind, PolsArray};
 use crate::r1cs2plonk::{r1cs2plonk, PlonkAdd, PlonkGate};
@@ -22,9 +23,22 @@ pub struct PlonkSetup {
 }
 
 impl PlonkSetup {
-    pub fn new(r1cs: &R1CS<GL>, opts: &Options) -> Self {
+    pub fn new(r1cs: &R1CS<GL>, opts: &Options) -> Result<Self> {
+        let (plonk_constrains, plonk_additions) = r1cs2plonk(r1cs);
+        Self::from_plonk(r1cs, plonk_constrains, plonk_additions, opts)
+    }
+
+    /// Setup by the plonk gates transformed from `r1cs` already, the constraints of `r1cs` are
+    /// not used, so they can be streamed into `r1cs2plonk_streaming` instead of kept in memory.
+    pub fn from_plonk(
+        r1cs: &R1CS<GL>,
+        plonk_constrains: Vec<PlonkGate>,
+        plonk_additions: Vec<PlonkAdd>,
+        opts: &Options,
+    ) -> Result<Self> {
         // 1. plonk_setup_render phase
-        let plonk_setup_info = PlonkSetupRenderInfo::plonk_setup_render(r1cs, opts);
+        let plonk_setup_info =
+            PlonkSetupRenderInfo::plonk_setup_render(r1cs, plonk_constrains, plonk_additions, opts);
         // 2. render .pil file by template.
         // //      And save as a file.
         let pil_str = compressor12_pil::render(plonk_setup_info.n_bits, plonk_setup_info.n_publics);
@@ -32,17 +46,17 @@ impl PlonkSetup {
         // write!(file, "{}", pil_str).unwrap();
 
         // 3. compile pil to pil_json
-        let pil_json = compile_pil_from_str(&pil_str);
+        let pil_json = compile_pil_from_str(&pil_str)?;
 
         //4. plonk_setup_fix_compressor phase
         let (const_pols, s_map) = plonk_setup_compressor(r1cs, &pil_json, &plonk_setup_info);
 
-        Self {
+        Ok(Self {
             pil_str,
             const_pols,
             s_map,
             plonk_additions: plonk_setup_info.pa,
-        }
+        })
     }
 }
 
@@ -175,9 +189,13 @@ pub struct PlonkSetupRenderInfo {
 }
 
 impl PlonkSetupRenderInfo {
-    pub fn plonk_setup_render(r1cs: &R1CS<GL>, opts: &Options) -> Self {
-        // 1. r1cs to plonk
-        let (plonk_constrains, plonk_additions) = r1cs2plonk(r1cs);
+    pub fn plonk_setup_render(
+        r1cs: &R1CS<GL>,
+        plonk_constrains: Vec<PlonkGate>,
+        plonk_additions: Vec<PlonkAdd>,
+        opts: &Options,
+    ) -> Self {
+        // 1. r1cs to plonk is done by the caller
 
         // 2. get normal plonk info
         let plonk_info = NormalPlonkInfo::new(&plonk_constrains);
@@ -0,0 +1,562 @@
+//! The layered proof pipeline of `test/recursive_proof_to_snark.sh` and
+//! `test/stark_aggregation.sh`, in process:
+//!
+//! 1. the input starks are proven, which generates the circuit verifying them;
+//! 2. every layer compiles the circuit of the previous proofs, sets it up as a compressor12 PIL,
+//!    and proves it on each of them, or on each group of `arity` of them if it joins;
+//! 3. the last proof is optionally proven by groth16.
+//!
+//! The layer `i` works in `<workspace>/layer_<i>`, with the circuit `verifier.circom` and the
+//! proofs `<j>.zkin.json` of the previous layer. The compilation and the setup of a layer are
+//! skipped if its circuit is unchanged.
+//!
+//! The starks proven out of the pipeline, like the chunks of powdr, go through the layers by
+//! [`LayeredPipeline::prove_layers`] from their verifier circuit in `layer_0`.
+use crate::compressor12::{compressor12_exec, compressor12_setup};
+use crate::errors::{EigenError, Result};
+use crate::prove::stark_prove;
+use crate::types::{load_json, StarkStruct};
+use crate::zkin_join::join_zkins;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A stark to prove, the PIL json with its constant and commit polynomials
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StarkInput {
+    pub pil: PathBuf,
+    pub constants: PathBuf,
+    pub commits: PathBuf,
+}
+
+/// A layer verifying the proofs of the previous one by a compressor12 circuit
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Layer {
+    /// its `nBits` must fit the degree of the compressor12 PIL of the circuit
+    pub stark_struct: PathBuf,
+    /// verify the previous proofs by groups of `arity`, the last group is filled up with its
+    /// last proof
+    #[serde(default)]
+    pub join: bool,
+    #[serde(default = "default_arity")]
+    pub arity: usize,
+}
+
+fn default_arity() -> usize {
+    2
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SnarkStage {
+    /// BN128 or BLS12381, the hash type of the stark struct of the last layer
+    pub curve: String,
+    /// the circom libraries of the circuit verifying the last proof
+    #[serde(default)]
+    pub link_directories: Vec<PathBuf>,
+}
+
+/// The description of a layered pipeline, like
+///
+/// ```json
+/// {
+///   "workspace": "/tmp/layered",
+///   "inputs": [{"pil": "fib.pil.json", "constants": "fib.const", "commits": "fib.cm"}],
+///   "stark_struct": "starky/data/starkStruct.json.gl",
+///   "link_directories": ["starkjs/node_modules/pil-stark/circuits.gl"],
+///   "layers": [
+///     {"stark_struct": "starky/data/c12.starkStruct.json"},
+///     {"stark_struct": "starky/data/final.starkStruct.bn128.json"}
+///   ],
+///   "snark": {"curve": "BN128", "link_directories": ["starkjs/node_modules/circomlib/circuits"]}
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LayeredPipeline {
+    pub workspace: PathBuf,
+    pub inputs: Vec<StarkInput>,
+    /// the stark struct of the inputs
+    pub stark_struct: PathBuf,
+    /// the circom libraries of the goldilocks circuits, like `pil-stark/circuits.gl`
+    #[serde(default)]
+    pub link_directories: Vec<PathBuf>,
+    pub layers: Vec<Layer>,
+    #[serde(default)]
+    pub snark: Option<SnarkStage>,
+    #[serde(default = "default_prover_addr")]
+    pub prover_addr: String,
+}
+
+fn default_prover_addr() -> String {
+    "273030697313060285579891744179749754319274977764".to_string()
+}
+
+/// The tools of the pipeline out of starky, `zkvm::CircomToolchain` implements them by dsl_compile
+/// and groth16
+pub trait Toolchain {
+    /// Compile `circom` over `prime` into `<name>.r1cs` and `<name>_js/<name>.wasm` next to
+    /// it. The outputs of an unchanged circuit should be left untouched, the setup of its layer
+    /// is cached by their modification time.
+    fn compile(&self, circom: &Path, prime: &str, link_directories: &[PathBuf]) -> Result<()>;
+
+    fn groth16_setup(&self, curve: &str, r1cs: &Path, pk: &Path, vk: &Path) -> Result<()>;
+
+    /// prove the circuit on the input `zkin`, and verify the proof
+    fn groth16_prove(
+        &self,
+        curve: &str,
+        r1cs: &Path,
+        wasm: &Path,
+        pk: &Path,
+        vk: &Path,
+        zkin: &Path,
+        public_input: &Path,
+        proof: &Path,
+    ) -> Result<()>;
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SnarkProof {
+    pub proof: PathBuf,
+    pub public_input: PathBuf,
+    pub verification_key: PathBuf,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LayeredProof {
+    /// the last stark proof, the input of `circuit`
+    pub zkin: PathBuf,
+    /// the circuit verifying the last stark proof
+    pub circuit: PathBuf,
+    pub snark: Option<SnarkProof>,
+}
+
+impl LayeredPipeline {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        load_json(&path.as_ref().to_string_lossy())
+    }
+
+    /// Check the pipeline before proving: the starks verified by a layer are hashed over
+    /// goldilocks, the last one fits the curve of the snark, and one proof is left.
+    pub fn validate(&self) -> Result<()> {
+        if self.inputs.is_empty() {
+            bail!(invalid("no input stark".to_string()));
+        }
+        self.check(Some(&self.stark_struct), self.inputs.len())
+    }
+
+    /// `validate` the layers on `num_proofs` starks proven out of the pipeline
+    pub fn validate_layers(&self, num_proofs: usize) -> Result<()> {
+        self.check(None, num_proofs)
+    }
+
+    /// check the layers on `num_proofs` proofs of the stark struct `input`, if it's known
+    fn check(&self, input: Option<&PathBuf>, num_proofs: usize) -> Result<()> {
+        if num_proofs == 0 {
+            bail!(invalid("no proof to verify".to_string()));
+        }
+        let mut stark_structs = vec![];
+        for path in input
+            .into_iter()
+            .chain(self.layers.iter().map(|l| &l.stark_struct))
+        {
+            let stark_struct: StarkStruct = load_json(&path.to_string_lossy())?;
+            stark_structs.push((path, stark_struct.verificationHashType));
+        }
+        let ((last, last_hash), verified) = match stark_structs.split_last() {
+            Some(split) => split,
+            None => bail!(invalid("no layer to prove".to_string())),
+        };
+        for (path, hash) in verified {
+            if hash != "GL" {
+                bail!(invalid(format!(
+                    "{} is verified by a goldilocks circuit, but hashed by {}",
+                    path.display(),
+                    hash
+                )));
+            }
+        }
+        if let Some(snark) = &self.snark {
+            if *last_hash != snark.curve.to_uppercase() {
+                bail!(invalid(format!(
+                    "{} is verified by a {} snark, but hashed by {}",
+                    last.display(),
+                    snark.curve,
+                    last_hash
+                )));
+            }
+        }
+        if self.layers.first().map(|l| l.join).unwrap_or(false) {
+            bail!(invalid(
+                "the input starks are proven without rootC, join them after a layer".to_string()
+            ));
+        }
+        let mut left = num_proofs;
+        for layer in self.layers.iter().filter(|l| l.join) {
+            if layer.arity < 2 {
+                bail!(invalid(format!(
+                    "{} joins {} proofs, expect at least 2",
+                    layer.stark_struct.display(),
+                    layer.arity
+                )));
+            }
+            left = left.div_ceil(layer.arity);
+        }
+        if left != 1 {
+            bail!(invalid(format!(
+                "{} proofs are left after the last layer, join them",
+                left
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn prove<T: Toolchain>(&self, toolchain: &T) -> Result<LayeredProof> {
+        self.validate()?;
+        log::debug!("Proving {} input starks...", self.inputs.len());
+        let current = self.layer_dir(0)?;
+        let mut proofs = vec![];
+        for (j, input) in self.inputs.iter().enumerate() {
+            let zkin = current.file(&format!("{}.zkin.json", j));
+            self.stark_prove(
+                &self.stark_struct,
+                &input.pil,
+                &input.constants,
+                &input.commits,
+                false,
+                self.join_arity(0),
+                &current.circom(),
+                &zkin,
+            )?;
+            proofs.push(zkin);
+        }
+        self.run_layers(toolchain, proofs)
+    }
+
+    /// Prove the layers on the `zkins` of starks proven out of the pipeline, which are verified
+    /// by the circuit `<workspace>/layer_0/verifier.circom`. The `inputs` and the `stark_struct`
+    /// of the pipeline are not used.
+    pub fn prove_layers<T: Toolchain>(
+        &self,
+        toolchain: &T,
+        zkins: Vec<PathBuf>,
+    ) -> Result<LayeredProof> {
+        self.validate_layers(zkins.len())?;
+        self.run_layers(toolchain, zkins)
+    }
+
+    /// the directory of the layer `i`
+    pub fn layer_dir(&self, i: usize) -> Result<LayerDir> {
+        let dir = self.workspace.join(format!("layer_{}", i));
+        std::fs::create_dir_all(&dir)?;
+        Ok(LayerDir { dir })
+    }
+
+    /// the arity of the circuit generated for the layer `i`, if it joins
+    fn join_arity(&self, i: usize) -> Option<usize> {
+        self.layers.get(i).filter(|l| l.join).map(|l| l.arity)
+    }
+
+    fn run_layers<T: Toolchain>(
+        &self,
+        toolchain: &T,
+        mut proofs: Vec<PathBuf>,
+    ) -> Result<LayeredProof> {
+        let mut current = self.layer_dir(0)?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            log::debug!("Proving layer {} on {} proofs...", i, proofs.len());
+            current.setup(toolchain, &self.link_directories)?;
+            let next = self.layer_dir(i + 1)?;
+            let inputs = if layer.join {
+                proofs
+                    .chunks(layer.arity)
+                    .enumerate()
+                    .map(|(j, group)| {
+                        let joined = current.file(&format!("{}.joined.zkin.json", j));
+                        let zkins = (0..layer.arity)
+                            .map(|k| group[k.min(group.len() - 1)].to_string_lossy().to_string())
+                            .collect::<Vec<_>>();
+                        join_zkins(&zkins, &joined.to_string_lossy())?;
+                        Ok(joined)
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                proofs
+            };
+            proofs = vec![];
+            for (j, zkin) in inputs.iter().enumerate() {
+                let commits = current.file(&format!("{}.cm", j));
+                current.exec(zkin, &commits)?;
+                let next_zkin = next.file(&format!("{}.zkin.json", j));
+                self.stark_prove(
+                    &layer.stark_struct,
+                    &current.pil_json(),
+                    &current.constants(),
+                    &commits,
+                    true,
+                    self.join_arity(i + 1),
+                    &next.circom(),
+                    &next_zkin,
+                )?;
+                proofs.push(next_zkin);
+            }
+            current = next;
+        }
+        let zkin = proofs.remove(0);
+
+        let snark = match &self.snark {
+            Some(snark) => {
+                log::debug!("Proving the last stark by groth16...");
+                Some(current.groth16(toolchain, snark, &zkin)?)
+            }
+            None => None,
+        };
+        Ok(LayeredProof {
+            zkin,
+            circuit: current.circom(),
+            snark,
+        })
+    }
+
+    /// prove the stark, and generate the circuit verifying it, or `agg_arity` of it, with its
+    /// input
+    fn stark_prove(
+        &self,
+        stark_struct: &Path,
+        pil_json: &Path,
+        constants: &Path,
+        commits: &Path,
+        norm_stage: bool,
+        agg_arity: Option<usize>,
+        circom: &Path,
+        zkin: &Path,
+    ) -> Result<()> {
+        stark_prove(
+            &stark_struct.to_string_lossy(),
+            &pil_json.to_string_lossy(),
+            norm_stage,
+            agg_arity.is_some(),
+            agg_arity.unwrap_or_default(),
+            &constants.to_string_lossy(),
+            &commits.to_string_lossy(),
+            &circom.to_string_lossy(),
+            &zkin.to_string_lossy(),
+            &self.prover_addr,
+        )
+    }
+}
+
+fn invalid(msg: String) -> EigenError {
+    EigenError::InvalidInput {
+        path: "layered pipeline".to_string(),
+        msg,
+    }
+}
+
+/// The directory of a layer, with the circuit `verifier.circom` and its compressor12 PIL
+pub struct LayerDir {
+    dir: PathBuf,
+}
+
+impl LayerDir {
+    const NAME: &'static str = "verifier";
+
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn circom(&self) -> PathBuf {
+        self.file(&format!("{}.circom", Self::NAME))
+    }
+
+    fn r1cs(&self) -> PathBuf {
+        self.file(&format!("{}.r1cs", Self::NAME))
+    }
+
+    fn wasm(&self) -> PathBuf {
+        self.file(&format!("{}_js/{}.wasm", Self::NAME, Self::NAME))
+    }
+
+    fn pil(&self) -> PathBuf {
+        self.file(&format!("{}.pil", Self::NAME))
+    }
+
+    /// written by `compressor12_exec`
+    fn pil_json(&self) -> PathBuf {
+        self.file(&format!("{}.pil.json", Self::NAME))
+    }
+
+    fn constants(&self) -> PathBuf {
+        self.file(&format!("{}.const", Self::NAME))
+    }
+
+    fn exec_file(&self) -> PathBuf {
+        self.file(&format!("{}.exec", Self::NAME))
+    }
+
+    /// compile the circuit, and set it up as a compressor12 PIL unless it's done already
+    fn setup<T: Toolchain>(&self, toolchain: &T, link_directories: &[PathBuf]) -> Result<()> {
+        toolchain.compile(&self.circom(), "goldilocks", link_directories)?;
+        if is_fresh(
+            &[self.pil(), self.constants(), self.exec_file()],
+            &self.r1cs(),
+        ) {
+            log::debug!("{} is set up already", self.r1cs().display());
+            return Ok(());
+        }
+        compressor12_setup::setup(
+            &self.r1cs().to_string_lossy(),
+            &self.pil().to_string_lossy(),
+            &self.constants().to_string_lossy(),
+            &self.exec_file().to_string_lossy(),
+            0,
+        )
+    }
+
+    /// the compressor12 commit polynomials of the circuit on `zkin`
+    fn exec(&self, zkin: &Path, commits: &Path) -> Result<()> {
+        compressor12_exec::exec(
+            &zkin.to_string_lossy(),
+            &self.wasm().to_string_lossy(),
+            &self.pil().to_string_lossy(),
+            &self.exec_file().to_string_lossy(),
+            &commits.to_string_lossy(),
+        )
+    }
+
+    fn groth16<T: Toolchain>(
+        &self,
+        toolchain: &T,
+        snark: &SnarkStage,
+        zkin: &Path,
+    ) -> Result<SnarkProof> {
+        let curve = snark.curve.to_uppercase();
+        toolchain.compile(&self.circom(), &curve, &snark.link_directories)?;
+        let (pk, vk) = (self.file("g16.zkey"), self.file("verification_key.json"));
+        if !is_fresh(&[pk.clone(), vk.clone()], &self.r1cs()) {
+            toolchain.groth16_setup(&curve, &self.r1cs(), &pk, &vk)?;
+        }
+        let (public_input, proof) = (self.file("public_input.json"), self.file("proof.json"));
+        toolchain.groth16_prove(
+            &curve,
+            &self.r1cs(),
+            &self.wasm(),
+            &pk,
+            &vk,
+            zkin,
+            &public_input,
+            &proof,
+        )?;
+        Ok(SnarkProof {
+            proof,
+            public_input,
+            verification_key: vk,
+        })
+    }
+}
+
+/// all the `outputs` exist, and are not older than `input`
+fn is_fresh(outputs: &[PathBuf], input: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match modified(input) {
+        Some(input) => outputs
+            .iter()
+            .all(|output| matches!(modified(output), Some(output) if output >= input)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(
+        layers: &[(&str, usize)],
+        num_inputs: usize,
+        curve: Option<&str>,
+    ) -> LayeredPipeline {
+        let input = StarkInput {
+            pil: "data/fib.pil.json.gl".into(),
+            constants: "data/fib.const.gl".into(),
+            commits: "data/fib.cm.gl".into(),
+        };
+        LayeredPipeline {
+            workspace: "/tmp/layered".into(),
+            inputs: vec![input; num_inputs],
+            stark_struct: "data/starkStruct.json.gl".into(),
+            link_directories: vec![],
+            layers: layers
+                .iter()
+                .map(|(stark_struct, arity)| Layer {
+                    stark_struct: format!("data/{}", stark_struct).into(),
+                    join: *arity > 0,
+                    arity: *arity,
+                })
+                .collect(),
+            snark: curve.map(|curve| SnarkStage {
+                curve: curve.to_string(),
+                link_directories: vec![],
+            }),
+            prover_addr: default_prover_addr(),
+        }
+    }
+
+    #[test]
+    fn validate_layered_pipeline() {
+        let c12 = ("c12.starkStruct.json", 0);
+        let join = ("r2.starkStruct.json", 2);
+        let join4 = ("r2.starkStruct.json", 4);
+        let bn128 = ("final.starkStruct.bn128.json", 0);
+        assert!(pipeline(&[c12, bn128], 1, Some("BN128")).validate().is_ok());
+        assert!(pipeline(&[c12, join, join, bn128], 3, Some("bn128"))
+            .validate()
+            .is_ok());
+        assert!(pipeline(&[c12, join4, bn128], 4, Some("BN128"))
+            .validate()
+            .is_ok());
+        assert!(pipeline(&[], 1, None).validate().is_ok());
+
+        // the proofs are not joined
+        assert!(pipeline(&[c12, bn128], 2, Some("BN128"))
+            .validate()
+            .is_err());
+        assert!(pipeline(&[c12, join4, bn128], 5, Some("BN128"))
+            .validate()
+            .is_err());
+        assert!(pipeline(&[c12, ("r2.starkStruct.json", 1)], 1, None)
+            .validate()
+            .is_err());
+        // the input starks have no rootC
+        assert!(pipeline(&[join, bn128], 2, Some("BN128"))
+            .validate()
+            .is_err());
+        assert!(pipeline(&[c12], 0, None).validate().is_err());
+        // a BN128 stark verified by a goldilocks circuit
+        assert!(pipeline(&[bn128, c12], 1, None).validate().is_err());
+        assert!(pipeline(&[c12, bn128], 1, Some("BLS12381"))
+            .validate()
+            .is_err());
+        assert!(pipeline(&[("no_such.json", 0)], 1, None)
+            .validate()
+            .is_err());
+
+        // the starks proven out of the pipeline
+        let external = pipeline(&[c12, join, bn128], 0, Some("BN128"));
+        assert!(external.validate().is_err());
+        assert!(external.validate_layers(2).is_ok());
+        assert!(external.validate_layers(3).is_err());
+        assert!(external.validate_layers(0).is_err());
+        assert!(pipeline(&[], 0, None).validate_layers(1).is_err());
+    }
+
+    #[test]
+    fn fresh_outputs() {
+        let dir = Path::new("/tmp/layered_fresh");
+        std::fs::create_dir_all(dir).unwrap();
+        let (input, output) = (dir.join("verifier.r1cs"), dir.join("verifier.pil"));
+        std::fs::write(&input, "r1cs").unwrap();
+        std::fs::write(&output, "pil").unwrap();
+        assert!(is_fresh(&[output.clone()], &input));
+        assert!(!is_fresh(&[output, dir.join("verifier.exec")], &input));
+        assert!(!is_fresh(&[], &dir.join("no_such.r1cs")));
+    }
+}
@@ -1,14 +1,17 @@
 use crate::digest::ElementDigest;
-use crate::errors::Result;
+use crate::errors::{EigenError, Result};
 use crate::starkinfo::Program;
 use crate::starkinfo::StarkInfo;
 use crate::types::{StarkStruct, PIL};
+use anyhow::bail;
 use profiler_macro::time_profiler;
 
 pub struct StarkOption {
     pub enable_input: bool,
     pub verkey_input: bool,
     pub agg_stage: bool,
+    /// the proofs verified by the aggregation circuit, joined by `join_zkins`
+    pub agg_arity: usize,
     pub skip_main: bool,
 }
 
@@ -21,6 +24,12 @@ pub fn pil2circom(
     program: &mut Program,
     options: &StarkOption,
 ) -> Result<String> {
+    if options.agg_stage && (options.agg_arity < 2 || !options.verkey_input) {
+        bail!(EigenError::InvalidValue(format!(
+            "the aggregation circuit verifies at least 2 proofs with their rootC, got {} with verkey_input {}",
+            options.agg_arity, options.verkey_input
+        )));
+    }
     starkinfo.set_code_dimensions_first(&mut program.verifier_code)?;
     starkinfo.set_code_dimensions_first(&mut program.verifier_query_code)?;
     let res = match stark_struct.verificationHashType.as_str() {
@@ -44,3 +53,64 @@ pub fn pil2circom(
     };
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merklehash::MerkleTreeGL;
+    use crate::polsarray::{PolKind, PolsArray};
+    use crate::stark_setup::StarkSetup;
+    use crate::types::load_json;
+
+    #[test]
+    fn aggregation_circuit_of_arity() {
+        let mut pil = load_json::<PIL>("data/plookup.pil.json.gl").unwrap();
+        let mut const_pol = PolsArray::new(&pil, PolKind::Constant);
+        const_pol.load("data/plookup.const.gl").unwrap();
+        let stark_struct = load_json::<StarkStruct>("data/starkStruct.json.gl").unwrap();
+        let mut setup =
+            StarkSetup::<MerkleTreeGL>::new(&const_pol, &mut pil, &stark_struct, None).unwrap();
+        let mut opt = StarkOption {
+            enable_input: false,
+            verkey_input: true,
+            agg_stage: true,
+            agg_arity: 3,
+            skip_main: false,
+        };
+        let circom = pil2circom(
+            &pil,
+            &setup.const_root,
+            &stark_struct,
+            &mut setup.starkinfo,
+            &mut setup.program,
+            &opt,
+        )
+        .unwrap();
+        assert!(circom.contains("template Recursive3()"));
+        assert!(circom.contains("signal input c_rootC[4];"));
+        assert!(circom.contains("vC.finalPol <== c_finalPol;"));
+        // plookup has stage 2 columns, the stage 3 tree is wired only if it has columns too
+        assert!(setup.starkinfo.map_sectionsN.get("cm2_2ns") > 0);
+        assert!(circom.contains("vA.s0_vals2 <== a_s0_vals2;"));
+        assert!(circom.contains("vC.s0_siblings2 <== c_s0_siblings2;"));
+        let cm3 = setup.starkinfo.map_sectionsN.get("cm3_2ns") > 0;
+        assert_eq!(circom.contains("signal input b_s0_vals3"), cm3);
+        assert_eq!(circom.contains("vB.s0_vals3 <== b_s0_vals3;"), cm3);
+        assert_eq!(circom.contains("vB.s0_siblings3 <== b_s0_siblings3;"), cm3);
+        assert!(!circom.contains("d_rootC"));
+        assert!(circom.contains(
+            "component main {public [a_publics, a_rootC, b_publics, b_rootC, c_publics, c_rootC]}= Recursive3();"
+        ));
+
+        opt.agg_arity = 1;
+        assert!(pil2circom(
+            &pil,
+            &setup.const_root,
+            &stark_struct,
+            &mut setup.starkinfo,
+            &mut setup.program,
+            &opt,
+        )
+        .is_err());
+    }
+}
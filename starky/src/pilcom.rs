@@ -2,19 +2,144 @@
 mod export;
 mod expression_counter;
 
+use crate::errors::{EigenError, Result};
 use crate::types::PIL;
+use anyhow::bail;
 use number::GoldilocksField;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::Path;
 
-pub fn compile_pil_from_str(pil_str: &str) -> PIL {
-    let analyze = pil_analyzer::analyze_string::<GoldilocksField>(pil_str);
+/// Parse the pil by the fallible parser of powdr, a syntax error is returned with its
+/// `<location>:<line>`, instead of being printed and panicked by the analyzer.
+fn parse(location: &str, source: &str) -> Result<()> {
+    if let Err(e) = parser::parse::<GoldilocksField>(Some(location), source) {
+        let offset = e.source_ref().start.min(source.len());
+        let line = source[..offset].matches('\n').count() + 1;
+        bail!(EigenError::PilCompileError {
+            location: format!("{}:{}", location, line),
+            msg: e.message().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Run the powdr analyzer on the parsed pil `source`. It still panics on the semantic errors,
+/// like an undefined symbol, so its message is returned as an error at `<location>:<line>`, as
+/// long as the panics unwind. The line is of the statement the analyzer panicked at.
+fn analyze<T>(location: &str, source: &str, analyzer: impl FnOnce() -> T) -> Result<T> {
+    catch_unwind(AssertUnwindSafe(analyzer)).map_err(|payload| {
+        let msg = match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(msg) => msg.to_string(),
+                Err(_) => "pil analyzer panicked".to_string(),
+            },
+        };
+        let location = match panicking_line(source) {
+            Some(line) => format!("{}:{}", location, line),
+            None => location.to_string(),
+        };
+        EigenError::PilCompileError { location, msg }.into()
+    })
+}
 
-    export::export(&analyze)
+/// The line of the first statement of `source` the analyzer panics at. The analyzer goes
+/// through the statements in order, so the shortest panicking prefix is bisected.
+fn panicking_line(source: &str) -> Option<usize> {
+    let ends = statement_ends(source);
+    let panics = |end: usize| {
+        let prefix = &source[..end];
+        parser::parse::<GoldilocksField>(None, prefix).is_ok()
+            && catch_unwind(|| pil_analyzer::analyze_string::<GoldilocksField>(prefix)).is_err()
+    };
+    if !panics(*ends.last()?) {
+        return None;
+    }
+    let (mut lo, mut hi) = (0, ends.len() - 1);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if panics(ends[mid]) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    // skip the spaces and the comments before the statement
+    let mut rest = source[if lo == 0 { 0 } else { ends[lo - 1] }..].trim_start();
+    loop {
+        rest = if let Some(comment) = rest.strip_prefix("//") {
+            comment.split_once('\n').map_or("", |(_, r)| r).trim_start()
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.split_once("*/").map_or("", |(_, r)| r).trim_start()
+        } else {
+            break;
+        };
+    }
+    let statement = source.len() - rest.len();
+    Some(source[..statement].matches('\n').count() + 1)
 }
-pub fn compile_pil_from_path(pil_path: &str) -> PIL {
-    let analyze = pil_analyzer::analyze::<GoldilocksField>(Path::new(pil_path));
 
-    export::export(&analyze)
+/// The byte offsets after the `;` ending the top level statements, skipping the comments,
+/// the strings and the blocks.
+fn statement_ends(source: &str) -> Vec<usize> {
+    let bytes = source.as_bytes();
+    let mut ends = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !bytes[i..].starts_with(b"*/") {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'{' | b'(' | b'[' => depth += 1,
+            b'}' | b')' | b']' => depth = depth.saturating_sub(1),
+            b';' if depth == 0 => ends.push(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    ends
+}
+
+pub fn compile_pil_from_str(pil_str: &str) -> Result<PIL> {
+    parse("<string>", pil_str)?;
+    let analyzed = analyze("<string>", pil_str, || {
+        pil_analyzer::analyze_string::<GoldilocksField>(pil_str)
+    })?;
+
+    export::export(&analyzed)
+}
+
+pub fn compile_pil_from_path(pil_path: &str) -> Result<PIL> {
+    let source = std::fs::read_to_string(pil_path)?;
+    parse(pil_path, &source)?;
+    let analyzed = analyze(pil_path, &source, || {
+        pil_analyzer::analyze::<GoldilocksField>(Path::new(pil_path))
+    })?;
+
+    export::export(&analyzed)
+}
+
+/// Compile the `.pil` file into the `pil.json` of the js pilcom
+pub fn compile_pil(pil_path: &str, out_path: &str) -> Result<()> {
+    let pil = compile_pil_from_path(pil_path)?;
+    std::fs::write(out_path, serde_json::to_string_pretty(&pil)?)?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -22,28 +147,83 @@ mod test {
     use super::*;
     use crate::types::load_json;
     use std::fs;
-    use std::fs::File;
-    use std::io::Write;
-    use std::path::Path;
 
-    // This test is check whether the js_res the js version pilcom.compile equals to compile_pil_from_str.
+    const FIB_PIL: &str = "../starkjs/fibonacci/fibonacci.pil";
+
+    // check field by field, that compile_pil_from_path equals to the js version pilcom.compile
+    fn assert_eq_pil(actual: &PIL, target: &PIL) {
+        assert_eq!(actual.nCommitments, target.nCommitments);
+        assert_eq!(actual.nQ, target.nQ);
+        assert_eq!(actual.nIm, target.nIm);
+        assert_eq!(actual.nConstants, target.nConstants);
+        assert_eq!(actual.publics, target.publics);
+        assert_eq!(actual.references, target.references);
+        assert_eq!(actual.expressions, target.expressions);
+        assert_eq!(actual.polIdentities, target.polIdentities);
+        assert_eq!(actual.plookupIdentities, target.plookupIdentities);
+        assert_eq!(actual.permutationIdentities, target.permutationIdentities);
+        assert_eq!(actual.connectionIdentities, target.connectionIdentities);
+    }
+
+    #[test]
+    fn test_compile_pil_from_path() {
+        let actual = compile_pil_from_path(FIB_PIL).unwrap();
+        let target = load_json::<PIL>("data/fib.pil.json").unwrap();
+        assert_eq_pil(&actual, &target);
+
+        let out = "/tmp/starky_fib.pil.json";
+        compile_pil(FIB_PIL, out).unwrap();
+        assert_eq_pil(&load_json::<PIL>(out).unwrap(), &target);
+    }
+
     #[test]
     fn test_compile_pil_from_str() {
-        let path = Path::new("../starkjs/fibonacci/fibonacci.pil")
-            .canonicalize()
-            .unwrap();
-
-        let pil_str = fs::read_to_string(path.clone()).unwrap();
-        // The target and actual pil_json
-        let actual = compile_pil_from_str(&pil_str);
-        let _target = load_json::<PIL>("data/fib.pil.json").unwrap();
-
-        // This will meet error, as the polArray.name are different.
-        // assert_eq!(actual, target);
-
-        // Check the file manually.
-        let mut file = File::create(Path::new("data/fib2.pil.json")).unwrap();
-        let input = serde_json::to_string_pretty(&actual).unwrap();
-        write!(file, "{}", input).unwrap();
+        let pil_str = fs::read_to_string(FIB_PIL).unwrap();
+        let actual = compile_pil_from_str(&pil_str).unwrap();
+        let target = load_json::<PIL>("data/fib.pil.json").unwrap();
+
+        // the file name is unknown from a string
+        assert_eq!(actual.expressions, target.expressions);
+        assert_eq!(actual.references, target.references);
+        for (a, t) in actual.polIdentities.iter().zip(&target.polIdentities) {
+            assert_eq!((a.e, a.line), (t.e, t.line));
+        }
+    }
+
+    #[test]
+    fn test_compile_pil_error() {
+        // a semantic error, b is undefined
+        let source = "namespace A(4);\npol commit a;\n// b\na * b = 0;\na = 0;\n";
+        let err = compile_pil_from_str(source).unwrap_err();
+        match err.downcast_ref::<EigenError>() {
+            Some(EigenError::PilCompileError { location, .. }) => {
+                assert_eq!(location, "<string>:4")
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        let path = "/tmp/starky_undefined.pil";
+        fs::write(path, source).unwrap();
+        let err = compile_pil_from_path(path).unwrap_err();
+        match err.downcast_ref::<EigenError>() {
+            Some(EigenError::PilCompileError { location, .. }) => {
+                assert_eq!(location, &format!("{}:4", path))
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // a syntax error, the semicolon is missing
+        let err = compile_pil_from_str("namespace A(4);\npol commit a\na = 0;\n").unwrap_err();
+        match err.downcast_ref::<EigenError>() {
+            Some(EigenError::PilCompileError { location, .. }) => {
+                assert_eq!(location, "<string>:3")
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let err = compile_pil_from_path("/tmp/no_such_file.pil").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+            Some(std::io::ErrorKind::NotFound)
+        );
     }
 }
@@ -2,6 +2,9 @@
 use number::FieldElement;
 use std::cmp;
 use std::collections::HashMap;
+use std::path::Path;
+
+use crate::errors::{EigenError, Result};
 
 use crate::types::{
     ConnectionIdentity, Expression as StarkyExpr, PermutationIdentity, PlookupIdentity,
@@ -35,7 +38,22 @@ struct Exporter<'a, T> {
     number_q: u64,
 }
 
-pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> PIL {
+fn error(location: impl Into<String>, msg: impl Into<String>) -> EigenError {
+    EigenError::PilCompileError {
+        location: location.into(),
+        msg: msg.into(),
+    }
+}
+
+/// Like the js pilcom, the file name of an identity is without the directories
+fn file_name(file: &str) -> String {
+    Path::new(file)
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.to_string())
+}
+
+pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> Result<PIL> {
     let mut exporter = Exporter::new(analyzed);
     let mut publics = Vec::new();
     let mut pol_identities = Vec::new();
@@ -46,18 +64,27 @@ pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> PIL {
         match item {
             StatementIdentifier::Definition(name) => {
                 if let Some((poly, value)) = analyzed.intermediate_columns.get(name) {
-                    assert_eq!(poly.kind, SymbolKind::Poly(PolynomialType::Intermediate));
-                    let expression_id = exporter.extract_expression(value, 1);
-                    assert_eq!(
-                        expression_id,
-                        exporter.intermediate_poly_expression_ids[&poly.id] as usize
-                    );
+                    if poly.kind != SymbolKind::Poly(PolynomialType::Intermediate) {
+                        return Err(error(name, "not an intermediate polynomial").into());
+                    }
+                    let expression_id = exporter
+                        .extract_expression(value, 1)
+                        .map_err(|e| error(name, e.to_string()))?;
+                    if expression_id != exporter.intermediate_poly_expression_ids[&poly.id] as usize
+                    {
+                        return Err(error(name, "unexpected intermediate expression id").into());
+                    }
                 }
             }
             StatementIdentifier::PublicDeclaration(name) => {
                 let pub_def = &analyzed.public_declarations[name];
                 let pub_ref = &pub_def.polynomial;
-                let poly_id = pub_ref.poly_id.unwrap();
+                let poly_id = match pub_ref.poly_id {
+                    Some(poly_id) => poly_id,
+                    None => {
+                        return Err(error(name, "public of an unknown polynomial").into());
+                    }
+                };
                 let (_, expr) = exporter.polynomial_reference_to_json(
                     PolyID {
                         id: poly_id.id + pub_def.array_index.unwrap_or_default() as u64,
@@ -76,21 +103,27 @@ pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> PIL {
             }
             StatementIdentifier::Identity(id) => {
                 let identity = &analyzed.identities[*id];
-                let file_name = identity.source.file.clone();
+                let file_name = file_name(&identity.source.file);
                 let line = identity.source.line;
+                let location = format!("{}:{}", file_name, line);
                 let selector_degree = if identity.kind == IdentityKind::Polynomial {
                     2
                 } else {
                     1
                 };
-                let left = exporter.extract_expression_vec(&identity.left.expressions, 1);
-                let sel_left =
-                    exporter.extract_expression_opt(&identity.left.selector, selector_degree);
-                let right = exporter.extract_expression_vec(&identity.right.expressions, 1);
-                let sel_right = exporter.extract_expression_opt(&identity.right.selector, 1);
+                let (left, sel_left, right, sel_right) = (|| -> Result<_> {
+                    Ok((
+                        exporter.extract_expression_vec(&identity.left.expressions, 1)?,
+                        exporter
+                            .extract_expression_opt(&identity.left.selector, selector_degree)?,
+                        exporter.extract_expression_vec(&identity.right.expressions, 1)?,
+                        exporter.extract_expression_opt(&identity.right.selector, 1)?,
+                    ))
+                })()
+                .map_err(|e| error(&location, e.to_string()))?;
                 match identity.kind {
                     IdentityKind::Polynomial => pol_identities.push(PolIdentity {
-                        e: sel_left.unwrap(),
+                        e: sel_left.ok_or_else(|| error(&location, "empty polynomial identity"))?,
                         fileName: file_name,
                         line,
                     }),
@@ -126,13 +159,13 @@ pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> PIL {
             }
         }
     }
-    PIL {
+    Ok(PIL {
         nCommitments: analyzed.commitment_count(),
         nQ: exporter.number_q as usize,
         nIm: analyzed.intermediate_count(),
         nConstants: analyzed.constant_count(),
         publics,
-        references: exporter.references(),
+        references: exporter.references()?,
         expressions: exporter.expressions,
         polIdentities: pol_identities,
         plookupIdentities: plookup_identities,
@@ -140,7 +173,7 @@ pub fn export<T: FieldElement>(analyzed: &Analyzed<T>) -> PIL {
         connectionIdentities: Some(connection_identities),
         cm_dims: Vec::new(),
         q2exp: Vec::new(),
-    }
+    })
 }
 
 fn symbol_kind_to_json_string(k: SymbolKind) -> &'static str {
@@ -182,14 +215,14 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
         }
     }
 
-    fn references(&self) -> HashMap<String, Reference> {
+    fn references(&self) -> Result<HashMap<String, Reference>> {
         self.analyzed
             .definitions
             .iter()
             .filter_map(|(name, (symbol, _value))| {
                 let id = match symbol.kind {
                     SymbolKind::Poly(PolynomialType::Intermediate) => {
-                        panic!("Should be in intermediates")
+                        return Some(Err(error(name, "should be in intermediates").into()))
                     }
                     SymbolKind::Poly(_) => Some(symbol.id),
                     SymbolKind::Other() | SymbolKind::Constant() => None,
@@ -204,14 +237,16 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
                     elementType: None,
                     len: symbol.length.map(|l| l as usize),
                 };
-                Some((name.clone(), out))
+                Some(Ok((name.clone(), out)))
             })
             .chain(
                 self.analyzed
                     .intermediate_columns
                     .iter()
                     .map(|(name, (symbol, _))| {
-                        assert_eq!(symbol.kind, SymbolKind::Poly(PolynomialType::Intermediate));
+                        if symbol.kind != SymbolKind::Poly(PolynomialType::Intermediate) {
+                            return Err(error(name, "not an intermediate polynomial").into());
+                        }
                         let id = self.intermediate_poly_expression_ids[&symbol.id];
 
                         let out = Reference {
@@ -223,44 +258,49 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
                             elementType: None,
                             len: symbol.length.map(|l| l as usize),
                         };
-                        (name.clone(), out)
+                        Ok((name.clone(), out))
                     }),
             )
-            .collect::<HashMap<String, Reference>>()
+            .collect::<Result<HashMap<String, Reference>>>()
     }
 
     /// Processes the given expression
     /// @returns the expression ID
-    fn extract_expression(&mut self, expr: &Expression<T>, max_degree: u32) -> usize {
+    fn extract_expression(&mut self, expr: &Expression<T>, max_degree: u32) -> Result<usize> {
         let id = self.expressions.len();
-        let (degree, mut expr) = self.expression_to_json(expr);
+        let (degree, mut expr) = self.expression_to_json(expr)?;
         if degree > max_degree {
             expr.idQ = Some(self.number_q as usize);
             expr.deg = 1;
             self.number_q += 1;
         }
         self.expressions.push(expr);
-        id
+        Ok(id)
     }
 
     fn extract_expression_opt(
         &mut self,
         expr: &Option<Expression<T>>,
         max_degree: u32,
-    ) -> Option<usize> {
+    ) -> Result<Option<usize>> {
         expr.as_ref()
             .map(|e| self.extract_expression(e, max_degree))
+            .transpose()
     }
 
-    fn extract_expression_vec(&mut self, expr: &[Expression<T>], max_degree: u32) -> Vec<usize> {
+    fn extract_expression_vec(
+        &mut self,
+        expr: &[Expression<T>],
+        max_degree: u32,
+    ) -> Result<Vec<usize>> {
         expr.iter()
             .map(|e| self.extract_expression(e, max_degree))
             .collect()
     }
 
     /// returns the degree and the JSON value (intermediate polynomial IDs)
-    fn expression_to_json(&self, expr: &Expression<T>) -> (u32, StarkyExpr) {
-        match expr {
+    fn expression_to_json(&self, expr: &Expression<T>) -> Result<(u32, StarkyExpr)> {
+        Ok(match expr {
             Expression::Reference(reference) => {
                 self.polynomial_reference_to_json(reference.poly_id, reference.next)
             }
@@ -283,18 +323,19 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
                 },
             ),
             Expression::BinaryOperation(left, op, right) => {
-                let (deg_left, left) = self.expression_to_json(left);
-                let (deg_right, right) = self.expression_to_json(right);
+                let (deg_left, left) = self.expression_to_json(left)?;
+                let (deg_right, right) = self.expression_to_json(right)?;
                 let (op, degree) = match op {
                     AlgebraicBinaryOperator::Add => ("add", cmp::max(deg_left, deg_right)),
                     AlgebraicBinaryOperator::Sub => ("sub", cmp::max(deg_left, deg_right)),
                     AlgebraicBinaryOperator::Mul => ("mul", deg_left + deg_right),
                     AlgebraicBinaryOperator::Pow => {
-                        assert_eq!(
-                            deg_left + deg_right,
-                            0,
-                            "Exponentiation can only be used on constants."
-                        );
+                        if deg_left + deg_right != 0 {
+                            return Err(EigenError::ExpressionError(
+                                "exponentiation can only be used on constants".to_string(),
+                            )
+                            .into());
+                        }
                         ("pow", deg_left + deg_right)
                     }
                 };
@@ -309,7 +350,7 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
                 )
             }
             Expression::UnaryOperation(op, value) => {
-                let (deg, value) = self.expression_to_json(value);
+                let (deg, value) = self.expression_to_json(value)?;
                 match op {
                     AlgebraicUnaryOperator::Plus => (deg, value),
                     AlgebraicUnaryOperator::Minus => (
@@ -323,7 +364,7 @@ impl<'a, T: FieldElement> Exporter<'a, T> {
                     ),
                 }
             }
-        }
+        })
     }
 
     fn polynomial_reference_to_json(
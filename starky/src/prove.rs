@@ -26,6 +26,7 @@ pub fn stark_prove(
     pil_file: &str,
     norm_stage: bool,
     agg_stage: bool,
+    agg_arity: usize,
     const_pol_file: &str,
     cm_pol_file: &str,
     circom_file: &str,
@@ -47,6 +48,7 @@ pub fn stark_prove(
             &cm_pol,
             &stark_struct,
             false,
+            0,
             norm_stage,
             circom_file,
             zkin,
@@ -58,6 +60,7 @@ pub fn stark_prove(
             &cm_pol,
             &stark_struct,
             false,
+            0,
             norm_stage,
             circom_file,
             zkin,
@@ -69,6 +72,7 @@ pub fn stark_prove(
             &cm_pol,
             &stark_struct,
             agg_stage,
+            agg_arity,
             norm_stage,
             circom_file,
             zkin,
@@ -86,6 +90,7 @@ fn prove<M: MerkleTree<MTNode = ElementDigest<4>>, T: Transcript>(
     cm_pol: &PolsArray,
     stark_struct: &StarkStruct,
     agg_stage: bool,
+    agg_arity: usize,
     norm_stage: bool,
     circom_file: &str,
     zkin: &str,
@@ -120,6 +125,7 @@ fn prove<M: MerkleTree<MTNode = ElementDigest<4>>, T: Transcript>(
         verkey_input: norm_stage,
         skip_main: false,
         agg_stage,
+        agg_arity,
     };
 
     let str_ver = pil2circom::pil2circom(
@@ -4,6 +4,7 @@ use plonky::circom_circuit::Constraint;
 use plonky::circom_circuit::R1CS;
 use plonky::field_gl::Fr as FGL;
 use plonky::field_gl::{Fr, GL};
+use std::borrow::Borrow;
 use std::collections::BTreeMap;
 use std::ops::Neg;
 
@@ -65,7 +66,21 @@ impl std::fmt::Display for PlonkAdd {
 }
 
 pub fn r1cs2plonk(r1cs: &R1CS<GL>) -> (Vec<PlonkGate>, Vec<PlonkAdd>) {
-    let mut plonk_n_var = r1cs.num_variables;
+    r1cs2plonk_streaming(r1cs.num_variables, r1cs.constraints.iter().map(Ok))
+        .expect("reading constraints from memory never fails")
+}
+
+/// Transform the constraints one by one, e.g. read by `algebraic::reader::load_r1cs_streaming`,
+/// without holding all of them in memory.
+pub fn r1cs2plonk_streaming<C, I>(
+    num_variables: usize,
+    constraints: I,
+) -> std::io::Result<(Vec<PlonkGate>, Vec<PlonkAdd>)>
+where
+    C: Borrow<Constraint<GL>>,
+    I: Iterator<Item = std::io::Result<C>>,
+{
+    let mut plonk_n_var = num_variables;
     let mut plonk_constraints: Vec<PlonkGate> = vec![];
     let mut plonk_additions: Vec<PlonkAdd> = vec![];
 
@@ -235,18 +250,20 @@ pub fn r1cs2plonk(r1cs: &R1CS<GL>) -> (Vec<PlonkGate>, Vec<PlonkAdd>) {
             }
         };
 
-    for (i, c) in r1cs.constraints.iter().enumerate() {
+    let n_constraints = constraints.size_hint().0;
+    for (i, c) in constraints.enumerate() {
         if i % 100000 == 0 {
-            log::trace!("processing constraints: {}/{}", i, r1cs.constraints.len());
+            log::trace!("processing constraints: {}/{}", i, n_constraints);
         }
+        let c = c?;
         process(
-            c,
+            Borrow::<Constraint<GL>>::borrow(&c),
             &mut plonk_constraints,
             &mut plonk_additions,
             &mut plonk_n_var,
         );
     }
-    (plonk_constraints, plonk_additions)
+    Ok((plonk_constraints, plonk_additions))
 }
 
 #[cfg(test)]
@@ -272,7 +289,7 @@ mod test {
         let CIRCUIT = "fib.verifier";
 
         let r1cs_file = format!("/tmp/{CIRCUIT}.r1cs");
-        let r1cs = load_r1cs::<GL>(&r1cs_file);
+        let r1cs = load_r1cs::<GL>(&r1cs_file).unwrap();
 
         let (plonk_constrains, plonk_additions) = r1cs2plonk(&r1cs);
 
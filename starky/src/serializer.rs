@@ -1,15 +1,22 @@
 // input json of plonk
 #![allow(non_snake_case)]
+use crate::digest::ElementDigest;
+use crate::errors::{EigenError, Result};
 use crate::f3g::F3G;
 use crate::f5g::F5G;
 use crate::field_bls12381::Fr as Fr_bls12381;
 use crate::field_bn128::Fr;
+use crate::fri::FRIProof;
 use crate::helper;
+use crate::merklehash::MerkleTreeGL;
 use crate::stark_gen::StarkProof;
 use crate::traits::FieldExtension;
 use crate::traits::{MTNodeType, MerkleTree};
+use crate::types::{StarkStruct, Step};
+use anyhow::bail;
 use plonky::field_gl::Fr as FGL;
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::Value;
 
 impl Serialize for F3G {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -315,3 +322,253 @@ impl<M: MerkleTree> Serialize for StarkProof<M> {
         map.end()
     }
 }
+
+/// Parse the zkin of a `StarkProof` on Goldilocks back, it's what `Serialize` writes, like the
+/// eStark proofs of powdr. The `stark_struct` isn't in the zkin.
+impl StarkProof<MerkleTreeGL> {
+    pub fn from_zkin(zkin: &Value, stark_struct: &StarkStruct) -> Result<Self> {
+        // an element is canonical, less than the Goldilocks prime
+        let field = |v: &Value, key: &str| -> Result<FGL> {
+            match v.as_str().and_then(|s| s.parse::<u64>().ok()) {
+                Some(e) if e < GOLDILOCKS_PRIME => Ok(FGL::from(e)),
+                _ => bail!(invalid(key, format!("invalid element {}", v))),
+            }
+        };
+        let array = |v: &Value, key: &str| -> Result<Vec<Value>> {
+            match v.as_array() {
+                Some(a) => Ok(a.clone()),
+                None => bail!(invalid(key, format!("expect an array, found {}", v))),
+            }
+        };
+        let get = |key: &str| -> Result<Value> {
+            match zkin.get(key) {
+                Some(v) => Ok(v.clone()),
+                None => bail!(invalid(key, "missing".to_string())),
+            }
+        };
+        // a digest of one element is written as the element
+        let node = |v: &Value, key: &str| -> Result<ElementDigest<4>> {
+            let mut e = [FGL::ZERO; 4];
+            if v.is_string() {
+                e[0] = field(v, key)?;
+            } else {
+                let elems = array(v, key)?;
+                if elems.len() != 4 {
+                    bail!(invalid(key, format!("expect 4 elements, found {}", v)));
+                }
+                for (i, x) in elems.iter().enumerate() {
+                    e[i] = field(x, key)?;
+                }
+            }
+            Ok(ElementDigest::new(&e))
+        };
+        let ext = |v: &Value, key: &str| -> Result<F3G> {
+            if v.is_string() {
+                return Ok(F3G::from(field(v, key)?));
+            }
+            match &array(v, key)?[..] {
+                [a, b, c] => Ok(F3G::new(field(a, key)?, field(b, key)?, field(c, key)?)),
+                _ => bail!(invalid(key, format!("expect 3 elements, found {}", v))),
+            }
+        };
+        let exts = |key: &str| -> Result<Vec<F3G>> {
+            array(&get(key)?, key)?
+                .iter()
+                .map(|v| ext(v, key))
+                .collect()
+        };
+        // the (values, siblings) of the queries of a tree, empty if the tree has no column
+        let queries =
+            |vals_key: &str, siblings_key: &str| -> Result<Vec<(Vec<FGL>, Vec<Vec<FGL>>)>> {
+                let (vals, siblings) = match (zkin.get(vals_key), zkin.get(siblings_key)) {
+                    (Some(vals), Some(siblings)) => {
+                        (array(vals, vals_key)?, array(siblings, siblings_key)?)
+                    }
+                    (None, None) => return Ok(vec![(vec![], vec![]); stark_struct.nQueries]),
+                    _ => bail!(invalid(
+                        vals_key,
+                        format!("{} without {}", vals_key, siblings_key)
+                    )),
+                };
+                if vals.is_empty() && siblings.is_empty() {
+                    return Ok(vec![(vec![], vec![]); stark_struct.nQueries]);
+                }
+                if vals.len() != stark_struct.nQueries || siblings.len() != stark_struct.nQueries {
+                    bail!(invalid(
+                        vals_key,
+                        format!("expect {} queries", stark_struct.nQueries)
+                    ));
+                }
+                vals.iter()
+                    .zip(siblings.iter())
+                    .map(|(v, s)| {
+                        let v = array(v, vals_key)?
+                            .iter()
+                            .map(|e| field(e, vals_key))
+                            .collect::<Result<Vec<_>>>()?;
+                        let s = array(s, siblings_key)?
+                            .iter()
+                            .map(|level| {
+                                array(level, siblings_key)?
+                                    .iter()
+                                    .map(|e| field(e, siblings_key))
+                                    .collect::<Result<Vec<_>>>()
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        Ok((v, s))
+                    })
+                    .collect()
+            };
+
+        let mut fri_proof = FRIProof::<F3G, MerkleTreeGL>::new(stark_struct.steps.len());
+        let trees = ["1", "2", "3", "4", "C"]
+            .iter()
+            .map(|t| queries(&format!("s0_vals{}", t), &format!("s0_siblings{}", t)))
+            .collect::<Result<Vec<_>>>()?;
+        fri_proof.queries[0].pol_queries = (0..stark_struct.nQueries)
+            .map(|q| trees.iter().map(|tree| tree[q].clone()).collect())
+            .collect();
+        for (i, query) in fri_proof.queries.iter_mut().enumerate().skip(1) {
+            let root = format!("s{}_root", i);
+            query.root = node(&get(&root)?, &root)?;
+            query.pol_queries = queries(&format!("s{}_vals", i), &format!("s{}_siblings", i))?
+                .into_iter()
+                .map(|q| vec![q])
+                .collect();
+        }
+        if zkin
+            .get(&format!("s{}_root", stark_struct.steps.len()))
+            .is_some()
+        {
+            bail!(invalid(
+                "steps",
+                format!(
+                    "more steps than the {} of the stark struct",
+                    stark_struct.steps.len()
+                )
+            ));
+        }
+        fri_proof.last = exts("finalPol")?;
+
+        Ok(StarkProof {
+            root1: node(&get("root1")?, "root1")?,
+            root2: node(&get("root2")?, "root2")?,
+            root3: node(&get("root3")?, "root3")?,
+            root4: node(&get("root4")?, "root4")?,
+            fri_proof,
+            evals: exts("evals")?,
+            publics: exts("publics")?,
+            rootC: match zkin.get("rootC") {
+                Some(v) => Some(node(v, "rootC")?),
+                None => None,
+            },
+            stark_struct: stark_struct.clone(),
+            prover_addr: "".to_string(),
+        })
+    }
+
+    /// The stark struct of the zkin of a proof on a pil of `2^n_bits` rows. The queries and the
+    /// FRI steps are read from the shape of the query proofs, a tree has a sibling per level.
+    pub fn stark_struct_of_zkin(zkin: &Value, n_bits: usize) -> Result<StarkStruct> {
+        // the number of queries and the levels of the tree of the queries `key`
+        let shape = |key: &str| -> Result<(usize, usize)> {
+            let queries = zkin.get(key).and_then(|v| v.as_array());
+            match queries.map(|q| (q.len(), q.first().and_then(|s| s.as_array()))) {
+                Some((n, Some(levels))) => Ok((n, levels.len())),
+                _ => bail!(invalid(
+                    key,
+                    "expect the siblings of the queries".to_string()
+                )),
+            }
+        };
+        let (n_queries, n_bits_ext) = shape("s0_siblings1")?;
+        let mut steps = vec![Step { nBits: n_bits_ext }];
+        while zkin.get(&format!("s{}_root", steps.len())).is_some() {
+            let key = format!("s{}_siblings", steps.len());
+            let (n, levels) = shape(&key)?;
+            if n != n_queries {
+                bail!(invalid(&key, format!("expect {} queries", n_queries)));
+            }
+            steps.push(Step { nBits: levels });
+        }
+        Ok(StarkStruct {
+            nBits: n_bits,
+            nBitsExt: n_bits_ext,
+            nQueries: n_queries,
+            verificationHashType: "GL".to_string(),
+            steps,
+        })
+    }
+}
+
+const GOLDILOCKS_PRIME: u64 = 0xFFFFFFFF00000001;
+
+fn invalid(key: &str, msg: String) -> EigenError {
+    EigenError::InvalidInput {
+        path: key.to_string(),
+        msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merklehash::MerkleTreeGL;
+    use crate::polsarray::{PolKind, PolsArray};
+    use crate::stark_gen::StarkProof;
+    use crate::stark_setup::StarkSetup;
+    use crate::stark_verify::stark_verify;
+    use crate::transcript::TranscriptGL;
+    use crate::types::{load_json, StarkStruct, PIL};
+
+    #[test]
+    fn zkin_round_trip() {
+        let mut pil = load_json::<PIL>("data/plookup.pil.json.gl").unwrap();
+        let mut const_pol = PolsArray::new(&pil, PolKind::Constant);
+        const_pol.load("data/plookup.const.gl").unwrap();
+        let mut cm_pol = PolsArray::new(&pil, PolKind::Commit);
+        cm_pol.load("data/plookup.cm.gl").unwrap();
+        let stark_struct = load_json::<StarkStruct>("data/starkStruct.json.gl").unwrap();
+        let mut setup =
+            StarkSetup::<MerkleTreeGL>::new(&const_pol, &mut pil, &stark_struct, None).unwrap();
+        let starkproof = StarkProof::<MerkleTreeGL>::stark_gen::<TranscriptGL>(
+            &cm_pol,
+            &const_pol,
+            &setup.const_tree,
+            &setup.starkinfo,
+            &setup.program,
+            &pil,
+            &stark_struct,
+            "",
+        )
+        .unwrap();
+
+        let zkin = serde_json::to_value(&starkproof).unwrap();
+        let parsed = StarkProof::<MerkleTreeGL>::from_zkin(&zkin, &stark_struct).unwrap();
+        assert_eq!(zkin, serde_json::to_value(&parsed).unwrap());
+        assert!(stark_verify::<MerkleTreeGL, TranscriptGL>(
+            &parsed,
+            &setup.const_root,
+            &setup.starkinfo,
+            &stark_struct,
+            &mut setup.program,
+        )
+        .unwrap());
+
+        let mut broken = zkin.clone();
+        broken.as_object_mut().unwrap().remove("root1");
+        assert!(StarkProof::<MerkleTreeGL>::from_zkin(&broken, &stark_struct).is_err());
+
+        // a non-canonical element, the prime itself
+        let mut broken = zkin.clone();
+        broken["evals"][0][0] = serde_json::json!("18446744069414584321");
+        assert!(StarkProof::<MerkleTreeGL>::from_zkin(&broken, &stark_struct).is_err());
+
+        // the stark struct is recovered from the shape of the proof
+        let recovered =
+            StarkProof::<MerkleTreeGL>::stark_struct_of_zkin(&zkin, stark_struct.nBits).unwrap();
+        assert_eq!(
+            serde_json::to_value(&recovered).unwrap(),
+            serde_json::to_value(&stark_struct).unwrap()
+        );
+    }
+}
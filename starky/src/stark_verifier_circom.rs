@@ -10,6 +10,7 @@ use crate::starkinfo_codegen::Section;
 use crate::traits::FieldExtension;
 use crate::traits::MTNodeType;
 use crate::types::{StarkStruct, PIL};
+use crate::zkin_join::zkin_prefix;
 use profiler_macro::time_profiler;
 
 fn header() -> String {
@@ -1518,290 +1519,210 @@ template StarkVerifier() {{
     ///////
 
     if options.agg_stage {
-        res.push_str(&format!(
-            r#"
-template Recursive2() {{
-    signal input a_publics[{}];
-    signal input a_root1[4];
-    signal input a_root2[4];
-    signal input a_root3[4];
-    signal input a_root4[4];
-
-    signal input b_publics[{}];
-    signal input b_root1[4];
-    signal input b_root2[4];
-    signal input b_root3[4];
-    signal input b_root4[4];
-    "#,
-            pil.publics.len(),
-            pil.publics.len()
+        res.push_str(&recursive_verifier(
+            starkinfo,
+            pil,
+            stark_struct,
+            options.agg_arity,
         ));
+    }
 
-        res.push_str(
-            r#"
-    signal input a_rootC[4];
-    signal input b_rootC[4];
-"#,
-        );
+    if !options.skip_main {
+        if options.agg_stage {
+            let publics = (0..options.agg_arity)
+                .map(|i| {
+                    let p = zkin_prefix(i);
+                    format!("{p}publics, {p}rootC")
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            res.push_str(&format!(
+                r#"
+component main {{public [{}]}}= Recursive{}();"#,
+                publics, options.agg_arity
+            ));
+        } else if options.verkey_input {
+            res.push_str(
+                r#"
+component main {public [publics, rootC]}= StarkVerifier();
+    "#,
+            );
+        } else {
+            res.push_str(
+                r#"
+component main {public [publics]}= StarkVerifier();
+    "#,
+            );
+        }
+    }
+    res
+}
 
+/// The template `Recursive<arity>` verifying `arity` proofs, the inputs of the `i`th one are
+/// prefixed by `zkin_prefix(i)`, like the zkin joined by `join_zkins`
+fn recursive_verifier(
+    starkinfo: &StarkInfo,
+    pil: &PIL,
+    stark_struct: &StarkStruct,
+    arity: usize,
+) -> String {
+    let prefixes = (0..arity).map(zkin_prefix).collect::<Vec<_>>();
+    let n_queries = stark_struct.nQueries;
+    let n_bits0 = stark_struct.steps[0].nBits;
+    let cm2 = starkinfo.map_sectionsN.get("cm2_2ns");
+    let cm3 = starkinfo.map_sectionsN.get("cm3_2ns");
+    let mut res = format!(
+        r#"
+template Recursive{}() {{"#,
+        arity
+    );
+    for p in &prefixes {
         res.push_str(&format!(
             r#"
-    signal input a_evals[{}][3];
-    signal input a_s0_vals1[{}][{}];
-
-    signal input b_evals[{}][3];
-    signal input b_s0_vals1[{}][{}];
-        "#,
+    signal input {p}publics[{}];
+    signal input {p}root1[4];
+    signal input {p}root2[4];
+    signal input {p}root3[4];
+    signal input {p}root4[4];
+    signal input {p}rootC[4];
+
+    signal input {p}evals[{}][3];
+    signal input {p}s0_vals1[{}][{}];
+"#,
+            pil.publics.len(),
             starkinfo.ev_map.len(),
-            stark_struct.nQueries,
+            n_queries,
             starkinfo.map_sectionsN.get("cm1_2ns"),
-            starkinfo.ev_map.len(),
-            stark_struct.nQueries,
-            starkinfo.map_sectionsN.get("cm1_2ns")
         ));
-
-        if starkinfo.map_sectionsN.get("cm2_2ns") > 0 {
+        if cm2 > 0 {
             res.push_str(&format!(
                 r#"
-    signal input a_s0_vals2[{}][{}];
-    signal input b_s0_vals2[{}][{}];
-            "#,
-                stark_struct.nQueries,
-                starkinfo.map_sectionsN.get("cm2_2ns"),
-                stark_struct.nQueries,
-                starkinfo.map_sectionsN.get("cm2_2ns")
+    signal input {p}s0_vals2[{}][{}];
+"#,
+                n_queries, cm2
             ));
         }
-
-        if starkinfo.map_sectionsN.get("cm3_2ns") > 0 {
+        if cm3 > 0 {
             res.push_str(&format!(
                 r#"
-    signal input a_s0_vals3[{}][{}];
-
-    signal input b_s0_vals3[{}][{}];
-            "#,
-                stark_struct.nQueries,
-                starkinfo.map_sectionsN.get("cm3_2ns"),
-                stark_struct.nQueries,
-                starkinfo.map_sectionsN.get("cm3_2ns")
+    signal input {p}s0_vals3[{}][{}];
+"#,
+                n_queries, cm3
             ));
         }
-
         res.push_str(&format!(
             r#"
-    signal input a_s0_vals4[{}][{}];
-    signal input a_s0_valsC[{}][{}];
-    signal input a_s0_siblings1[{}][{}][4];
-
-    signal input b_s0_vals4[{}][{}];
-    signal input b_s0_valsC[{}][{}];
-    signal input b_s0_siblings1[{}][{}][4];
-    "#,
-            stark_struct.nQueries,
-            starkinfo.map_sectionsN.get("cm4_2ns"),
-            stark_struct.nQueries,
-            starkinfo.n_constants,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits,
-            stark_struct.nQueries,
+    signal input {p}s0_vals4[{}][{}];
+    signal input {p}s0_valsC[{}][{}];
+    signal input {p}s0_siblings1[{}][{}][4];
+"#,
+            n_queries,
             starkinfo.map_sectionsN.get("cm4_2ns"),
-            stark_struct.nQueries,
+            n_queries,
             starkinfo.n_constants,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits
+            n_queries,
+            n_bits0,
         ));
-
-        if starkinfo.map_sectionsN.get("cm2_2ns") > 0 {
+        if cm2 > 0 {
             res.push_str(&format!(
                 r#"
-    signal input a_s0_siblings2[{}][{}][4];
-
-    signal input b_s0_siblings2[{}][{}][4];
-            "#,
-                stark_struct.nQueries,
-                stark_struct.steps[0].nBits,
-                stark_struct.nQueries,
-                stark_struct.steps[0].nBits
+    signal input {p}s0_siblings2[{}][{}][4];
+"#,
+                n_queries, n_bits0
             ));
         }
-
-        if starkinfo.map_sectionsN.get("cm3_2ns") > 0 {
+        if cm3 > 0 {
             res.push_str(&format!(
                 r#"
-    signal input a_s0_siblings3[{}][{}][4];
-
-    signal input b_s0_siblings3[{}][{}][4];
-            "#,
-                stark_struct.nQueries,
-                stark_struct.steps[0].nBits,
-                stark_struct.nQueries,
-                stark_struct.steps[0].nBits,
+    signal input {p}s0_siblings3[{}][{}][4];
+"#,
+                n_queries, n_bits0
             ));
         }
-
         res.push_str(&format!(
             r#"
-    signal input a_s0_siblings4[{}][{}][4];
-    signal input a_s0_siblingsC[{}][{}][4];
-
-    signal input b_s0_siblings4[{}][{}][4];
-    signal input b_s0_siblingsC[{}][{}][4];
-            "#,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits,
-            stark_struct.nQueries,
-            stark_struct.steps[0].nBits
+    signal input {p}s0_siblings4[{}][{}][4];
+    signal input {p}s0_siblingsC[{}][{}][4];
+"#,
+            n_queries, n_bits0, n_queries, n_bits0
         ));
-
-        for s in 0..(stark_struct.steps.len() - 1) {
-            res.push_str(&format!(
-                r#"
-        signal input a_s{}_root[4];
-
-        signal input b_s{}_root[4];
-            "#,
-                s + 1,
-                s + 1
-            ));
-        }
-
         for s in 1..stark_struct.steps.len() {
             res.push_str(&format!(
                 r#"
-    signal input a_s{}_vals[{}][{}];
-    signal input a_s{}_siblings[{}][{}][4];
-
-    signal input b_s{}_vals[{}][{}];
-    signal input b_s{}_siblings[{}][{}][4];
-            "#,
-                s,
-                stark_struct.nQueries,
-                (1 << (stark_struct.steps[s - 1].nBits - stark_struct.steps[s].nBits)) * 3,
-                s,
-                stark_struct.nQueries,
-                stark_struct.steps[s].nBits,
-                s,
-                stark_struct.nQueries,
+    signal input {p}s{s}_root[4];
+    signal input {p}s{s}_vals[{}][{}];
+    signal input {p}s{s}_siblings[{}][{}][4];
+"#,
+                n_queries,
                 (1 << (stark_struct.steps[s - 1].nBits - stark_struct.steps[s].nBits)) * 3,
-                s,
-                stark_struct.nQueries,
+                n_queries,
                 stark_struct.steps[s].nBits
             ));
         }
-
         res.push_str(&format!(
             r#"
-    signal input a_finalPol[{}][3];
-
-    signal input b_finalPol[{}][3];
-        "#,
-            1 << stark_struct.steps[stark_struct.steps.len() - 1].nBits,
-            1 << stark_struct.steps[stark_struct.steps.len() - 1].nBits,
+    signal input {p}finalPol[{}][3];
+"#,
+            1 << stark_struct.steps[stark_struct.steps.len() - 1].nBits
         ));
+    }
 
-        res.push_str(
+    for p in &prefixes {
+        let v = format!("v{}", p.trim_end_matches('_').to_uppercase());
+        res.push_str(&format!(
             r#"
-    component vA = StarkVerifier();
-
-    vA.publics <== a_publics;
-    vA.rootC <== a_rootC;
-
-    vA.root1 <== a_root1;
-    vA.root2 <== a_root2;
-    vA.root3 <== a_root3;
-    vA.root4 <== a_root4;
-    vA.evals <== a_evals;
-    vA.s0_vals1 <== a_s0_vals1;
-    vA.s0_vals3 <== a_s0_vals3;
-    vA.s0_vals4 <== a_s0_vals4;
-    vA.s0_valsC <== a_s0_valsC;
-    vA.s0_siblings1 <== a_s0_siblings1;
-    vA.s0_siblings3 <== a_s0_siblings3;
-    vA.s0_siblings4 <== a_s0_siblings4;
-    vA.s0_siblingsC <== a_s0_siblingsC;
-
-    vA.finalPol <== a_finalPol;
-            "#,
-        );
-
-        for s in 1..(stark_struct.steps.len()) {
+    component {v} = StarkVerifier();
+
+    {v}.publics <== {p}publics;
+    {v}.rootC <== {p}rootC;
+
+    {v}.root1 <== {p}root1;
+    {v}.root2 <== {p}root2;
+    {v}.root3 <== {p}root3;
+    {v}.root4 <== {p}root4;
+    {v}.evals <== {p}evals;
+    {v}.s0_vals1 <== {p}s0_vals1;
+    {v}.s0_vals4 <== {p}s0_vals4;
+    {v}.s0_valsC <== {p}s0_valsC;
+    {v}.s0_siblings1 <== {p}s0_siblings1;
+    {v}.s0_siblings4 <== {p}s0_siblings4;
+    {v}.s0_siblingsC <== {p}s0_siblingsC;
+
+    {v}.finalPol <== {p}finalPol;
+"#
+        ));
+        // the stage 2 and 3 trees are only declared by StarkVerifier if they have columns
+        if cm2 > 0 {
             res.push_str(&format!(
                 r#"
-    vA.s{}_root <== a_s{}_root;
-    vA.s{}_vals <== a_s{}_vals;
-    vA.s{}_siblings <== a_s{}_siblings;
-            "#,
-                s, s, s, s, s, s,
+    {v}.s0_vals2 <== {p}s0_vals2;
+    {v}.s0_siblings2 <== {p}s0_siblings2;
+"#
             ));
         }
-
-        res.push_str(
-            r#"
-    component vB = StarkVerifier();
-
-    vB.publics <== b_publics;
-    vB.rootC <== b_rootC;
-
-    vB.root1 <== b_root1;
-    vB.root2 <== b_root2;
-    vB.root3 <== b_root3;
-    vB.root4 <== b_root4;
-    vB.evals <== b_evals;
-    vB.s0_vals1 <== b_s0_vals1;
-    vB.s0_vals3 <== b_s0_vals3;
-    vB.s0_vals4 <== b_s0_vals4;
-    vB.s0_valsC <== b_s0_valsC;
-    vB.s0_siblings1 <== b_s0_siblings1;
-    vB.s0_siblings3 <== b_s0_siblings3;
-    vB.s0_siblings4 <== b_s0_siblings4;
-    vB.s0_siblingsC <== b_s0_siblingsC;
-
-    vB.finalPol <== b_finalPol;
-            "#,
-        );
-
-        for s in 1..(stark_struct.steps.len()) {
+        if cm3 > 0 {
             res.push_str(&format!(
                 r#"
-    vB.s{}_root <== b_s{}_root;
-    vB.s{}_vals <== b_s{}_vals;
-    vB.s{}_siblings <== b_s{}_siblings;
-            "#,
-                s, s, s, s, s, s,
+    {v}.s0_vals3 <== {p}s0_vals3;
+    {v}.s0_siblings3 <== {p}s0_siblings3;
+"#
             ));
         }
-
-        res.push_str(
-            r#"
-}
-            "#,
-        )
-    }
-
-    if !options.skip_main {
-        if options.agg_stage {
-            res.push_str(
-                r#"
-component main {public [a_publics, a_rootC, b_publics,b_rootC]}= Recursive2();"#,
-            );
-        } else if options.verkey_input {
-            res.push_str(
-                r#"
-component main {public [publics, rootC]}= StarkVerifier();
-    "#,
-            );
-        } else {
-            res.push_str(
+        for s in 1..stark_struct.steps.len() {
+            res.push_str(&format!(
                 r#"
-component main {public [publics]}= StarkVerifier();
-    "#,
-            );
+    {v}.s{s}_root <== {p}s{s}_root;
+    {v}.s{s}_vals <== {p}s{s}_vals;
+    {v}.s{s}_siblings <== {p}s{s}_siblings;
+"#
+            ));
         }
     }
+    res.push_str(
+        r#"
+}
+"#,
+    );
     res
 }
 
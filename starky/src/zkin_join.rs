@@ -1,8 +1,24 @@
-use crate::errors::Result;
+use crate::errors::{EigenError, Result};
+use anyhow::bail;
+use serde_json::Value;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Write;
 
+/// The key prefix of the `i`th joined proof, `a_`, `b_`, ..., `z_`, `aa_`, `ab_`, ..., like the
+/// inputs of the aggregation circuit of `pil2circom`
+pub fn zkin_prefix(i: usize) -> String {
+    let mut name = vec![];
+    let mut n = i + 1;
+    while n > 0 {
+        n -= 1;
+        name.push(b'a' + (n % 26) as u8);
+        n /= 26;
+    }
+    name.reverse();
+    format!("{}_", String::from_utf8_lossy(&name))
+}
+
 /// Combine the `input1.zkin.json` and `input1.zkin.json` into one(`out.zkin.json`)
 // ../../target/release/eigen-zkit join_zkin --zkin1 0/fibonacci.recursive1/input.zkin.json  --zkin2 1/fibonacci.recursive1/input.zkin.json  --zkinout 0/fibonacci.recursive1/r1_input-rs.zkin.json
 pub fn join_zkin(
@@ -11,27 +27,165 @@ pub fn join_zkin(
     zkin2: &String,
     zkout: &String,
 ) -> Result<()> {
-    // 1. load files.
-    let inputs_str = std::fs::read_to_string(zkin1)?;
-    let zkin1_map: BTreeMap<String, serde_json::Value> = serde_json::from_str(&inputs_str)?;
+    join_zkins(&[zkin1.clone(), zkin2.clone()], zkout)
+}
 
-    let inputs_str = std::fs::read_to_string(zkin2)?;
-    let zkin2_map: BTreeMap<String, serde_json::Value> = serde_json::from_str(&inputs_str)?;
+/// Combine the zkins into one, the keys of the `i`th one prefixed by `zkin_prefix(i)`, for the
+/// aggregation circuit of `zkins.len()` proofs.
+///
+/// The proofs must be verified by the same circuit: they have the same keys and the same
+/// shapes, so the same stark struct, and the same `rootC`, which the circuit takes as input.
+pub fn join_zkins(zkins: &[String], zkout: &str) -> Result<()> {
+    let invalid = |path: &str, msg: String| EigenError::InvalidInput {
+        path: path.to_string(),
+        msg,
+    };
+    if zkins.len() < 2 {
+        bail!(invalid(
+            zkout,
+            format!("join {} zkins, expect at least 2", zkins.len())
+        ));
+    }
 
-    // 2. construct zkout
-    let mut zkout_map = BTreeMap::new();
+    // 1. load files.
+    let mut zkin_maps = Vec::with_capacity(zkins.len());
+    for zkin in zkins {
+        let inputs_str = std::fs::read_to_string(zkin)?;
+        let zkin_map: BTreeMap<String, Value> = serde_json::from_str(&inputs_str)?;
+        zkin_maps.push(zkin_map);
+    }
 
-    for (k, v) in zkin1_map {
-        zkout_map.insert(format!("a_{k}"), v);
+    // 2. check the proofs against the first one
+    let first = &zkin_maps[0];
+    for (zkin, zkin_map) in zkins.iter().zip(&zkin_maps) {
+        match zkin_map.get("rootC") {
+            None => bail!(invalid(
+                zkin,
+                "no rootC, the proof must be generated with norm_stage".to_string()
+            )),
+            Some(root_c) if Some(root_c) != first.get("rootC") => bail!(invalid(
+                zkin,
+                format!(
+                    "rootC {} is not the rootC {} of {}",
+                    root_c, first["rootC"], zkins[0]
+                )
+            )),
+            _ => {}
+        }
+        if zkin_map.len() != first.len() {
+            bail!(invalid(
+                zkin,
+                format!("{} keys, {} has {}", zkin_map.len(), zkins[0], first.len())
+            ));
+        }
+        for (k, v) in zkin_map {
+            match first.get(k) {
+                None => bail!(invalid(zkin, format!("{} is not in {}", k, zkins[0]))),
+                Some(expected) if shape(v) != shape(expected) => bail!(invalid(
+                    zkin,
+                    format!(
+                        "the shape {:?} of {} is not {:?} of {}, the stark structs differ",
+                        shape(v),
+                        k,
+                        shape(expected),
+                        zkins[0]
+                    )
+                )),
+                _ => {}
+            }
+        }
     }
-    for (k, v) in zkin2_map {
-        zkout_map.insert(format!("b_{k}"), v);
+
+    // 3. construct zkout
+    let mut zkout_map = BTreeMap::new();
+    for (i, zkin_map) in zkin_maps.into_iter().enumerate() {
+        let prefix = zkin_prefix(i);
+        for (k, v) in zkin_map {
+            zkout_map.insert(format!("{prefix}{k}"), v);
+        }
     }
 
-    // 3. save zkout to file
+    // 4. save zkout to file
     let input = serde_json::to_string(&zkout_map)?;
     let mut file = File::create(zkout)?;
     write!(file, "{}", input)?;
     log::trace!("zkout file Generated Correctly");
     Ok(())
 }
+
+/// the lengths of the nested arrays, along the first elements
+fn shape(v: &Value) -> Vec<usize> {
+    let mut dims = vec![];
+    let mut v = v;
+    while let Value::Array(elements) = v {
+        dims.push(elements.len());
+        match elements.first() {
+            Some(first) => v = first,
+            None => break,
+        }
+    }
+    dims
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn zkin(path: &str, root_c: u64, n_queries: usize) -> String {
+        let zkin = json!({
+            "publics": ["1", "2"],
+            "root1": ["1", "2", "3", "4"],
+            "rootC": [root_c.to_string(), "0", "0", "0"],
+            "s0_vals1": vec![vec!["0"; 3]; n_queries],
+            "finalPol": vec![vec!["0"; 3]; 4],
+        });
+        std::fs::write(path, zkin.to_string()).unwrap();
+        path.to_string()
+    }
+
+    #[test]
+    fn zkin_prefixes() {
+        assert_eq!(zkin_prefix(0), "a_");
+        assert_eq!(zkin_prefix(1), "b_");
+        assert_eq!(zkin_prefix(25), "z_");
+        assert_eq!(zkin_prefix(26), "aa_");
+        assert_eq!(zkin_prefix(27), "ab_");
+        assert_eq!(zkin_prefix(26 * 27), "aaa_");
+    }
+
+    #[test]
+    fn join_n_zkins() {
+        let zkins = (0..3)
+            .map(|i| zkin(&format!("/tmp/join_{}.zkin.json", i), 7, 2))
+            .collect::<Vec<_>>();
+        let out = "/tmp/join_out.zkin.json";
+        join_zkins(&zkins, out).unwrap();
+        let joined: BTreeMap<String, Value> =
+            serde_json::from_str(&std::fs::read_to_string(out).unwrap()).unwrap();
+        assert_eq!(joined.len(), 15);
+        assert!(joined.contains_key("a_rootC"));
+        assert!(joined.contains_key("c_finalPol"));
+
+        join_zkin(&zkins[0], &zkins[1], &out.to_string()).unwrap();
+        let joined: BTreeMap<String, Value> =
+            serde_json::from_str(&std::fs::read_to_string(out).unwrap()).unwrap();
+        assert_eq!(joined.len(), 10);
+        assert!(joined.contains_key("b_s0_vals1"));
+
+        assert!(join_zkins(&zkins[..1], out).is_err());
+        // another stark struct
+        let other = zkin("/tmp/join_queries.zkin.json", 7, 3);
+        assert!(join_zkins(&[zkins[0].clone(), other], out).is_err());
+        // another circuit
+        let other = zkin("/tmp/join_root_c.zkin.json", 8, 2);
+        assert!(join_zkins(&[zkins[0].clone(), other], out).is_err());
+        // not norm_stage
+        let mut zkin_map: BTreeMap<String, Value> =
+            serde_json::from_str(&std::fs::read_to_string(&zkins[1]).unwrap()).unwrap();
+        zkin_map.remove("rootC");
+        let other = "/tmp/join_no_root_c.zkin.json";
+        std::fs::write(other, serde_json::to_string(&zkin_map).unwrap()).unwrap();
+        assert!(join_zkins(&[zkins[0].clone(), other.to_string()], out).is_err());
+    }
+}
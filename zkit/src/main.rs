@@ -1,14 +1,20 @@
 extern crate clap;
+use algebraic::witness::NativeWitnessCalculator;
 use clap::{command, Parser};
-use dsl_compile::circom_compiler;
+use dsl_compile::{compile_with_diagnostics, CompileOptions, SimplificationStyle};
 use groth16::api::*;
 use plonky::api::{
     aggregation_check, aggregation_prove, aggregation_verify, analyse, calculate_witness,
     export_aggregation_verification_key, export_verification_key, generate_aggregation_verifier,
     generate_verifier, prove as plonky_prove, setup, verify,
 };
+use starky::layered::LayeredPipeline;
 use starky::prove::stark_prove;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use zkvm::{
+    zkvm_evm_prove_dir, BackendType, ChannelData, CircomToolchain, Coprocessors, CostModel,
+    GuestProgram, ZkvmProof,
+};
 
 /// Trust setup for Plonk
 #[derive(Parser, Debug)]
@@ -17,6 +23,9 @@ pub struct SetupOpt {
     power: u32,
     #[arg(short, required = true)]
     srs_monomial_form: String,
+    /// INSECURE: seed the randomness to reproduce the SRS, only for testing
+    #[arg(long)]
+    seed: Option<u64>,
 }
 
 #[derive(Debug, Parser)]
@@ -47,6 +56,27 @@ pub struct CompilierOpt {
     /// setup the library path
     #[arg(short)]
     link_directories: Vec<String>,
+
+    /// write the compiler reports into this file as json, instead of printing them
+    #[arg(long = "diagnostics-json")]
+    diagnostics_json: Option<String>,
+
+    /// Reuse the outputs if the circuit, its includes and the options are unchanged. The
+    /// outputs are hashed into `<circuit>.cache.json`, editing them compiles again.
+    #[arg(long = "cache")]
+    cache: bool,
+
+    /// simplify the constraints in parallel
+    #[arg(long = "parallel")]
+    parallel: bool,
+
+    /// the number of threads of the parallel simplification, all the CPUs by default
+    #[arg(long = "workers", requires = "parallel")]
+    workers: Option<usize>,
+
+    /// also generate the C++ witness generator, into <output>/<circuit>_cpp
+    #[arg(long = "c")]
+    c: bool,
 }
 
 /// Calculate witness and save to output file
@@ -54,8 +84,11 @@ pub struct CompilierOpt {
 
 struct CalculateWitnessOpt {
     /// wasm circuit
-    #[arg(short, required = true)]
-    wasm_file: String,
+    #[arg(short, required_unless_present = "cpp_folder")]
+    wasm_file: Option<String>,
+    /// calculate by the C++ witness generator in this <circuit>_cpp folder instead of the wasm
+    #[arg(long = "cpp", conflicts_with = "wasm_file")]
+    cpp_folder: Option<String>,
     /// [input] input json
     #[arg(short, required = true)]
     input_json: String,
@@ -202,6 +235,9 @@ struct StarkProveOpt {
     norm_stage: bool,
     #[arg(short, long = "agg_stage", action= clap::ArgAction::SetTrue)]
     agg_stage: bool,
+    /// the proofs verified by the aggregation circuit of agg_stage
+    #[arg(long = "agg_arity", default_value = "2")]
+    agg_arity: usize,
     #[arg(long = "o", default_value = "pols.const")]
     const_pols: String,
     #[arg(long = "m", default_value = "pols.cm")]
@@ -262,7 +298,17 @@ struct Compressor12ExecOpt {
     commit_file: String,
 }
 
-/// generate the input1.zkin.json and input2.zkin.json into out.zkin.json
+/// Compile a .pil file into its json form, like the js pilcom
+#[derive(Parser, Debug)]
+struct PilCompileOpt {
+    #[arg(short, required = true)]
+    input: String,
+    #[arg(short, required = true)]
+    output: String,
+}
+
+/// generate the input1.zkin.json and input2.zkin.json into out.zkin.json, or the N zkins of
+/// `--zkin` in order
 #[derive(Parser, Debug)]
 struct JoinZkinExecOpt {
     // #[arg(long = "starksetup", default_value = "starksetup.json")]
@@ -271,6 +317,9 @@ struct JoinZkinExecOpt {
     zkin1: String,
     #[arg(long = "zkin2", default_value = "input2.zkin.json")]
     zkin2: String,
+    /// join these zkins instead of zkin1 and zkin2
+    #[arg(long = "zkin")]
+    zkins: Vec<String>,
     #[arg(long = "zkinout", default_value = "out.zkin.json")]
     zkinout: String,
 }
@@ -288,6 +337,9 @@ pub struct Groth16SetupOpt {
     vk_file: String,
     #[arg(short, action= clap::ArgAction::SetTrue)]
     to_hex: bool,
+    /// INSECURE: seed the randomness to reproduce the keys, only for testing
+    #[arg(long)]
+    seed: Option<u64>,
 }
 
 /// Prove with groth16
@@ -297,12 +349,14 @@ pub struct Groth16ProveOpt {
     curve_type: String,
     #[arg(long = "r1cs", required = true)]
     circuit_file: String,
+    /// wasm circuit, or a precomputed witness file (.wtns or .json)
     #[arg(short, required = true)]
-    wasm_file: String,
+    wtns_file: String,
     #[arg(short, required = true, default_value = "g16.zkey")]
     pk_file: String,
-    #[arg(short, required = true)]
-    input_file: String,
+    /// [input] input json, required when calculating the witness by the wasm circuit
+    #[arg(short)]
+    input_file: Option<String>,
     #[arg(
         long = "public-input",
         required = true,
@@ -313,6 +367,9 @@ pub struct Groth16ProveOpt {
     proof_file: String,
     #[arg(short, action= clap::ArgAction::SetTrue)]
     to_hex: bool,
+    /// INSECURE: seed the randomness to reproduce the proof, only for testing
+    #[arg(long)]
+    seed: Option<u64>,
 }
 
 /// Verify with groth16
@@ -332,6 +389,145 @@ pub struct Groth16VerifyOpt {
     proof_file: String,
 }
 
+/// Check a witness satisfies the r1cs, and report the unsatisfied constraints
+#[derive(Parser, Debug)]
+pub struct CheckWitnessOpt {
+    #[arg(short, required = true, default_value = "BN128")]
+    curve_type: String,
+    #[arg(long = "r1cs", required = true)]
+    circuit_file: String,
+    /// witness file (.wtns or .json)
+    #[arg(long = "wtns", required = true)]
+    wtns_file: String,
+    /// [optional] symbol file, to name the wires of the unsatisfied constraints
+    #[arg(long = "sym")]
+    sym_file: Option<String>,
+    /// [optional] the wire mapping written by r1cs_optimize, if the r1cs is optimized by it
+    #[arg(long = "wire_mapping")]
+    wire_mapping: Option<String>,
+}
+
+/// Optimize the r1cs by eliminating the linear constraints, duplicates and unused wires
+#[derive(Parser, Debug)]
+pub struct R1csOptimizeOpt {
+    #[arg(short, required = true, default_value = "BN128")]
+    curve_type: String,
+    /// input r1cs file
+    #[arg(short, required = true)]
+    input: String,
+    /// optimized r1cs file
+    #[arg(short, required = true)]
+    output: String,
+    /// json of the wires of the input r1cs for the optimized one, to map the witness
+    #[arg(short, required = true, default_value = "wire_mapping.json")]
+    wire_mapping: String,
+}
+
+/// The guest program of the zkVM commands
+#[derive(clap::Args, Debug)]
+pub struct GuestOpt {
+    /// the guest crate
+    #[arg(short, required = true)]
+    guest: String,
+    /// input channel `<channel>:<kind>:<value>`, the channel is a number or a name, the kinds
+    /// are str, file, u32, field, json and jsonfile
+    #[arg(short, long = "input")]
+    inputs: Vec<String>,
+    /// output directory of the compiled guest and its proofs
+    #[arg(short, required = true)]
+    output: String,
+    /// link the poseidon coprocessor
+    #[arg(long)]
+    poseidon: bool,
+    /// the backend of powdr, like estark
+    #[arg(long, default_value = "estark")]
+    backend: String,
+}
+
+impl GuestOpt {
+    fn program(&self) -> anyhow::Result<GuestProgram> {
+        let backend = self
+            .backend
+            .parse::<BackendType>()
+            .map_err(|_| anyhow::anyhow!("unknown backend {}", self.backend))?;
+        let mut guest = GuestProgram::new(&self.guest, &self.output).backend(backend);
+        for input in &self.inputs {
+            let (channel, data) = ChannelData::parse_arg(input)?;
+            guest = guest.input(channel, data);
+        }
+        if self.poseidon {
+            guest = guest.coprocessors(Coprocessors::default().with_poseidon());
+        }
+        Ok(guest)
+    }
+}
+
+/// Execute a zkVM guest without proving, and estimate the proving cost
+#[derive(Parser, Debug)]
+pub struct ZkvmExecuteOpt {
+    #[command(flatten)]
+    guest: GuestOpt,
+    /// proving seconds per continuation chunk, measured on this machine
+    #[arg(long)]
+    chunk_secs: Option<u64>,
+    /// peak proving memory of a chunk in bytes, measured on this machine, 16 GiB by default
+    #[arg(long)]
+    chunk_memory: Option<u64>,
+}
+
+/// Prove a zkVM guest, one proof per continuation chunk
+#[derive(Parser, Debug)]
+pub struct ZkvmProveOpt {
+    #[command(flatten)]
+    guest: GuestOpt,
+    /// json of the chunk proofs
+    #[arg(long, default_value = "zkvm_proof.json")]
+    proof: String,
+}
+
+/// Verify the chunk proofs of a zkVM guest
+#[derive(Parser, Debug)]
+pub struct ZkvmVerifyOpt {
+    #[command(flatten)]
+    guest: GuestOpt,
+    /// json of the chunk proofs, written by zkvm_prove
+    #[arg(long, default_value = "zkvm_proof.json")]
+    proof: String,
+    /// verify the eStark proofs by starky
+    #[arg(long)]
+    starky: bool,
+}
+
+/// Execute or prove a directory of ethereum state tests by the EVM guest
+#[derive(Parser, Debug)]
+pub struct ZkvmEvmSuiteOpt {
+    /// directory of the state test files
+    #[arg(short, required = true)]
+    dir: String,
+    /// output directory of the tests
+    #[arg(short, required = true)]
+    output: String,
+    #[arg(long, default_value = "1")]
+    chain_id: u32,
+    /// prove the tests, instead of executing them
+    #[arg(long)]
+    prove: bool,
+    /// json report of the tests
+    #[arg(short, required = true, default_value = "evm_suite_report.json")]
+    report: String,
+}
+
+/// Run the layered proof pipeline described by a json, stark -> c12 -> recursion -> snark
+#[derive(Parser, Debug)]
+pub struct LayeredProveOpt {
+    /// json of the pipeline, see `starky::layered::LayeredPipeline`
+    #[arg(short, required = true)]
+    pipeline: String,
+    /// json of the paths of the last proof and the snark
+    #[arg(long, default_value = "layered_proof.json")]
+    proof: String,
+}
+
 #[derive(Parser, Debug)]
 enum Command {
     #[command(name = "setup")]
@@ -372,6 +568,10 @@ enum Command {
     Compressor12Exec(Compressor12ExecOpt),
     #[command(name = "join_zkin")]
     JoinZkin(JoinZkinExecOpt),
+    #[command(name = "layered_prove")]
+    LayeredProve(LayeredProveOpt),
+    #[command(name = "pil_compile")]
+    PilCompile(PilCompileOpt),
 
     #[command(name = "groth16_setup")]
     Groth16Setup(Groth16SetupOpt),
@@ -379,6 +579,20 @@ enum Command {
     Groth16Prove(Groth16ProveOpt),
     #[command(name = "groth16_verify")]
     Groth16Verify(Groth16VerifyOpt),
+
+    #[command(name = "check_witness")]
+    CheckWitness(CheckWitnessOpt),
+    #[command(name = "r1cs_optimize")]
+    R1csOptimize(R1csOptimizeOpt),
+
+    #[command(name = "zkvm_execute")]
+    ZkvmExecute(ZkvmExecuteOpt),
+    #[command(name = "zkvm_prove")]
+    ZkvmProve(ZkvmProveOpt),
+    #[command(name = "zkvm_verify")]
+    ZkvmVerify(ZkvmVerifyOpt),
+    #[command(name = "zkvm_evm_suite")]
+    ZkvmEvmSuite(ZkvmEvmSuiteOpt),
 }
 
 #[derive(Parser, Debug)]
@@ -388,24 +602,100 @@ struct Cli {
     command: Command,
 }
 
+fn compile(args: CompilierOpt) -> anyhow::Result<()> {
+    let o_style = SimplificationStyle::from_flags(
+        args.no_simplification,
+        args.reduced_simplification,
+        &args.full_simplification,
+    )?;
+    let opts = CompileOptions::new(&args.input)
+        .prime(&args.prime)
+        .simplification(o_style)
+        .link_directories(&args.link_directories)
+        .cache(args.cache)
+        .parallel_simplification(args.parallel)
+        .c(args.c);
+    let opts = match args.workers {
+        Some(workers) => opts.workers(workers),
+        None => opts,
+    };
+    match args.diagnostics_json {
+        None => dsl_compile::compile(&opts, &args.output),
+        Some(diagnostics_json) => {
+            let mut diagnostics = vec![];
+            let result = compile_with_diagnostics(&opts, &args.output, &mut diagnostics);
+            std::fs::write(
+                diagnostics_json,
+                serde_json::to_string_pretty(&diagnostics)?,
+            )?;
+            result
+        }
+    }
+}
+
+fn zkvm_execute(args: ZkvmExecuteOpt) -> anyhow::Result<()> {
+    let mut cost_model = CostModel::default();
+    if let Some(secs) = args.chunk_secs {
+        cost_model.time_per_chunk = Duration::from_secs(secs);
+    }
+    if let Some(memory) = args.chunk_memory {
+        cost_model.memory_per_chunk = memory;
+    }
+    let report = args.guest.program()?.execute(&cost_model)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+fn zkvm_prove(args: ZkvmProveOpt) -> anyhow::Result<()> {
+    let proof = args.guest.program()?.prove()?;
+    proof.save(&args.proof)?;
+    println!("{}", String::from_utf8_lossy(&proof.stdout));
+    println!("{}", serde_json::to_string_pretty(&proof.hints)?);
+    for (chunk, publics) in proof.publics()?.iter().enumerate() {
+        println!("chunk {} publics: {:?}", chunk, publics);
+    }
+    println!("{:?}", proof.stats);
+    Ok(())
+}
+
+fn zkvm_verify(args: ZkvmVerifyOpt) -> anyhow::Result<()> {
+    let proof = ZkvmProof::load(&args.proof)?;
+    if args.starky {
+        proof.verify_by_starky()?;
+    } else {
+        args.guest.program()?.verify(&proof)?;
+    }
+    println!("verify {} chunk proofs: OK", proof.chunks.len());
+    Ok(())
+}
+
+fn zkvm_evm_suite(args: ZkvmEvmSuiteOpt) -> anyhow::Result<()> {
+    let report = zkvm_evm_prove_dir(&args.dir, args.chain_id, &args.output, args.prove)?;
+    std::fs::write(&args.report, serde_json::to_string_pretty(&report)?)?;
+    println!("passed: {}, failed: {}", report.passed, report.failed);
+    Ok(())
+}
+
+fn layered_prove(args: LayeredProveOpt) -> anyhow::Result<()> {
+    let proof = LayeredPipeline::load(&args.pipeline)?.prove(&CircomToolchain)?;
+    std::fs::write(&args.proof, serde_json::to_string_pretty(&proof)?)?;
+    println!("{}", proof.zkin.display());
+    Ok(())
+}
+
 fn main() {
     let args = Cli::parse();
     env_logger::init();
     let start = Instant::now();
     let exec_result = match args.command {
-        Command::Setup(args) => setup(args.power, &args.srs_monomial_form),
-        Command::Compile(args) => circom_compiler(
-            args.input,
-            args.prime.to_lowercase(),
-            args.full_simplification,
-            args.link_directories,
-            args.output,
-            args.no_simplification,
-            args.reduced_simplification,
-        ),
-        Command::CalculateWitness(args) => {
-            calculate_witness(&args.wasm_file, &args.input_json, &args.output)
-        }
+        Command::Setup(args) => setup(args.power, &args.srs_monomial_form, args.seed),
+        Command::Compile(args) => compile(args),
+        Command::CalculateWitness(args) => match (&args.wasm_file, &args.cpp_folder) {
+            (_, Some(cpp_folder)) => NativeWitnessCalculator::build(cpp_folder)
+                .and_then(|native| native.calculate_witness_file(&args.input_json, &args.output)),
+            (Some(wasm_file), None) => calculate_witness(wasm_file, &args.input_json, &args.output),
+            (None, None) => unreachable!("required by clap"),
+        },
         Command::PlonkProve(args) => plonky_prove(
             &args.circuit_file,
             &args.witness,
@@ -447,6 +737,7 @@ fn main() {
             &args.piljson,
             args.norm_stage,
             args.agg_stage,
+            args.agg_arity,
             &args.const_pols,
             &args.cm_pols,
             &args.circom_file,
@@ -468,25 +759,30 @@ fn main() {
             &args.exec_file,
             &args.commit_file,
         ),
-        Command::JoinZkin(args) => {
+        Command::JoinZkin(args) if args.zkins.is_empty() => {
             starky::zkin_join::join_zkin(&args.zkin1, &args.zkin2, &args.zkinout)
         }
+        Command::JoinZkin(args) => starky::zkin_join::join_zkins(&args.zkins, &args.zkinout),
+        Command::LayeredProve(args) => layered_prove(args),
+        Command::PilCompile(args) => starky::pilcom::compile_pil(&args.input, &args.output),
         Command::Groth16Setup(args) => groth16_setup(
             &args.curve_type,
             &args.circuit_file,
             &args.pk_file,
             &args.vk_file,
             args.to_hex,
+            args.seed,
         ),
         Command::Groth16Prove(args) => groth16_prove(
             &args.curve_type,
             &args.circuit_file,
-            &args.wasm_file,
+            &args.wtns_file,
             &args.pk_file,
-            &args.input_file,
+            args.input_file.as_deref(),
             &args.public_input_file,
             &args.proof_file,
             args.to_hex,
+            args.seed,
         ),
         Command::Groth16Verify(args) => groth16_verify(
             &args.curve_type,
@@ -494,6 +790,23 @@ fn main() {
             &args.public_input_file,
             &args.proof_file,
         ),
+        Command::CheckWitness(args) => algebraic::r1cs_check::check_witness(
+            &args.curve_type,
+            &args.circuit_file,
+            &args.wtns_file,
+            args.sym_file.as_deref(),
+            args.wire_mapping.as_deref(),
+        ),
+        Command::R1csOptimize(args) => algebraic::r1cs_optimize::optimize_r1cs_file(
+            &args.curve_type,
+            &args.input,
+            &args.output,
+            &args.wire_mapping,
+        ),
+        Command::ZkvmExecute(args) => zkvm_execute(args),
+        Command::ZkvmProve(args) => zkvm_prove(args),
+        Command::ZkvmVerify(args) => zkvm_verify(args),
+        Command::ZkvmEvmSuite(args) => zkvm_evm_suite(args),
     };
     match exec_result {
         Err(x) => {
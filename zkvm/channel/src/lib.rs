@@ -0,0 +1,213 @@
+//! The typed channels between the host and the guest of the zkVM.
+//!
+//! An input is a value serialized into a named channel by the host, and deserialized by the
+//! guest with [`guest::read`]. The serialized bytes are packed 4 by 4 into words, one word per
+//! field element, after the byte length.
+//!
+//! A hint is a value written by the guest with [`guest::hint`], it goes through the print
+//! channel as a line `\x1e<name>:<hex>`, and is split from the printed text by [`split_hints`]
+//! on the host. The hints are not bound to the proof, the host must not trust them.
+#![no_std]
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// the first byte of a hint line
+pub const HINT_MARKER: u8 = 0x1e;
+
+#[derive(Debug)]
+pub enum Error {
+    Serde(serde_json::Error),
+    /// the words are shorter than their byte length
+    Truncated,
+    /// the name of a hint is empty, or has a `:` or a newline
+    InvalidHintName,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serde(e) => write!(f, "serde error, {}", e),
+            Error::Truncated => write!(f, "truncated channel data"),
+            Error::InvalidHintName => write!(f, "invalid hint name"),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serde(e)
+    }
+}
+
+/// The channel id of `name`, the high bit is set so it doesn't clash with the numeric channels,
+/// like 0 for the prover input.
+pub fn channel_id(name: &str) -> u32 {
+    // FNV-1a
+    let hash = name.bytes().fold(0x811c_9dc5u32, |h, b| {
+        (h ^ b as u32).wrapping_mul(0x0100_0193)
+    });
+    hash | 1 << 31
+}
+
+/// serialize `value` into the words of a channel, the byte length first
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u32>, Error> {
+    let bytes = serde_json::to_vec(value)?;
+    let mut words = Vec::with_capacity(1 + (bytes.len() + 3) / 4);
+    words.push(bytes.len() as u32);
+    words.extend(bytes.chunks(4).map(|c| {
+        let mut word = [0u8; 4];
+        word[..c.len()].copy_from_slice(c);
+        u32::from_le_bytes(word)
+    }));
+    Ok(words)
+}
+
+pub fn decode<T: DeserializeOwned>(words: &[u32]) -> Result<T, Error> {
+    let (len, words) = words.split_first().ok_or(Error::Truncated)?;
+    let len = *len as usize;
+    if words.len() * 4 < len {
+        return Err(Error::Truncated);
+    }
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take(len)
+        .collect::<Vec<_>>();
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// the hint line of `value`, without the newline
+pub fn encode_hint<T: Serialize + ?Sized>(name: &str, value: &T) -> Result<String, Error> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    if name.is_empty() || name.contains([':', '\n']) {
+        return Err(Error::InvalidHintName);
+    }
+    let bytes = serde_json::to_vec(value)?;
+    let mut line = String::with_capacity(name.len() + 2 + bytes.len() * 2);
+    line.push(HINT_MARKER as char);
+    line.push_str(name);
+    line.push(':');
+    for b in bytes {
+        line.push(HEX[(b >> 4) as usize] as char);
+        line.push(HEX[(b & 0xf) as usize] as char);
+    }
+    Ok(line)
+}
+
+/// Split what the guest printed into the text, and the hints by name with their serialized
+/// bytes, in the order they were written. A line which starts with the marker but is not a hint
+/// is kept in the text, the guest may print anything.
+pub fn split_hints(stdout: &[u8]) -> (Vec<u8>, Vec<(String, Vec<u8>)>) {
+    let mut text = Vec::with_capacity(stdout.len());
+    let mut hints = Vec::new();
+    for line in stdout.split_inclusive(|c| *c == b'\n') {
+        match line.strip_prefix(&[HINT_MARKER]).and_then(|hint| {
+            let hint = hint.strip_suffix(b"\n").unwrap_or(hint);
+            parse_hint(hint)
+        }) {
+            Some(hint) => hints.push(hint),
+            None => text.extend_from_slice(line),
+        }
+    }
+    (text, hints)
+}
+
+/// the name and the bytes of a hint line `<name>:<hex>`, without its marker
+fn parse_hint(line: &[u8]) -> Option<(String, Vec<u8>)> {
+    let colon = line.iter().position(|c| *c == b':')?;
+    let name = core::str::from_utf8(&line[..colon]).ok()?;
+    let hex = &line[colon + 1..];
+    if name.is_empty() || hex.len() % 2 != 0 {
+        return None;
+    }
+    let nibble = |c: u8| match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    };
+    let bytes = hex
+        .chunks(2)
+        .map(|p| Some(nibble(p[0])? << 4 | nibble(p[1])?))
+        .collect::<Option<Vec<_>>>()?;
+    Some((String::from(name), bytes))
+}
+
+#[cfg(feature = "guest")]
+pub mod guest {
+    use super::*;
+    use alloc::vec;
+    use powdr_riscv_rt::coprocessors::{get_data, get_data_len};
+    use powdr_riscv_rt::print;
+
+    /// read the input channel `name`, it panics if the host didn't write it as `T`
+    pub fn read<T: DeserializeOwned>(name: &str) -> T {
+        let channel = channel_id(name);
+        let mut words = vec![0; get_data_len(channel)];
+        get_data(channel, &mut words);
+        match decode(&words) {
+            Ok(value) => value,
+            Err(e) => panic!("invalid input {}: {}", name, e),
+        }
+    }
+
+    /// write the hint `name` to the host, it is not proven
+    pub fn hint<T: Serialize + ?Sized>(name: &str, value: &T) {
+        match encode_hint(name, value) {
+            Ok(line) => print!("{}\n", line),
+            Err(e) => panic!("invalid hint {}: {}", name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn encode_and_decode() {
+        let words = encode("hello").unwrap();
+        // "\"hello\"" is 7 bytes
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[0], 7);
+        assert_eq!(decode::<String>(&words).unwrap(), "hello");
+        assert_eq!(decode::<u64>(&encode(&42u64).unwrap()).unwrap(), 42);
+        assert!(decode::<String>(&words[..2]).is_err());
+        assert!(decode::<u64>(&words).is_err());
+
+        assert_ne!(channel_id("suite"), channel_id("caller"));
+        assert!(channel_id("") >= 1 << 31);
+    }
+
+    #[test]
+    fn split_hints_from_text() {
+        let mut stdout = b"chain_id: 1\n".to_vec();
+        stdout.extend(encode_hint("gas", &21000u64).unwrap().bytes());
+        stdout.extend(b"\nno newline");
+        let (text, hints) = split_hints(&stdout);
+        assert_eq!(text, b"chain_id: 1\nno newline");
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].0, "gas");
+        assert_eq!(serde_json::from_slice::<u64>(&hints[0].1).unwrap(), 21000);
+
+        // the text printed by the guest is kept, even if it starts with the marker
+        for line in [
+            vec![HINT_MARKER, b'x', b'\n'],
+            vec![HINT_MARKER, b':', b'0', b'0'],
+            vec![HINT_MARKER, b'a', b':', b'z', b'z'],
+        ] {
+            let (text, hints) = split_hints(&line);
+            assert_eq!(text, line);
+            assert!(hints.is_empty());
+        }
+
+        assert!(encode_hint("a:b", &1).is_err());
+        assert!(encode_hint("a\nb", &1).is_err());
+        assert!(encode_hint("", &1).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::errors::{Result, ZkvmError};
+use crate::{ChunkProof, ZkvmProof};
+use dsl_compile::{CompileOptions, SimplificationStyle};
+use powdr::number::GoldilocksField;
+use powdr::riscv::continuations::bootloader::MEMORY_HASH_START_INDEX;
+use starky::layered::{Layer, LayeredPipeline, SnarkStage, Toolchain};
+use starky::pil2circom::StarkOption;
+use std::path::{Path, PathBuf};
+
+pub use starky::layered::{LayeredProof as AggregatedProof, SnarkProof};
+
+/// the words of a memory root in the bootloader inputs, the initial root is followed by the
+/// final one
+const MEMORY_ROOT_WORDS: usize = 4;
+
+const STARKY_DATA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../starky/data");
+
+/// The stark structs of the aggregation stages, their `nBits` must fit the degree of the PIL
+/// proven by them.
+#[derive(Clone, Debug)]
+pub struct StarkStructs {
+    /// the compressor12 PIL of the chunk verifier
+    pub c12: PathBuf,
+    /// the compressor12 PIL of the aggregation circuits
+    pub recursive: PathBuf,
+    /// the final proof, verified by the snark
+    pub final_: PathBuf,
+}
+
+impl Default for StarkStructs {
+    fn default() -> Self {
+        let data = Path::new(STARKY_DATA);
+        Self {
+            c12: data.join("c12.starkStruct.json"),
+            recursive: data.join("r2.starkStruct.json"),
+            final_: data.join("final.starkStruct.bn128.json"),
+        }
+    }
+}
+
+/// Aggregate the chunk proofs recursively into one stark proof, like `test/stark_aggregation.sh`,
+/// by the layered pipeline of starky:
+///
+/// 1. every eStark chunk proof of powdr is verified by a compressor12 circuit from `pil2circom`,
+///    all the chunks share it in `<workspace>/layer_0`;
+/// 2. the proofs are joined by groups of `arity`, and proven by an aggregation circuit, layer
+///    by layer until one is left;
+/// 3. the last proof is optionally proven again with a BN128 stark, and by groth16.
+///
+/// The aggregated proof proves that every chunk proof is valid, it is not a proof of a run of
+/// the guest. The bootloader of powdr declares no public values, so the memory roots of a chunk
+/// are not bound to its proof, and the aggregation circuits can't constrain the final root of a
+/// chunk to be the initial root of the next one. `check_continuity` runs on the saved bootloader
+/// inputs before, as a sanity check of the proof file only.
+///
+/// The circuits are compiled with the circom libraries of pil-stark, like
+/// `Aggregator::new("/tmp/agg").link_directory("starkjs/node_modules/pil-stark/circuits.gl")`.
+#[derive(Clone, Debug)]
+pub struct Aggregator {
+    workspace: PathBuf,
+    link_directories: Vec<PathBuf>,
+    stark_structs: StarkStructs,
+    prover_addr: String,
+    arity: usize,
+    snark: Option<SnarkStage>,
+}
+
+impl Aggregator {
+    pub fn new<P: AsRef<Path>>(workspace: P) -> Self {
+        Self {
+            workspace: workspace.as_ref().to_path_buf(),
+            link_directories: vec![],
+            stark_structs: StarkStructs::default(),
+            prover_addr: "273030697313060285579891744179749754319274977764".to_string(),
+            arity: 2,
+            snark: None,
+        }
+    }
+
+    /// add a circom library of the goldilocks circuits
+    pub fn link_directory<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.link_directories.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn stark_structs(mut self, stark_structs: StarkStructs) -> Self {
+        self.stark_structs = stark_structs;
+        self
+    }
+
+    pub fn prover_addr(mut self, prover_addr: &str) -> Self {
+        self.prover_addr = prover_addr.to_string();
+        self
+    }
+
+    /// the proofs verified by an aggregation circuit, 2 by default
+    pub fn arity(mut self, arity: usize) -> Self {
+        self.arity = arity;
+        self
+    }
+
+    /// prove the final stark by groth16 on `curve`, with the circom libraries of its circuits
+    pub fn snark<P: AsRef<Path>>(mut self, curve: &str, link_directories: &[P]) -> Self {
+        self.snark = Some(SnarkStage {
+            curve: curve.to_string(),
+            link_directories: link_directories
+                .iter()
+                .map(|d| d.as_ref().to_path_buf())
+                .collect(),
+        });
+        self
+    }
+
+    /// The layered pipeline of `num_chunks` chunks: the c12 layer of the chunk verifier, a
+    /// joining layer until one proof is left, and the final layer of the snark.
+    pub fn pipeline(&self, num_chunks: usize) -> LayeredPipeline {
+        let layer = |stark_struct: &PathBuf, join: bool| Layer {
+            stark_struct: stark_struct.clone(),
+            join,
+            arity: self.arity,
+        };
+        let mut layers = vec![layer(&self.stark_structs.c12, false)];
+        let mut left = num_chunks;
+        while left > 1 {
+            layers.push(layer(&self.stark_structs.recursive, true));
+            left = left.div_ceil(self.arity);
+        }
+        if self.snark.is_some() {
+            layers.push(layer(&self.stark_structs.final_, false));
+        }
+        LayeredPipeline {
+            workspace: self.workspace.clone(),
+            inputs: vec![],
+            stark_struct: PathBuf::new(),
+            link_directories: self.link_directories.clone(),
+            layers,
+            snark: self.snark.clone(),
+            prover_addr: self.prover_addr.clone(),
+        }
+    }
+
+    pub fn aggregate(&self, proof: &ZkvmProof) -> Result<AggregatedProof> {
+        if proof.chunks.is_empty() {
+            return Err(ZkvmError::AggregationError("no chunk proof".to_string()));
+        }
+        if self.arity < 2 {
+            return Err(ZkvmError::AggregationError(format!(
+                "the arity {} is less than 2",
+                self.arity
+            )));
+        }
+        check_continuity(&proof.chunks)?;
+
+        let pipeline = self.pipeline(proof.chunks.len());
+        // the chunks have the same PIL and constants, so they are verified by the same circuit
+        let layer = pipeline.layer_dir(0)?;
+        let mut zkins = vec![];
+        for (i, chunk) in proof.chunks.iter().enumerate() {
+            log::debug!("Generating the c12 verifier of chunk {}...", chunk.index);
+            let (circom, zkin_json) = chunk.to_starky()?.verifier_circom(&StarkOption {
+                enable_input: false,
+                verkey_input: false,
+                skip_main: false,
+                agg_stage: false,
+                agg_arity: 0,
+            })?;
+            if i == 0 {
+                std::fs::write(layer.circom(), circom)?;
+            }
+            let zkin = layer.file(&format!("{}.zkin.json", chunk.index));
+            std::fs::write(&zkin, zkin_json.to_string())?;
+            zkins.push(zkin);
+        }
+        Ok(pipeline.prove_layers(&CircomToolchain, zkins)?)
+    }
+}
+
+/// The toolchain of the layered pipeline: the circuits are compiled by dsl_compile, and the
+/// snark is proven by groth16
+pub struct CircomToolchain;
+
+impl Toolchain for CircomToolchain {
+    fn compile(
+        &self,
+        circom: &Path,
+        prime: &str,
+        link_directories: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        let opts = CompileOptions::new(circom)
+            .prime(prime)
+            .simplification(SimplificationStyle::O2(usize::MAX))
+            .link_directories(link_directories)
+            .cache(true);
+        dsl_compile::compile(&opts, circom.parent().unwrap_or(Path::new(".")))
+    }
+
+    fn groth16_setup(&self, curve: &str, r1cs: &Path, pk: &Path, vk: &Path) -> anyhow::Result<()> {
+        groth16::api::groth16_setup(
+            curve,
+            &r1cs.to_string_lossy(),
+            &pk.to_string_lossy(),
+            &vk.to_string_lossy(),
+            false,
+            None,
+        )
+    }
+
+    fn groth16_prove(
+        &self,
+        curve: &str,
+        r1cs: &Path,
+        wasm: &Path,
+        pk: &Path,
+        vk: &Path,
+        zkin: &Path,
+        public_input: &Path,
+        proof: &Path,
+    ) -> anyhow::Result<()> {
+        let (public_input, proof) = (public_input.to_string_lossy(), proof.to_string_lossy());
+        groth16::api::groth16_prove(
+            curve,
+            &r1cs.to_string_lossy(),
+            &wasm.to_string_lossy(),
+            &pk.to_string_lossy(),
+            Some(&zkin.to_string_lossy()),
+            &public_input,
+            &proof,
+            false,
+            None,
+        )?;
+        groth16::api::groth16_verify(curve, &vk.to_string_lossy(), &public_input, &proof)
+    }
+}
+
+/// the initial and final memory roots in the bootloader inputs of a chunk
+fn memory_roots(chunk: &ChunkProof) -> Result<(&[GoldilocksField], &[GoldilocksField])> {
+    let start = MEMORY_HASH_START_INDEX;
+    let end = start + 2 * MEMORY_ROOT_WORDS;
+    match chunk.bootloader_inputs.get(start..end) {
+        Some(roots) => Ok(roots.split_at(MEMORY_ROOT_WORDS)),
+        None => Err(ZkvmError::ChunkError {
+            chunk: chunk.index,
+            msg: "no memory roots in the bootloader inputs".to_string(),
+        }),
+    }
+}
+
+/// Every chunk must start from the memory the previous one ended with. The check is advisory:
+/// it runs on the bootloader inputs saved with the proofs, which are not public values of them.
+pub(crate) fn check_continuity(chunks: &[ChunkProof]) -> Result<()> {
+    for (i, pair) in chunks.windows(2).enumerate() {
+        if pair[0].index != i || pair[1].index != i + 1 {
+            return Err(ZkvmError::AggregationError(format!(
+                "chunk {} is not followed by chunk {}",
+                i,
+                i + 1
+            )));
+        }
+        let (_, end) = memory_roots(&pair[0])?;
+        let (start, _) = memory_roots(&pair[1])?;
+        if end != start {
+            return Err(ZkvmError::ChunkError {
+                chunk: i + 1,
+                msg: format!(
+                    "initial memory root {:?} is not the final memory root {:?} of chunk {}",
+                    start, end, i
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(index: usize, initial: u64, last: u64) -> ChunkProof {
+        let mut bootloader_inputs = vec![GoldilocksField::from(0u64); MEMORY_HASH_START_INDEX];
+        bootloader_inputs.extend((0..MEMORY_ROOT_WORDS).map(|_| GoldilocksField::from(initial)));
+        bootloader_inputs.extend((0..MEMORY_ROOT_WORDS).map(|_| GoldilocksField::from(last)));
+        ChunkProof {
+            index,
+            dir: PathBuf::new(),
+            proof: PathBuf::new(),
+            bootloader_inputs,
+        }
+    }
+
+    #[test]
+    fn aggregation_layers() {
+        let layers = |num_chunks: usize, aggregator: &Aggregator| {
+            let pipeline = aggregator.pipeline(num_chunks);
+            assert!(pipeline.validate_layers(num_chunks).is_ok());
+            pipeline.layers.iter().map(|l| l.join).collect::<Vec<_>>()
+        };
+        let aggregator = Aggregator::new("/tmp/zkvm_agg");
+        assert_eq!(layers(1, &aggregator), vec![false]);
+        assert_eq!(layers(3, &aggregator), vec![false, true, true]);
+        let aggregator = aggregator.arity(4).snark("BN128", &["circomlib"]);
+        assert_eq!(layers(4, &aggregator), vec![false, true, false]);
+        assert_eq!(layers(5, &aggregator), vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn memory_root_continuity() {
+        assert!(check_continuity(&[]).is_ok());
+        assert!(check_continuity(&[chunk(0, 1, 2), chunk(1, 2, 3), chunk(2, 3, 3)]).is_ok());
+        assert!(matches!(
+            check_continuity(&[chunk(0, 1, 2), chunk(1, 3, 4)]),
+            Err(ZkvmError::ChunkError { chunk: 1, .. })
+        ));
+        // out of order
+        assert!(check_continuity(&[chunk(1, 2, 3), chunk(0, 1, 2)]).is_err());
+
+        let mut truncated = chunk(1, 2, 3);
+        truncated
+            .bootloader_inputs
+            .truncate(MEMORY_HASH_START_INDEX);
+        assert!(check_continuity(&[chunk(0, 1, 2), truncated]).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, ZkvmError>;
+
+#[derive(Error, Debug)]
+pub enum ZkvmError {
+    #[error("could not compile the guest `{0}`")]
+    CompileError(String),
+
+    #[error("invalid input `{0}`")]
+    InvalidInput(String),
+
+    #[error("pipeline error, `{0}`")]
+    PipelineError(String),
+
+    /// the guest panicked, like by a failed assertion, after writing `hints`
+    #[error("the guest panicked, `{msg}`")]
+    GuestPanic {
+        msg: String,
+        hints: BTreeMap<String, Value>,
+    },
+
+    #[error("chunk {chunk} failed, `{msg}`")]
+    ChunkError { chunk: usize, msg: String },
+
+    #[error("aggregation error, `{0}`")]
+    AggregationError(String),
+
+    #[error("io error")]
+    IoError(#[from] std::io::Error),
+}
+
+impl From<Vec<String>> for ZkvmError {
+    fn from(e: Vec<String>) -> Self {
+        ZkvmError::PipelineError(e.join("\n"))
+    }
+}
+
+impl From<anyhow::Error> for ZkvmError {
+    fn from(e: anyhow::Error) -> Self {
+        ZkvmError::AggregationError(format!("{:#}", e))
+    }
+}
@@ -0,0 +1,273 @@
+use crate::aggregation::check_continuity;
+use crate::errors::{Result, ZkvmError};
+use crate::prover::find_file;
+use crate::{ChunkProof, ZkvmProof};
+use powdr::number::GoldilocksField;
+use serde_json::Value;
+use starky::merklehash::MerkleTreeGL;
+use starky::pil2circom::{pil2circom, StarkOption};
+use starky::pilcom::compile_pil_from_path;
+use starky::polsarray::{PolKind, PolsArray};
+use starky::stark_gen::StarkProof;
+use starky::stark_setup::StarkSetup;
+use starky::stark_verify::stark_verify;
+use starky::transcript::TranscriptGL;
+use starky::types::{StarkStruct, Step, PIL};
+use std::path::{Path, PathBuf};
+
+const GOLDILOCKS_PRIME: u64 = 0xFFFFFFFF00000001;
+
+/// The stark struct of the eStark backend of powdr for `degree` rows: blowup factor 2, 2
+/// queries, and a FRI step every 4 bits. `to_starky` reads the struct of a chunk from its
+/// proof, and warns if it differs from this one.
+pub fn powdr_stark_struct(degree: usize) -> Result<StarkStruct> {
+    if degree <= 1 {
+        return Err(ZkvmError::InvalidInput(format!(
+            "the degree {} is not greater than 1",
+            degree
+        )));
+    }
+    let n_bits = (usize::BITS - (degree - 1).leading_zeros()) as usize;
+    let n_bits_ext = n_bits + 1;
+    Ok(StarkStruct {
+        nBits: n_bits,
+        nBitsExt: n_bits_ext,
+        nQueries: 2,
+        verificationHashType: "GL".to_string(),
+        steps: (2..=n_bits_ext)
+            .rev()
+            .step_by(4)
+            .map(|b| Step { nBits: b })
+            .collect(),
+    })
+}
+
+/// A chunk proof of the eStark backend of powdr, in the representation of starky, to be
+/// verified by `stark_verify` and by the circuit of `pil2circom`.
+///
+/// The PIL is the optimized PIL exported by powdr, compiled by the pilcom of starky, so the
+/// polynomials are in the same order as in the exported constants and commits.
+pub struct StarkyChunk {
+    pub index: usize,
+    pub pil: PIL,
+    pub constants: PolsArray,
+    pub proof: StarkProof<MerkleTreeGL>,
+    pub stark_struct: StarkStruct,
+    commits_file: PathBuf,
+}
+
+impl ChunkProof {
+    /// The public values of the eStark proof, in the `publics` of its zkin. The bootloader of
+    /// powdr declares none, so the memory roots of the chunk are not among them.
+    pub fn publics(&self) -> Result<Vec<GoldilocksField>> {
+        let invalid = |msg: String| ZkvmError::ChunkError {
+            chunk: self.index,
+            msg: format!("{}: {}", self.proof.display(), msg),
+        };
+        let zkin: Value = serde_json::from_slice(&std::fs::read(&self.proof)?)
+            .map_err(|e| invalid(e.to_string()))?;
+        let publics = match zkin.get("publics") {
+            Some(publics) => publics
+                .as_array()
+                .ok_or_else(|| invalid("the publics are not an array".to_string()))?,
+            None => return Ok(vec![]),
+        };
+        publics
+            .iter()
+            .map(|v| match v.as_str().and_then(|s| s.parse::<u64>().ok()) {
+                Some(e) if e < GOLDILOCKS_PRIME => Ok(GoldilocksField::from(e)),
+                _ => Err(invalid(format!("invalid public {}", v))),
+            })
+            .collect()
+    }
+
+    /// Load the PIL, the constants and the proof exported by powdr into the chunk directory,
+    /// `<name>_opt.pil`, `<name>_constants.bin` and `<name>_proof.bin`
+    pub fn to_starky(&self) -> Result<StarkyChunk> {
+        let find = |suffix: &str| {
+            find_file(&self.dir, suffix).ok_or_else(|| ZkvmError::ChunkError {
+                chunk: self.index,
+                msg: format!("no *{} in {}", suffix, self.dir.display()),
+            })
+        };
+        let pil_file = find("_opt.pil").or_else(|_| find(".pil"))?;
+        let pil = compile_pil_from_path(&pil_file.to_string_lossy())?;
+        let degree = pil
+            .references
+            .values()
+            .map(|r| r.polDeg)
+            .max()
+            .ok_or_else(|| ZkvmError::ChunkError {
+                chunk: self.index,
+                msg: format!("no polynomial in {}", pil_file.display()),
+            })?;
+        let expected = powdr_stark_struct(degree).map_err(|e| ZkvmError::ChunkError {
+            chunk: self.index,
+            msg: format!("{}: {}", pil_file.display(), e),
+        })?;
+
+        let constants = load_pols(&pil, PolKind::Constant, &find("_constants.bin")?)?;
+        let zkin: Value = serde_json::from_slice(&std::fs::read(&self.proof)?).map_err(|e| {
+            ZkvmError::ChunkError {
+                chunk: self.index,
+                msg: format!("{}: {}", self.proof.display(), e),
+            }
+        })?;
+        // the queries and the FRI steps are read from the proof, powdr doesn't export them
+        let stark_struct = StarkProof::<MerkleTreeGL>::stark_struct_of_zkin(&zkin, expected.nBits)?;
+        if serde_json::to_value(&stark_struct).ok() != serde_json::to_value(&expected).ok() {
+            log::warn!(
+                "chunk {} is proven with the stark struct {:?}, not {:?}",
+                self.index,
+                stark_struct,
+                expected
+            );
+        }
+        let proof = StarkProof::<MerkleTreeGL>::from_zkin(&zkin, &stark_struct)?;
+        Ok(StarkyChunk {
+            index: self.index,
+            pil,
+            constants,
+            proof,
+            stark_struct,
+            commits_file: find("_commits.bin")?,
+        })
+    }
+}
+
+impl StarkyChunk {
+    /// the commits are loaded on demand, they are the largest of the exports
+    pub fn commits(&self) -> Result<PolsArray> {
+        load_pols(&self.pil, PolKind::Commit, &self.commits_file)
+    }
+
+    fn setup(&mut self) -> Result<StarkSetup<MerkleTreeGL>> {
+        Ok(StarkSetup::new(
+            &self.constants,
+            &mut self.pil,
+            &self.stark_struct,
+            None,
+        )?)
+    }
+
+    /// verify the proof by `stark_verify`
+    pub fn verify(&mut self) -> Result<bool> {
+        let mut setup = self.setup()?;
+        Ok(stark_verify::<MerkleTreeGL, TranscriptGL>(
+            &self.proof,
+            &setup.const_root,
+            &setup.starkinfo,
+            &self.stark_struct,
+            &mut setup.program,
+        )?)
+    }
+
+    /// The circuit verifying the proof by `pil2circom`, and its input. The root of the
+    /// constants is an input of the circuit if `opt.verkey_input`, like `stark_prove` does.
+    pub fn verifier_circom(&mut self, opt: &StarkOption) -> Result<(String, Value)> {
+        let mut setup = self.setup()?;
+        let circom = pil2circom(
+            &self.pil,
+            &setup.const_root,
+            &self.stark_struct,
+            &mut setup.starkinfo,
+            &mut setup.program,
+            opt,
+        )?;
+        let root_c = self.proof.rootC.take();
+        if opt.verkey_input {
+            self.proof.rootC = Some(setup.const_root);
+        }
+        let zkin = serde_json::to_value(&self.proof);
+        self.proof.rootC = root_c;
+        let zkin = zkin.map_err(|e| ZkvmError::ChunkError {
+            chunk: self.index,
+            msg: e.to_string(),
+        })?;
+        Ok((circom, zkin))
+    }
+}
+
+impl ZkvmProof {
+    /// Verify the eStark chunk proofs by starky instead of powdr, each against the public values
+    /// in its proof. Like `GuestProgram::verify`, the continuity of the memory roots is only
+    /// checked on the saved bootloader inputs, which are not bound to the proofs.
+    pub fn verify_by_starky(&self) -> Result<()> {
+        check_continuity(&self.chunks)?;
+        for chunk in &self.chunks {
+            log::debug!("Verifying chunk {} by starky...", chunk.index);
+            if !chunk.to_starky()?.verify()? {
+                return Err(ZkvmError::ChunkError {
+                    chunk: chunk.index,
+                    msg: "invalid proof".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// load the polynomials of `kind` exported by powdr, row by row like starky
+fn load_pols(pil: &PIL, kind: PolKind, file: &Path) -> Result<PolsArray> {
+    let mut pols = PolsArray::new(pil, kind);
+    let expected = (pols.nPols * pols.n * 8) as u64;
+    let size = std::fs::metadata(file)?.len();
+    if size != expected {
+        return Err(ZkvmError::InvalidInput(format!(
+            "{} has {} bytes, expect {} of {} polynomials of degree {}",
+            file.display(),
+            size,
+            expected,
+            pols.nPols,
+            pols.n
+        )));
+    }
+    pols.load(&file.to_string_lossy())?;
+    Ok(pols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stark_struct_of_powdr() {
+        let stark_struct = powdr_stark_struct(1 << 18).unwrap();
+        assert_eq!(stark_struct.nBits, 18);
+        assert_eq!(stark_struct.nBitsExt, 19);
+        assert_eq!(
+            stark_struct
+                .steps
+                .iter()
+                .map(|s| s.nBits)
+                .collect::<Vec<_>>(),
+            vec![19, 15, 11, 7, 3]
+        );
+        assert_eq!(powdr_stark_struct(5).unwrap().nBits, 3);
+        assert!(powdr_stark_struct(1).is_err());
+        assert!(powdr_stark_struct(0).is_err());
+    }
+
+    /// The chunk proofs exported by the eStark backend of powdr, loaded by `from_zkin` and
+    /// verified by starky, so a change of the export of powdr breaks it.
+    #[test]
+    #[ignore]
+    fn verify_powdr_proof_by_starky() {
+        env_logger::try_init().unwrap_or_default();
+        let suite_json = std::fs::read_to_string("test-vectors/solidityExample.json").unwrap();
+        let addr = "a94f5374fce5edbc8e2a8697c15331677e6ebf0b";
+        let proof =
+            crate::zkvm_evm_prove_one(suite_json, addr, 1, "/tmp/test_powdr_proof").unwrap();
+        for chunk in &proof.chunks {
+            let starky_chunk = chunk.to_starky().unwrap();
+            let degree = starky_chunk.constants.n;
+            assert_eq!(1 << starky_chunk.stark_struct.nBits, degree);
+        }
+        proof.verify_by_starky().unwrap();
+
+        // a proof saved and loaded again is verified the same
+        proof.save("/tmp/test_powdr_proof/proof.json").unwrap();
+        let loaded = ZkvmProof::load("/tmp/test_powdr_proof/proof.json").unwrap();
+        loaded.verify_by_starky().unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use crate::errors::{Result, ZkvmError};
+use crate::prover::hint;
+use crate::{Coprocessors, CostModel, GuestProgram, ZkvmProof};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The guest crate of the EVM, relative to this crate
+const EVM_GUEST: &str = "vm/evm";
+
+/// The secret keys of the senders in the ethereum state tests, and their addresses
+const CALLER_KEYS: [(&str, &str); 6] = [
+    (
+        "45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+        "a94f5374fce5edbc8e2a8697c15331677e6ebf0b",
+    ),
+    (
+        "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4",
+        "cd2a3d9f938e13cd947ec05abc7fe734df8dd826",
+    ),
+    (
+        "044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d",
+        "82a978b3f5962a5b0957d9ee9eef472ee55b42f1",
+    ),
+    (
+        "6a7eeac5f12b409d42028f66b0b2132535ee158cfda439e3bfdd4558e8f4bf6c",
+        "c9c5a15a403e41498b6f69f6f89dd9f5892d21f7",
+    ),
+    (
+        "a95defe70ebea7804f9c3be42d20d24375e2a92b9d9666b832069c5f3cd423dd",
+        "3fb1cd2cd96c6d5c0b5eb3322d807b34482481d4",
+    ),
+    (
+        "fe13266ff57000135fb9aa854bbfe455d8da85b21f626307bf3263a0c2a8e7fe",
+        "dcc5ba93a1ed7e045690d722f2bf460a51c61415",
+    ),
+];
+
+/// The EVM guest without its inputs, it's the same program for all the test units
+fn evm_program(output_path: &Path) -> GuestProgram {
+    GuestProgram::new(EVM_GUEST, output_path).coprocessors(Coprocessors::default().with_poseidon())
+}
+
+/// The EVM guest reads the test unit from the channel `suite`, and writes the result of the
+/// test to the hint `result`. The test fails by the assertion of the guest, which is proven.
+fn evm_guest(unit: &Value, addr: &str, chain_id: u32, output_path: &Path) -> Result<GuestProgram> {
+    evm_program(output_path)
+        .channel("suite", unit)?
+        .channel("chain_id", &(chain_id as u64))?
+        .channel("caller", addr)
+}
+
+/// Prove the execution of the state test `suite_json` by the EVM guest, with `addr` as the
+/// caller
+pub fn zkvm_evm_prove_one(
+    suite_json: String,
+    addr: &str,
+    chain_id: u32,
+    output_path: &str,
+) -> Result<ZkvmProof> {
+    let unit: Value = serde_json::from_str(&suite_json)
+        .map_err(|e| ZkvmError::InvalidInput(format!("state test, {}", e)))?;
+    evm_guest(&unit, addr, chain_id, Path::new(output_path))?.prove()
+}
+
+/// The caller of the test unit, the address of its `transaction.secretKey`, or its
+/// `transaction.sender` if the key is unknown
+fn caller_of(unit: &Value) -> Result<String> {
+    let transaction = &unit["transaction"];
+    let strip = |v: &Value| {
+        v.as_str()
+            .map(|s| s.trim_start_matches("0x").to_lowercase())
+    };
+    let key = strip(&transaction["secretKey"]);
+    CALLER_KEYS
+        .iter()
+        .find(|(k, _)| Some(*k) == key.as_deref())
+        .map(|(_, addr)| addr.to_string())
+        .or_else(|| strip(&transaction["sender"]))
+        .ok_or_else(|| {
+            ZkvmError::InvalidInput(format!("unknown secret key {}", key.unwrap_or_default()))
+        })
+}
+
+/// The test units of a state test file, it's either a unit, or units by name
+fn test_units(file: &Path) -> Result<Vec<(String, Value)>> {
+    let json: Value = serde_json::from_slice(&std::fs::read(file)?)
+        .map_err(|e| ZkvmError::InvalidInput(format!("{}: {}", file.display(), e)))?;
+    let stem = file
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match json {
+        Value::Object(_) if json.get("transaction").is_some() => Ok(vec![(stem, json)]),
+        Value::Object(units) => Ok(units.into_iter().collect()),
+        _ => Err(ZkvmError::InvalidInput(format!(
+            "{} is not a state test",
+            file.display()
+        ))),
+    }
+}
+
+/// the `*.json` files in `dir` and its subdirectories, sorted
+fn test_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(test_files(&path)?);
+        } else if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The result of a test unit
+#[derive(Clone, Debug, Serialize)]
+pub struct EvmTestReport {
+    pub file: PathBuf,
+    pub name: String,
+    pub caller: Option<String>,
+    pub passed: bool,
+    pub error: Option<String>,
+    /// the cycles of the guest, including the bootloader
+    pub cycles: Option<usize>,
+    pub num_chunks: Option<usize>,
+    pub prove_time: Option<Duration>,
+    pub time: Duration,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EvmSuiteReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub tests: Vec<EvmTestReport>,
+}
+
+/// The reason of a failed test, the error of the `result` hint if the guest panicked on it
+fn failure_reason(e: &ZkvmError) -> String {
+    match e {
+        ZkvmError::GuestPanic { hints, .. } => {
+            match hint::<std::result::Result<(), String>>(hints, "result") {
+                Ok(Err(reason)) => reason,
+                _ => e.to_string(),
+            }
+        }
+        _ => e.to_string(),
+    }
+}
+
+/// Execute, or prove if `prove`, all the state tests in `dir` by the EVM guest, the outputs of
+/// a test are in `<output>/<file>/<name>`. The guest is compiled once, into `<output>`. A failed
+/// test is reported with the reason from the guest, and the others go on, a file which can't be
+/// read is reported as a failed test too.
+pub fn zkvm_evm_prove_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    chain_id: u32,
+    output_path: Q,
+    prove: bool,
+) -> Result<EvmSuiteReport> {
+    let mut report = EvmSuiteReport::default();
+    // compiled for the first test unit
+    let mut compiled = None;
+    for file in test_files(dir.as_ref())? {
+        let stem = file
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let units = match test_units(&file) {
+            Ok(units) => units,
+            Err(e) => {
+                // an unreadable file fails as a whole, named by its stem
+                report.tests.push(EvmTestReport {
+                    file: file.clone(),
+                    name: stem,
+                    caller: None,
+                    passed: false,
+                    error: Some(e.to_string()),
+                    cycles: None,
+                    num_chunks: None,
+                    prove_time: None,
+                    time: Duration::default(),
+                });
+                report.failed += 1;
+                continue;
+            }
+        };
+        if compiled.is_none() {
+            compiled = Some(evm_program(output_path.as_ref()).compile()?);
+        }
+        let asm = compiled.as_ref().unwrap();
+        for (name, unit) in units {
+            log::info!("Running {} of {}...", name, file.display());
+            let start = Instant::now();
+            let mut test = EvmTestReport {
+                file: file.clone(),
+                name: name.clone(),
+                caller: None,
+                passed: false,
+                error: None,
+                cycles: None,
+                num_chunks: None,
+                prove_time: None,
+                time: Duration::default(),
+            };
+            let result = caller_of(&unit).and_then(|caller| {
+                test.caller = Some(caller.clone());
+                let output = output_path.as_ref().join(&stem).join(&name);
+                let guest = evm_guest(&unit, &caller, chain_id, &output)?;
+                // the guest panics if the test fails, after writing the reason
+                if prove {
+                    guest.prove_compiled(asm).map(|proof| {
+                        test.cycles = Some(proof.stats.cycles);
+                        test.num_chunks = Some(proof.stats.num_chunks);
+                        test.prove_time = Some(proof.stats.prove_time);
+                    })
+                } else {
+                    guest
+                        .execute_compiled(asm, &CostModel::default())
+                        .map(|execution| {
+                            test.cycles = Some(execution.cycles);
+                            test.num_chunks = Some(execution.num_chunks);
+                        })
+                }
+            });
+            test.time = start.elapsed();
+            match result {
+                Ok(()) => {
+                    test.passed = true;
+                    report.passed += 1;
+                }
+                Err(e) => {
+                    test.error = Some(failure_reason(&e));
+                    report.failed += 1;
+                }
+            }
+            report.tests.push(test);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_caller_keys() {
+        let units = test_units(Path::new("test-vectors/solidityExample.json")).unwrap();
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].0, "solidityExample");
+        assert_eq!(
+            caller_of(&units[0].1).unwrap(),
+            "a94f5374fce5edbc8e2a8697c15331677e6ebf0b"
+        );
+
+        let unit = serde_json::json!({"transaction": {"secretKey": "0x01"}});
+        assert!(caller_of(&unit).is_err());
+        let unit = serde_json::json!({"transaction": {"secretKey": "0x01", "sender": "0xAB"}});
+        assert_eq!(caller_of(&unit).unwrap(), "ab");
+
+        let files = test_files(Path::new("test-vectors")).unwrap();
+        assert!(files.iter().any(|f| f.ends_with("blockInfo.json")));
+    }
+
+    #[test]
+    fn guest_failure_reason() {
+        let hints = [(
+            "result".to_string(),
+            serde_json::json!({"Err": "state root mismatch"}),
+        )];
+        let e = ZkvmError::GuestPanic {
+            msg: "assertion failed: result.is_ok()".to_string(),
+            hints: hints.into_iter().collect(),
+        };
+        assert_eq!(failure_reason(&e), "state root mismatch");
+
+        let e = ZkvmError::GuestPanic {
+            msg: "out of gas".to_string(),
+            hints: Default::default(),
+        };
+        assert_eq!(failure_reason(&e), e.to_string());
+    }
+
+    #[test]
+    fn report_unreadable_file() {
+        let dir = Path::new("/tmp/zkvm_evm_unreadable");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        let report = zkvm_evm_prove_dir(dir, 1, "/tmp/zkvm_evm_unreadable_output", false).unwrap();
+        assert_eq!((report.passed, report.failed), (0, 1));
+        assert_eq!(report.tests[0].name, "broken");
+        assert!(!report.tests[0].passed);
+        assert!(report.tests[0].error.is_some());
+    }
+}
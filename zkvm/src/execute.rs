@@ -0,0 +1,159 @@
+use crate::errors::Result;
+use crate::prover::{hint, run_guest, split_stdout};
+use crate::{data_to_query_callback, GuestProgram, ProveStats};
+use powdr::number::GoldilocksField;
+use powdr::pipeline::Pipeline;
+use powdr::riscv::continuations::{bootloader::default_input, rust_continuations_dry_run};
+use powdr::riscv_executor;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The cost of proving a continuation chunk. The chunks have the same degree, so the cost is
+/// the same whatever the rows used by the guest, and they are proven one by one.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct CostModel {
+    pub time_per_chunk: Duration,
+    /// the peak memory of proving a chunk in bytes
+    pub memory_per_chunk: u64,
+}
+
+impl Default for CostModel {
+    /// A rough estimate of eStark on a chunk of 2^18 rows: a minute and 16 GiB, most of the
+    /// memory is the extended trace and its merkle trees. Calibrate the time by `from_stats`,
+    /// and the memory by the peak measured on the machine.
+    fn default() -> Self {
+        Self {
+            time_per_chunk: Duration::from_secs(60),
+            memory_per_chunk: 16 << 30,
+        }
+    }
+}
+
+impl CostModel {
+    /// The time per chunk measured by a proven run
+    pub fn from_stats(stats: &ProveStats) -> Self {
+        let mut model = Self::default();
+        if stats.num_chunks > 0 {
+            model.time_per_chunk = stats.prove_time / stats.num_chunks as u32;
+        }
+        model
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProvingEstimate {
+    pub time: Duration,
+    /// the peak memory in bytes, the chunks are proven one by one
+    pub memory: u64,
+}
+
+/// The execution of a guest without proving
+#[derive(Clone, Debug, Serialize)]
+pub struct ExecutionReport {
+    /// what the guest printed, without its hints
+    pub stdout: String,
+    /// the hints written by the guest, the last one of a name wins
+    pub hints: BTreeMap<String, Value>,
+    /// the cycles of the guest, including the bootloader
+    pub cycles: usize,
+    pub num_chunks: usize,
+    /// the rows used in each chunk, before its shutdown routine
+    pub chunk_rows: Vec<u64>,
+    pub execute_time: Duration,
+    pub estimate: ProvingEstimate,
+}
+
+impl GuestProgram {
+    /// Compile and execute the guest, to find the continuation chunks it would be proven by,
+    /// and estimate the proving cost by `cost_model`.
+    pub fn execute(&self, cost_model: &CostModel) -> Result<ExecutionReport> {
+        self.execute_compiled(&self.compile()?, cost_model)
+    }
+
+    /// Execute the guest compiled by `compile` into `asm`, to compile it once for many inputs
+    pub(crate) fn execute_compiled(
+        &self,
+        asm: &(PathBuf, String),
+        cost_model: &CostModel,
+    ) -> Result<ExecutionReport> {
+        let (asm_file_path, asm_contents) = asm;
+        let data = self.data();
+
+        log::debug!("Running powdr-riscv executor in trace mode for continuations...");
+        let start = Instant::now();
+        // the output of the guest is collected by its queries, like `prove` does
+        let stdout = Arc::new(Mutex::new(vec![]));
+        let bootloader_inputs = run_guest(&stdout, || {
+            let pipeline = Pipeline::<GoldilocksField>::default()
+                .from_asm_string(asm_contents.clone(), Some(asm_file_path.clone()))
+                .add_query_callback(Box::new(data_to_query_callback(
+                    data.clone(),
+                    stdout.clone(),
+                )));
+            rust_continuations_dry_run(pipeline, data.clone())
+        })?;
+
+        log::debug!("Running powdr-riscv executor in fast mode...");
+        let (trace, _mem) = run_guest(&stdout, || {
+            riscv_executor::execute::<GoldilocksField>(
+                asm_contents,
+                &data,
+                &default_input(),
+                riscv_executor::ExecMode::Fast,
+            )
+        })?;
+        log::debug!("Trace length: {}", trace.len);
+        let execute_time = start.elapsed();
+        let (stdout, hints) = split_stdout(&stdout.lock().unwrap())?;
+
+        let num_chunks = bootloader_inputs.len();
+        Ok(ExecutionReport {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            hints,
+            cycles: trace.len,
+            num_chunks,
+            chunk_rows: bootloader_inputs.iter().map(|(_, rows)| *rows).collect(),
+            execute_time,
+            estimate: ProvingEstimate {
+                time: cost_model.time_per_chunk * num_chunks as u32,
+                memory: if num_chunks > 0 {
+                    cost_model.memory_per_chunk
+                } else {
+                    0
+                },
+            },
+        })
+    }
+}
+
+impl ExecutionReport {
+    /// the hint `name` of the guest
+    pub fn hint<T: DeserializeOwned>(&self, name: &str) -> Result<T> {
+        hint(&self.hints, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_model_from_stats() {
+        let stats = ProveStats {
+            num_chunks: 4,
+            prove_time: Duration::from_secs(100),
+            ..Default::default()
+        };
+        let model = CostModel::from_stats(&stats);
+        assert_eq!(model.time_per_chunk, Duration::from_secs(25));
+        assert_eq!(
+            CostModel::from_stats(&ProveStats::default()).time_per_chunk,
+            CostModel::default().time_per_chunk
+        );
+    }
+}
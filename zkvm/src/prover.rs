@@ -0,0 +1,549 @@
+use crate::aggregation::check_continuity;
+use crate::data_to_query_callback;
+use crate::errors::{Result, ZkvmError};
+use backend::BackendType;
+use powdr::number::{FieldElement, GoldilocksField};
+use powdr::pipeline::{Pipeline, Stage};
+use powdr::riscv::continuations::{
+    bootloader::default_input, rust_continuations, rust_continuations_dry_run,
+};
+use powdr::riscv::{compile_rust, CoProcessors};
+use powdr::riscv_executor;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap as STDHashMap};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The data of an input channel, read by the guest with `get_data_len` and `get_data`. It's
+/// prefixed by its length, and each byte or u32 is widened to a field element.
+#[derive(Clone, Debug)]
+pub enum ChannelData {
+    Bytes(Vec<u8>),
+    U32(Vec<u32>),
+    Field(Vec<GoldilocksField>),
+}
+
+impl ChannelData {
+    /// a typed value, read by the guest with `zkvm_channel::guest::read`
+    pub fn typed<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        let words =
+            zkvm_channel::encode(value).map_err(|e| ZkvmError::InvalidInput(e.to_string()))?;
+        Ok(ChannelData::U32(words))
+    }
+
+    /// Parse an input channel argument `<channel>:<kind>:<value>`. The channel is a number, or
+    /// the name of a typed channel. The kinds are `str` for the bytes of a string, `file` for
+    /// the bytes of a file, `u32` or `field` for comma separated numbers, and `json` or
+    /// `jsonfile` for a typed value, like `suite:jsonfile:suite.json` or `chain_id:json:1`.
+    pub fn parse_arg(arg: &str) -> Result<(u32, ChannelData)> {
+        let invalid = |msg: &str| ZkvmError::InvalidInput(format!("{}: {}", arg, msg));
+        let parts = arg.splitn(3, ':').collect::<Vec<_>>();
+        let (channel, kind, value) = match parts[..] {
+            [channel, kind, value] => (channel, kind, value),
+            _ => return Err(invalid("expect <channel>:<kind>:<value>")),
+        };
+        let channel = match channel.parse::<u32>() {
+            Ok(channel) => channel,
+            Err(_) if !channel.is_empty() => zkvm_channel::channel_id(channel),
+            Err(_) => return Err(invalid("invalid channel")),
+        };
+        let json = |bytes: &[u8]| {
+            serde_json::from_slice::<Value>(bytes)
+                .map_err(|e| invalid(&e.to_string()))
+                .and_then(|value| ChannelData::typed(&value))
+        };
+        let numbers = || {
+            value
+                .split(',')
+                .map(|v| v.trim().parse::<u64>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| invalid("invalid number"))
+        };
+        let data = match kind {
+            "str" => ChannelData::Bytes(value.as_bytes().to_vec()),
+            "file" => ChannelData::Bytes(std::fs::read(value)?),
+            "u32" => ChannelData::U32(
+                numbers()?
+                    .into_iter()
+                    .map(u32::try_from)
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| invalid("invalid u32"))?,
+            ),
+            "field" => ChannelData::Field(numbers()?.into_iter().map(|v| v.into()).collect()),
+            "json" => json(value.as_bytes())?,
+            "jsonfile" => json(&std::fs::read(value)?)?,
+            _ => return Err(invalid("unknown kind")),
+        };
+        Ok((channel, data))
+    }
+
+    fn to_field_elements(&self) -> Vec<GoldilocksField> {
+        let elements: Vec<GoldilocksField> = match self {
+            ChannelData::Bytes(bytes) => bytes.iter().map(|b| (*b as u32).into()).collect(),
+            ChannelData::U32(values) => values.iter().map(|v| (*v).into()).collect(),
+            ChannelData::Field(elements) => elements.clone(),
+        };
+        std::iter::once((elements.len() as u32).into())
+            .chain(elements)
+            .collect()
+    }
+}
+
+/// The coprocessors linked to the guest, the base ones are always linked
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Coprocessors {
+    pub poseidon: bool,
+}
+
+impl Coprocessors {
+    pub fn with_poseidon(mut self) -> Self {
+        self.poseidon = true;
+        self
+    }
+
+    fn to_powdr(self) -> CoProcessors {
+        let coprocessors = CoProcessors::base();
+        if self.poseidon {
+            coprocessors.with_poseidon()
+        } else {
+            coprocessors
+        }
+    }
+}
+
+/// The proof of a continuation chunk
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChunkProof {
+    pub index: usize,
+    /// the output directory of the chunk, with the artifacts of the backend
+    pub dir: PathBuf,
+    /// the proof file in `dir`
+    pub proof: PathBuf,
+    /// the bootloader inputs of the chunk, with its initial and final memory roots
+    #[serde(
+        serialize_with = "serialize_fields",
+        deserialize_with = "deserialize_fields"
+    )]
+    pub bootloader_inputs: Vec<GoldilocksField>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProveStats {
+    /// the cycles of the guest, including the bootloader
+    pub cycles: usize,
+    pub num_chunks: usize,
+    pub compile_time: Duration,
+    pub execute_time: Duration,
+    pub prove_time: Duration,
+}
+
+/// The proofs of a guest execution, one per continuation chunk
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ZkvmProof {
+    pub chunks: Vec<ChunkProof>,
+    /// what the guest printed, without its hints
+    pub stdout: Vec<u8>,
+    /// The hints written by the guest with `zkvm_channel::guest::hint`, the last one of a name
+    /// wins. They come through the print channel, so they are not proven, the proven values are
+    /// the `publics` of the chunks.
+    #[serde(default)]
+    pub hints: BTreeMap<String, Value>,
+    pub stats: ProveStats,
+}
+
+impl ZkvmProof {
+    /// the hint `name` of the guest
+    pub fn hint<T: DeserializeOwned>(&self, name: &str) -> Result<T> {
+        hint(&self.hints, name)
+    }
+
+    /// the public values of the chunk proofs, by chunk
+    pub fn publics(&self) -> Result<Vec<Vec<GoldilocksField>>> {
+        self.chunks.iter().map(|chunk| chunk.publics()).collect()
+    }
+
+    /// write the proof as json, the chunk proofs stay in their directories
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| ZkvmError::InvalidInput(e.to_string()))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| ZkvmError::InvalidInput(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// split the hints of the guest from what it printed
+pub(crate) fn split_stdout(stdout: &[u8]) -> Result<(Vec<u8>, BTreeMap<String, Value>)> {
+    let (text, hints) = zkvm_channel::split_hints(stdout);
+    let hints = hints
+        .into_iter()
+        .map(|(name, bytes)| {
+            let value = serde_json::from_slice(&bytes)
+                .map_err(|e| ZkvmError::InvalidInput(format!("guest hint {}, {}", name, e)))?;
+            Ok((name, value))
+        })
+        .collect::<Result<_>>()?;
+    Ok((text, hints))
+}
+
+/// Run `f`, which executes the guest. A panic of the guest, like a failed assertion, is returned
+/// as `ZkvmError::GuestPanic`, with the hints it wrote into `stdout` before.
+pub(crate) fn run_guest<T>(stdout: &Mutex<Vec<u8>>, f: impl FnOnce() -> T) -> Result<T> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let msg = match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(msg) => msg.to_string(),
+                Err(_) => "the guest panicked".to_string(),
+            },
+        };
+        let stdout = stdout.lock().unwrap_or_else(|e| e.into_inner());
+        let hints = split_stdout(&stdout)
+            .map(|(_, hints)| hints)
+            .unwrap_or_default();
+        ZkvmError::GuestPanic { msg, hints }
+    })
+}
+
+pub(crate) fn hint<T: DeserializeOwned>(hints: &BTreeMap<String, Value>, name: &str) -> Result<T> {
+    let value = hints
+        .get(name)
+        .ok_or_else(|| ZkvmError::InvalidInput(format!("no hint {}", name)))?;
+    T::deserialize(value).map_err(|e| ZkvmError::InvalidInput(format!("hint {}, {}", name, e)))
+}
+
+fn serialize_fields<S: Serializer>(
+    elements: &[GoldilocksField],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(elements.iter().map(|e| e.to_degree()))
+}
+
+fn deserialize_fields<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<GoldilocksField>, D::Error> {
+    let values = Vec::<u64>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(GoldilocksField::from).collect())
+}
+
+/// A RISC-V guest crate to prove, built like
+/// `GuestProgram::new("vm/evm", "/tmp/evm").channel("chain_id", &1u64)?`.
+///
+/// The guest is compiled with the bootloader, and proven by continuations, one proof per chunk
+/// of the execution.
+#[derive(Clone)]
+pub struct GuestProgram {
+    pub(crate) guest_path: PathBuf,
+    pub(crate) output_path: PathBuf,
+    pub(crate) inputs: Vec<(u32, ChannelData)>,
+    pub(crate) coprocessors: Coprocessors,
+    pub(crate) backend: BackendType,
+}
+
+impl GuestProgram {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(guest_path: P, output_path: Q) -> Self {
+        Self {
+            guest_path: guest_path.as_ref().to_path_buf(),
+            output_path: output_path.as_ref().to_path_buf(),
+            inputs: vec![],
+            coprocessors: Coprocessors::default(),
+            backend: BackendType::EStark,
+        }
+    }
+
+    /// add the input channel `channel`, channel 0 is the prover input of the guest
+    pub fn input(mut self, channel: u32, data: ChannelData) -> Self {
+        self.inputs.push((channel, data));
+        self
+    }
+
+    /// add the typed input channel `name`, read by the guest with `zkvm_channel::guest::read`
+    pub fn channel<T: Serialize + ?Sized>(self, name: &str, value: &T) -> Result<Self> {
+        Ok(self.input(zkvm_channel::channel_id(name), ChannelData::typed(value)?))
+    }
+
+    pub fn coprocessors(mut self, coprocessors: Coprocessors) -> Self {
+        self.coprocessors = coprocessors;
+        self
+    }
+
+    pub fn backend(mut self, backend: BackendType) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub(crate) fn data(&self) -> STDHashMap<GoldilocksField, Vec<GoldilocksField>> {
+        self.inputs
+            .iter()
+            .map(|(channel, data)| ((*channel).into(), data.to_field_elements()))
+            .collect()
+    }
+
+    /// compile the guest into powdr-asm in the output directory, returns its path and contents
+    pub(crate) fn compile(&self) -> Result<(PathBuf, String)> {
+        log::debug!("Compiling Rust...");
+        let force_overwrite = true;
+        let with_bootloader = true;
+        compile_rust(
+            &self.guest_path.to_string_lossy(),
+            &self.output_path,
+            force_overwrite,
+            &self.coprocessors.to_powdr(),
+            with_bootloader,
+        )
+        .ok_or_else(|| ZkvmError::CompileError(self.guest_path.display().to_string()))
+    }
+
+    /// Compile and prove the guest, the proof of chunk `i` is in `<output>/chunk_<i>`
+    pub fn prove(&self) -> Result<ZkvmProof> {
+        let start = Instant::now();
+        let asm = self.compile()?;
+        let compile_time = start.elapsed();
+        let mut proof = self.prove_compiled(&asm)?;
+        proof.stats.compile_time = compile_time;
+        Ok(proof)
+    }
+
+    /// Prove the guest compiled by `compile` into `asm`, to compile it once for many inputs
+    pub(crate) fn prove_compiled(&self, asm: &(PathBuf, String)) -> Result<ZkvmProof> {
+        let mut stats = ProveStats::default();
+        let (asm_file_path, asm_contents) = asm;
+
+        let mk_pipeline = || {
+            Pipeline::<GoldilocksField>::default()
+                .from_asm_string(asm_contents.clone(), Some(asm_file_path.clone()))
+        };
+
+        log::debug!("Creating pipeline from powdr-asm...");
+        let start = Instant::now();
+        let pipeline = mk_pipeline();
+        let duration = start.elapsed();
+        log::debug!("Pipeline from powdr-asm took: {:?}", duration);
+
+        log::debug!("Advancing pipeline to fixed columns...");
+        let start = Instant::now();
+        let pil_with_evaluated_fixed_cols = pipeline.pil_with_evaluated_fixed_cols()?;
+        let duration = start.elapsed();
+        log::debug!("Advancing pipeline took: {:?}", duration);
+
+        let mk_pipeline_opt = || {
+            mk_pipeline().from_pil_with_evaluated_fixed_cols(pil_with_evaluated_fixed_cols.clone())
+        };
+
+        log::debug!("Creating data callback...");
+        let data = self.data();
+
+        log::debug!("Running powdr-riscv executor in trace mode for continuations...");
+        let execute_start = Instant::now();
+        let start = Instant::now();
+        // the output of the dry run is only kept for the hints of a panicking guest
+        let dry_run_stdout = Arc::new(Mutex::new(vec![]));
+        let bootloader_inputs = run_guest(&dry_run_stdout, || {
+            let pipeline = mk_pipeline().add_query_callback(Box::new(data_to_query_callback(
+                data.clone(),
+                dry_run_stdout.clone(),
+            )));
+            rust_continuations_dry_run(pipeline, data.clone())
+        })?;
+        let duration = start.elapsed();
+        log::debug!("Trace executor took: {:?}", duration);
+
+        log::debug!("Running powdr-riscv executor in fast mode...");
+        let start = Instant::now();
+        let (trace, _mem) = run_guest(&dry_run_stdout, || {
+            riscv_executor::execute::<GoldilocksField>(
+                asm_contents,
+                &data,
+                &default_input(),
+                riscv_executor::ExecMode::Fast,
+            )
+        })?;
+        let duration = start.elapsed();
+        log::debug!("Fast executor took: {:?}", duration);
+        log::debug!("Trace length: {}", trace.len);
+        stats.cycles = trace.len;
+        stats.num_chunks = bootloader_inputs.len();
+        let chunk_inputs = bootloader_inputs
+            .iter()
+            .map(|(inputs, _)| inputs.clone())
+            .collect::<Vec<_>>();
+        stats.execute_time = execute_start.elapsed();
+
+        let backend = self.backend;
+        let stdout = Arc::new(Mutex::new(vec![]));
+        let chunks = RefCell::new(vec![]);
+        let generate_witness = |pipeline: Pipeline<GoldilocksField>| -> Result<()> {
+            let index = chunks.borrow().len();
+            let chunk_error = |e: Vec<String>| ZkvmError::ChunkError {
+                chunk: index,
+                msg: e.join("\n"),
+            };
+            let dir = self.output_path.join(format!("chunk_{}", index));
+            std::fs::create_dir_all(&dir)?;
+
+            let data = data_to_query_callback(data.clone(), stdout.clone());
+            let mut pipeline = pipeline
+                .with_output(dir.clone(), true)
+                .add_query_callback(Box::new(data));
+            pipeline
+                .advance_to(Stage::GeneratedWitness)
+                .map_err(chunk_error)?;
+            pipeline
+                .with_backend(backend)
+                .proof()
+                .map_err(chunk_error)?;
+            let proof = find_file(&dir, "_proof.bin").ok_or_else(|| ZkvmError::ChunkError {
+                chunk: index,
+                msg: format!("no proof in {}", dir.display()),
+            })?;
+            chunks.borrow_mut().push(ChunkProof {
+                index,
+                dir,
+                proof,
+                bootloader_inputs: chunk_inputs[index].clone(),
+            });
+            Ok(())
+        };
+
+        log::debug!("Running witness generation...");
+        let start = Instant::now();
+        run_guest(&stdout, || {
+            rust_continuations(mk_pipeline_opt, generate_witness, bootloader_inputs)
+        })??;
+        let duration = start.elapsed();
+        log::debug!("Witness generation took: {:?}", duration);
+        stats.prove_time = duration;
+
+        let (stdout, hints) = split_stdout(&stdout.lock().unwrap())?;
+        Ok(ZkvmProof {
+            chunks: chunks.into_inner(),
+            stdout,
+            hints,
+            stats,
+        })
+    }
+}
+
+impl GuestProgram {
+    /// Verify each chunk proof of the guest by the backend, against the public values of the
+    /// proof. It proves every chunk is a valid execution of the bootloader, not that they are
+    /// one run of the guest: the memory roots are not public values of the chunks, so their
+    /// order and continuity are only checked on the saved bootloader inputs, which are not bound
+    /// to the proofs.
+    pub fn verify(&self, proof: &ZkvmProof) -> Result<()> {
+        if proof.chunks.is_empty() {
+            return Err(ZkvmError::InvalidInput("no chunk proof".to_string()));
+        }
+        check_continuity(&proof.chunks)?;
+        let (asm_file_path, asm_contents) = self.compile()?;
+        let mk_pipeline = || {
+            Pipeline::<GoldilocksField>::default()
+                .from_asm_string(asm_contents.clone(), Some(asm_file_path.clone()))
+        };
+        // the chunks share the PIL and the fixed columns of the bootloader
+        let pil_with_evaluated_fixed_cols = mk_pipeline().pil_with_evaluated_fixed_cols()?;
+        for chunk in &proof.chunks {
+            log::debug!("Verifying chunk {}...", chunk.index);
+            let chunk_proof = std::fs::read(&chunk.proof)?;
+            mk_pipeline()
+                .from_pil_with_evaluated_fixed_cols(pil_with_evaluated_fixed_cols.clone())
+                .with_backend(self.backend)
+                .verify(&chunk_proof, &[chunk.publics()?])
+                .map_err(|e| ZkvmError::ChunkError {
+                    chunk: chunk.index,
+                    msg: e.join("\n"),
+                })?;
+        }
+        Ok(())
+    }
+}
+
+/// the file of `dir` whose name ends with `suffix`, like the `_proof.bin` of the backend
+pub(crate) fn find_file(dir: &Path, suffix: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| {
+            matches!(
+                path.file_name().and_then(|f| f.to_str()),
+                Some(f) if f.ends_with(suffix)
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_data_is_length_prefixed() {
+        let elements = |data: ChannelData| -> Vec<GoldilocksField> { data.to_field_elements() };
+        let expected = |values: &[u32]| -> Vec<GoldilocksField> {
+            values.iter().map(|v| (*v).into()).collect()
+        };
+        assert_eq!(
+            elements(ChannelData::Bytes(b"ab".to_vec())),
+            expected(&[2, 97, 98])
+        );
+        assert_eq!(elements(ChannelData::U32(vec![1])), expected(&[1, 1]));
+        assert_eq!(elements(ChannelData::Field(vec![])), expected(&[0]));
+    }
+
+    #[test]
+    fn save_and_load_proof() {
+        let proof = ZkvmProof {
+            chunks: vec![ChunkProof {
+                index: 0,
+                dir: PathBuf::from("/tmp/zkvm_proof/chunk_0"),
+                proof: PathBuf::from("/tmp/zkvm_proof/chunk_0/guest_proof.bin"),
+                // the largest element is p - 1
+                bootloader_inputs: vec![1u64.into(), 0xffff_ffff_0000_0000u64.into()],
+            }],
+            stdout: b"ok".to_vec(),
+            hints: BTreeMap::from([("gas".to_string(), 21000.into())]),
+            stats: ProveStats::default(),
+        };
+        let path = "/tmp/zkvm_proof.json";
+        proof.save(path).unwrap();
+        let loaded = ZkvmProof::load(path).unwrap();
+        assert_eq!(
+            loaded.chunks[0].bootloader_inputs,
+            proof.chunks[0].bootloader_inputs
+        );
+        assert_eq!(loaded.chunks[0].proof, proof.chunks[0].proof);
+        assert_eq!(loaded.stdout, proof.stdout);
+        assert_eq!(loaded.hint::<u64>("gas").unwrap(), 21000);
+        assert!(loaded.hint::<String>("gas").is_err());
+        assert!(loaded.hint::<u64>("caller").is_err());
+        assert!(ZkvmProof::load("/tmp/no_such_zkvm_proof.json").is_err());
+    }
+
+    #[test]
+    fn parse_channel_args() {
+        let (channel, data) = ChannelData::parse_arg("668:str:a9:4f").unwrap();
+        assert_eq!(channel, 668);
+        assert!(matches!(data, ChannelData::Bytes(b) if b == b"a9:4f"));
+        assert!(matches!(
+            ChannelData::parse_arg("667:u32:1, 2").unwrap().1,
+            ChannelData::U32(v) if v == [1, 2]
+        ));
+        assert!(ChannelData::parse_arg("667:u32:4294967296").is_err());
+        assert!(ChannelData::parse_arg("667:bytes:1").is_err());
+        assert!(ChannelData::parse_arg(":str:1").is_err());
+        let (channel, data) = ChannelData::parse_arg("chain_id:json:1").unwrap();
+        assert_eq!(channel, zkvm_channel::channel_id("chain_id"));
+        assert!(matches!(data, ChannelData::U32(v) if v == zkvm_channel::encode(&1).unwrap()));
+        assert!(ChannelData::parse_arg("chain_id:json:{").is_err());
+        assert!(ChannelData::parse_arg("/tmp/no_such_file").is_err());
+    }
+}
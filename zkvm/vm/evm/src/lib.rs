@@ -7,52 +7,30 @@ use revm::{
         calc_excess_blob_gas, keccak256, Env, SpecId, AccountInfo, Bytecode, TransactTo, U256,
     },
 };
-//use runtime::{print, get_prover_input, coprocessors::{get_data, get_data_len}};
-use powdr_riscv_rt::{print, coprocessors::{get_data, get_data_len}};
+use powdr_riscv_rt::print;
+use zkvm_channel::guest::{hint, read};
 
 use models::*;
 
 extern crate alloc;
 use alloc::vec::Vec;
-use alloc::vec;
 use alloc::string::String;
 use alloc::string::ToString;
 
 #[no_mangle]
 fn main() {
-    let suite_len = get_data_len(666);
-    let mut suite_json = vec![0; suite_len];
-    get_data(666, &mut suite_json);
-    let suite_json: Vec<u8> = suite_json.into_iter().map(|x| x as u8).collect();
-    let suite_json_str = String::from_utf8(suite_json).unwrap();
-    let suite = read_suite(&suite_json_str);
-
-    let chain_id_len = get_data_len(667);
-    let mut chain_id_in = vec![0; chain_id_len];
-    get_data(667, &mut chain_id_in);
-    let chain_id: u64 = chain_id_in[0].into();
+    let suite: TestUnit = read("suite");
+
+    let chain_id: u64 = read("chain_id");
     print!("chain_id: {chain_id}\n");
 
-    let addr_len = get_data_len(668);
-    let mut addr_in = vec![0; addr_len];
-    print!("addr: {addr_len}\n");
-    get_data(668, &mut addr_in);
-    let addr_in: Vec<u8> = addr_in.into_iter().map(|x| x as u8).collect();
-    let addr_in = String::from_utf8(addr_in).unwrap();
+    let addr_in: String = read("caller");
     print!("addr: {:?}\n", addr_in);
     let addr: Address = addr_in.parse().unwrap();
 
-    /*
-    let chain_id = 1;
-    let addr = address!("a94f5374fce5edbc8e2a8697c15331677e6ebf0b");
-    */
-
-    assert!(execute_test(&suite, addr, chain_id).is_ok());
-}
-
-fn read_suite(s: &String) -> TestUnit {
-    let suite: TestUnit = serde_json::from_str(s).map_err(|e| e).unwrap();
-    suite
+    let result = execute_test(&suite, addr, chain_id);
+    hint("result", &result);
+    assert!(result.is_ok());
 }
 
 fn execute_test(unit: &TestUnit, addr: Address, chain_id: u64) -> Result<(), String> {